@@ -0,0 +1,45 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use programs::{compose_chain, nested_lets};
+use simpl::{
+    hir::Expr,
+    types::{constraints_of, type_of, unify},
+};
+use std::str::FromStr;
+
+mod programs;
+
+const DEPTHS: &[usize] = &[10, 100, 1000];
+
+fn bench_type_of(c: &mut Criterion, name: &str, gen: fn(usize) -> String) {
+    let mut group = c.benchmark_group(name);
+    for &depth in DEPTHS {
+        let expr = Expr::from_str(&gen(depth)).unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &expr, |b, expr| {
+            b.iter(|| type_of(expr).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// The constraints of a program, repeated `copies` times. Repeats are skipped
+/// by `unify`, so its time should barely grow with `copies`
+fn bench_unify_repeated(c: &mut Criterion) {
+    let cons = constraints_of(&Expr::from_str(&nested_lets(100)).unwrap()).unwrap();
+    let mut group = c.benchmark_group("unify_repeated");
+    for &copies in &[1, 10, 100] {
+        let cons = cons.repeat(copies);
+        group.bench_with_input(BenchmarkId::from_parameter(copies), &cons, |b, cons| {
+            b.iter(|| unify(cons).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn infer(c: &mut Criterion) {
+    bench_type_of(c, "nested_lets", nested_lets);
+    bench_type_of(c, "compose_chain", compose_chain);
+    bench_unify_repeated(c);
+}
+
+criterion_group!(benches, infer);
+criterion_main!(benches);
@@ -0,0 +1,23 @@
+//! Generators for large programs, shared by the benchmarks and the tests
+
+/// `let x0 = 0 in let x1 = x0 + 1 in ... in xN`
+pub fn nested_lets(depth: usize) -> String {
+    let mut src = String::from("let x0 = 0 in ");
+    for i in 1..=depth {
+        src.push_str(&format!("let x{} = x{} + 1 in ", i, i - 1));
+    }
+    src.push_str(&format!("x{}", depth));
+    src
+}
+
+/// `let f = \x -> x + 1 in compose f (compose f (... f))`, which instantiates
+/// the polymorphic `compose` `depth` times
+pub fn compose_chain(depth: usize) -> String {
+    let mut src = String::from(r"let f = \x -> x + 1 in ");
+    for _ in 0..depth {
+        src.push_str("compose f (");
+    }
+    src.push('f');
+    src.push_str(&")".repeat(depth));
+    src
+}
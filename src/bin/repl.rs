@@ -0,0 +1,43 @@
+use simpl::{
+    eval::eval,
+    types::{parse_and_type, ty::Polytype},
+    with_large_stack,
+};
+use std::{
+    error::Error,
+    io::{self, BufRead, Write},
+};
+
+/// Parse, type and evaluate one line of input, giving its value and type. The
+/// type is generalized, to show which parts of it are polymorphic
+fn eval_line(line: &str) -> Result<String, Box<dyn Error>> {
+    let expr = parse_and_type(line)?;
+    let val = eval(&expr)?;
+    Ok(format!("{} : {}", val, Polytype::generalize(expr.ty())))
+}
+
+fn main() -> io::Result<()> {
+    // deeply nested input would overflow the main thread's stack
+    with_large_stack(repl)
+}
+
+fn repl() -> io::Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match eval_line(&line) {
+            Ok(output) => writeln!(stdout, "{}", output)?,
+            Err(err) => writeln!(stdout, "Error: {}", err)?,
+        }
+        stdout.flush()?;
+    }
+
+    Ok(())
+}
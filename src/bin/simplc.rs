@@ -0,0 +1,133 @@
+use inkwell::{
+    context::Context,
+    targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine},
+    OptimizationLevel,
+};
+use simpl::{
+    codegen::compile_program,
+    types::{default_type_vars, parse_and_type},
+    with_large_stack,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+const USAGE: &str =
+    "usage: simplc <input.simpl> [--emit=llvm-ir|obj] [--default-types] [-o <output>]";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Emit {
+    LlvmIr,
+    Obj,
+}
+
+impl Emit {
+    const fn extension(self) -> &'static str {
+        match self {
+            Self::LlvmIr => "ll",
+            Self::Obj => "o",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Args {
+    input: PathBuf,
+    output: PathBuf,
+    emit: Emit,
+    /// Default ambiguous types to `Int`, rather than rejecting them
+    default_types: bool,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut emit = Emit::LlvmIr;
+    let mut default_types = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--emit=llvm-ir" => emit = Emit::LlvmIr,
+            "--emit=obj" => emit = Emit::Obj,
+            "--default-types" => default_types = true,
+            "-o" => match args.next() {
+                Some(path) => output = Some(PathBuf::from(path)),
+                None => return Err("missing path after `-o`".into()),
+            },
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+
+    let input = input.ok_or_else(|| String::from("missing input file"))?;
+    let output = output.unwrap_or_else(|| input.with_extension(emit.extension()));
+    Ok(Args {
+        input,
+        output,
+        emit,
+        default_types,
+    })
+}
+
+fn write_object(module: &inkwell::module::Module, path: &Path) -> Result<(), String> {
+    Target::initialize_native(&InitializationConfig::default())?;
+    let triple = TargetMachine::get_default_triple();
+    let target = Target::from_triple(&triple).map_err(|err| err.to_string())?;
+    let machine = target
+        .create_target_machine(
+            &triple,
+            "generic",
+            "",
+            OptimizationLevel::Default,
+            // modern linkers produce position independent executables by default
+            RelocMode::PIC,
+            CodeModel::Default,
+        )
+        .ok_or_else(|| String::from("could not create a target machine"))?;
+    machine
+        .write_to_file(module, FileType::Object, path)
+        .map_err(|err| err.to_string())
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let src = fs::read_to_string(&args.input)
+        .map_err(|err| format!("cannot read {}: {}", args.input.display(), err))?;
+    let mut expr = parse_and_type(&src).map_err(|err| err.to_string())?;
+    if args.default_types {
+        expr = default_type_vars(&expr);
+    }
+
+    let llvm = Context::create();
+    let name = args
+        .input
+        .file_stem()
+        .map_or_else(|| "simpl".into(), |stem| stem.to_string_lossy());
+    let module = compile_program(&llvm, &name, expr).map_err(|err| err.to_string())?;
+    module.verify().map_err(|err| err.to_string())?;
+
+    match args.emit {
+        Emit::LlvmIr => module
+            .print_to_file(&args.output)
+            .map_err(|err| err.to_string()),
+        Emit::Obj => write_object(&module, &args.output),
+    }
+}
+
+fn main() {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {}\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    // every pass, including dropping the program, recurses on its tree
+    if let Err(err) = with_large_stack(move || run(&args)) {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
@@ -1,10 +1,10 @@
 use crate::hir::Expr;
-pub use crate::hir::{Binop, Lit, Param, Symbol, Type};
-use indexmap::{indexmap as imap, IndexMap};
+pub use crate::hir::{
+    visit::{free_vars, FreeVars},
+    Binop, Lit, Param, Symbol, Type, Unop,
+};
 use std::collections::HashMap;
 
-pub type FreeVars = IndexMap<Symbol, Type>;
-
 #[derive(Debug, Clone)]
 pub enum CExpr {
     Lit {
@@ -25,6 +25,11 @@ pub enum CExpr {
         rhs: Box<Self>,
         op: Binop,
     },
+    Unop {
+        ty: Type,
+        op: Unop,
+        operand: Box<Self>,
+    },
     If {
         ty: Type,
         test: Box<Self>,
@@ -46,11 +51,19 @@ pub enum CExpr {
         param: Param,
         free_vars: FreeVars,
         body: Box<Self>,
+        /// The name of the function shared with alpha-equivalent closures.
+        /// Set by `dedup_closures`
+        code: Option<Symbol>,
+        /// Whether the env can be allocated on the stack, because the closure
+        /// does not outlive the function that creates it. Set by
+        /// `mark_stack_envs`
+        stack_env: bool,
     },
     App {
         ty: Type,
         func: Box<Self>,
         arg: Box<Self>,
+        tail: bool,
     },
 }
 
@@ -68,6 +81,7 @@ impl CExpr {
             | Self::Var { ty, .. }
             | Self::EnvRef { ty, .. }
             | Self::Binop { ty, .. }
+            | Self::Unop { ty, .. }
             | Self::If { ty, .. }
             | Self::Let { ty, .. }
             | Self::Letrec { ty, .. }
@@ -87,6 +101,11 @@ pub fn convert(expr: Expr) -> CExpr {
             rhs: box convert(*rhs),
             op,
         },
+        Expr::Unop { ty, op, operand } => CExpr::Unop {
+            ty,
+            op,
+            operand: box convert(*operand),
+        },
         Expr::If {
             ty,
             test,
@@ -143,12 +162,15 @@ pub fn convert(expr: Expr) -> CExpr {
                 param: param.clone(),
                 free_vars: fv,
                 body: box substitute(convert(*body.clone()), subst),
+                code: None,
+                stack_env: false,
             }
         }
         Expr::App { ty, func, arg } => CExpr::App {
             ty,
             func: box convert(*func),
             arg: box convert(*arg),
+            tail: false,
         },
     }
 }
@@ -163,6 +185,11 @@ fn substitute(expr: CExpr, subst: &HashMap<Symbol, CExpr>) -> CExpr {
             rhs: box substitute(*rhs, subst),
             op,
         },
+        CExpr::Unop { ty, op, operand } => CExpr::Unop {
+            ty,
+            op,
+            operand: box substitute(*operand, subst),
+        },
         CExpr::If {
             ty,
             test,
@@ -198,70 +225,102 @@ fn substitute(expr: CExpr, subst: &HashMap<Symbol, CExpr>) -> CExpr {
             param,
             free_vars,
             body,
+            code,
+            stack_env,
         } => CExpr::MkClosure {
             ty,
             param,
             free_vars,
             body: box substitute(*body, subst),
+            code,
+            stack_env,
         },
-        CExpr::App { ty, func, arg } => CExpr::App {
+        CExpr::App {
+            ty,
+            func,
+            arg,
+            tail,
+        } => CExpr::App {
             ty,
             func: box substitute(*func, subst),
             arg: box substitute(*arg, subst),
+            tail,
         },
     }
 }
 
-pub fn free_vars(expr: &Expr) -> FreeVars {
-    match expr {
-        Expr::Lit { .. } => imap![],
-        Expr::Var { name, ty } => imap![*name => ty.clone()],
-        Expr::Binop { lhs, rhs, .. } => imap_union(free_vars(lhs), free_vars(rhs)),
-        Expr::If {
-            test, then, els, ..
-        } => imap_union(imap_union(free_vars(test), free_vars(then)), free_vars(els)),
-        Expr::Let { binding, body, .. } => imap_diff(
-            imap_union(free_vars(&*binding.val), free_vars(body)),
-            &imap![binding.name => binding.ty.clone()],
-        ),
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{opt::eliminate_dead_lets, types::parse_and_type};
 
-        Expr::Letrec { bindings, body, .. } => imap_diff(
-            bindings.iter().fold(free_vars(body), |acc, b| {
-                imap_union(acc, free_vars(&*b.val))
-            }),
-            &bindings.iter().map(|b| (b.name, b.ty.clone())).collect(),
-        ),
-        Expr::Lambda { param, body, .. } => {
-            imap_diff(free_vars(body), &imap![param.name => param.ty.clone()])
+    /// The variables captured by the first closure in `expr`, looking through
+    /// `let`s
+    fn captures(expr: &CExpr) -> Vec<String> {
+        match expr {
+            CExpr::Let { body, .. } => captures(body),
+            CExpr::MkClosure { free_vars, .. } => {
+                free_vars.keys().map(ToString::to_string).collect()
+            }
+            _ => panic!("no closure in {:?}", expr),
         }
+    }
 
-        Expr::App { func, arg, .. } => imap_union(free_vars(func), free_vars(arg)),
+    #[track_caller]
+    fn test_captures(src: &str, before: &[&str], after: &[&str]) {
+        let expr = parse_and_type(src).unwrap();
+        assert_eq!(captures(&convert(expr.clone())), before);
+        assert_eq!(captures(&convert(eliminate_dead_lets(expr))), after);
     }
-}
 
-fn imap_union<K, V>(hm1: IndexMap<K, V>, hm2: IndexMap<K, V>) -> IndexMap<K, V>
-where
-    K: std::hash::Hash + Eq,
-    V: std::hash::Hash + Eq,
-{
-    let mut ret = IndexMap::new();
-    ret.extend(hm1);
-    ret.extend(hm2);
-    ret
-}
+    #[test]
+    fn captures_after_dead_lets() {
+        test_captures(r"\y -> let z = 1 in y", &[], &[]);
+        test_captures(r"let x = 1 in \y -> x + y", &["x"], &["x"]);
+        test_captures(r"let x = 1 in \y -> let z = x in y", &["x"], &[]);
+        test_captures(
+            r"let x = 1, w = 2 in \y -> let z = x + w in y + w",
+            &["x", "w"],
+            &["w"],
+        );
+    }
 
-fn imap_diff<K, V>(hm1: IndexMap<K, V>, hm2: &IndexMap<K, V>) -> IndexMap<K, V>
-where
-    K: std::hash::Hash + Eq + Clone + std::fmt::Debug,
-    V: std::hash::Hash + Eq + Clone + std::fmt::Debug,
-{
-    hm1.into_iter()
-        .filter_map(|(k, v)| {
-            if hm2.contains_key(&k) {
-                None
-            } else {
-                Some((k, v))
+    #[test]
+    fn convert_binop_in_closure() {
+        let cexpr = convert(parse_and_type(r"\x -> x + 1").unwrap());
+        let body = match cexpr {
+            CExpr::MkClosure { free_vars, body, .. } => {
+                assert!(free_vars.is_empty());
+                body
             }
-        })
-        .collect::<IndexMap<K, V>>()
+            cexpr => panic!("expected a closure, got {:?}", cexpr),
+        };
+        assert!(matches!(
+            *body,
+            CExpr::Binop {
+                ty: Type::Int,
+                op: Binop::IntAdd,
+                lhs: box CExpr::Var { .. },
+                rhs: box CExpr::Lit {
+                    val: Lit::Int(1),
+                    ..
+                },
+            }
+        ));
+    }
+
+    #[test]
+    fn convert_binop() {
+        let cexpr = convert(parse_and_type("1 + 2").unwrap());
+        assert_eq!(cexpr.ty(), Type::Int);
+        assert!(matches!(
+            cexpr,
+            CExpr::Binop {
+                op: Binop::IntAdd,
+                lhs: box CExpr::Lit { .. },
+                rhs: box CExpr::Lit { .. },
+                ..
+            }
+        ));
+    }
 }
@@ -0,0 +1,417 @@
+use super::closure::{CExpr, FreeVars, LetBinding, Symbol};
+use simple_symbol::intern;
+use std::collections::HashMap;
+
+/// Give alpha-equivalent closures (same types, same captured variable layout,
+/// and the same body up to renaming of bound variables) the same `code`, so
+/// that the backend only generates one function for all of them.
+/// Closures with no equivalent are left alone
+pub fn dedup_closures(expr: CExpr) -> CExpr {
+    let mut closures = Vec::new();
+    collect_closures(&expr, &mut closures);
+
+    // the index of the first closure equivalent to each closure
+    let reprs: Vec<usize> = closures
+        .iter()
+        .enumerate()
+        .map(|(idx, closure)| {
+            closures[..idx]
+                .iter()
+                .position(|other| alpha_eq_closures(other, closure))
+                .unwrap_or(idx)
+        })
+        .collect();
+
+    let mut names = HashMap::new();
+    let codes: Vec<Option<Symbol>> = reprs
+        .iter()
+        .enumerate()
+        .map(|(idx, &repr)| {
+            if repr == idx && !reprs[idx + 1..].contains(&idx) {
+                return None;
+            }
+            let len = names.len();
+            Some(
+                *names
+                    .entry(repr)
+                    .or_insert_with(|| intern(format!("shared_lambda.{}", len))),
+            )
+        })
+        .collect();
+
+    set_codes(expr, &mut codes.into_iter())
+}
+
+/// Every `MkClosure` in `expr`, in pre-order
+fn collect_closures<'a>(expr: &'a CExpr, closures: &mut Vec<&'a CExpr>) {
+    match expr {
+        CExpr::Lit { .. } | CExpr::Var { .. } | CExpr::EnvRef { .. } => {}
+        CExpr::Binop { lhs, rhs, .. } => {
+            collect_closures(lhs, closures);
+            collect_closures(rhs, closures);
+        }
+        CExpr::Unop { operand, .. } => collect_closures(operand, closures),
+        CExpr::If {
+            test, then, els, ..
+        } => {
+            collect_closures(test, closures);
+            collect_closures(then, closures);
+            collect_closures(els, closures);
+        }
+        CExpr::Let { binding, body, .. } => {
+            collect_closures(&binding.val, closures);
+            collect_closures(body, closures);
+        }
+        CExpr::Letrec { bindings, body, .. } => {
+            for binding in bindings {
+                collect_closures(&binding.val, closures);
+            }
+            collect_closures(body, closures);
+        }
+        CExpr::MkClosure { body, .. } => {
+            closures.push(expr);
+            collect_closures(body, closures);
+        }
+        CExpr::App { func, arg, .. } => {
+            collect_closures(func, closures);
+            collect_closures(arg, closures);
+        }
+    }
+}
+
+/// Replace the `code` of every `MkClosure`, in the same order as
+/// `collect_closures`
+fn set_codes(expr: CExpr, codes: &mut impl Iterator<Item = Option<Symbol>>) -> CExpr {
+    match expr {
+        CExpr::Lit { .. } | CExpr::Var { .. } | CExpr::EnvRef { .. } => expr,
+        CExpr::Binop { ty, lhs, rhs, op } => CExpr::Binop {
+            ty,
+            lhs: box set_codes(*lhs, codes),
+            rhs: box set_codes(*rhs, codes),
+            op,
+        },
+        CExpr::Unop { ty, op, operand } => CExpr::Unop {
+            ty,
+            op,
+            operand: box set_codes(*operand, codes),
+        },
+        CExpr::If {
+            ty,
+            test,
+            then,
+            els,
+        } => CExpr::If {
+            ty,
+            test: box set_codes(*test, codes),
+            then: box set_codes(*then, codes),
+            els: box set_codes(*els, codes),
+        },
+        CExpr::Let { ty, binding, body } => CExpr::Let {
+            ty,
+            binding: LetBinding {
+                val: box set_codes(*binding.val, codes),
+                ..binding
+            },
+            body: box set_codes(*body, codes),
+        },
+        CExpr::Letrec { ty, bindings, body } => CExpr::Letrec {
+            ty,
+            bindings: bindings
+                .into_iter()
+                .map(|binding| LetBinding {
+                    val: box set_codes(*binding.val, codes),
+                    ..binding
+                })
+                .collect(),
+            body: box set_codes(*body, codes),
+        },
+        CExpr::MkClosure {
+            ty,
+            param,
+            free_vars,
+            body,
+            stack_env,
+            ..
+        } => {
+            let code = codes.next().unwrap();
+            CExpr::MkClosure {
+                ty,
+                param,
+                free_vars,
+                body: box set_codes(*body, codes),
+                code,
+                stack_env,
+            }
+        }
+        CExpr::App {
+            ty,
+            func,
+            arg,
+            tail,
+        } => CExpr::App {
+            ty,
+            func: box set_codes(*func, codes),
+            arg: box set_codes(*arg, codes),
+            tail,
+        },
+    }
+}
+
+/// Whether two closures can share the same generated function
+fn alpha_eq_closures(lhs: &CExpr, rhs: &CExpr) -> bool {
+    match (lhs, rhs) {
+        (
+            CExpr::MkClosure {
+                ty: ty1,
+                param: param1,
+                free_vars: fv1,
+                body: body1,
+                ..
+            },
+            CExpr::MkClosure {
+                ty: ty2,
+                param: param2,
+                free_vars: fv2,
+                body: body2,
+                ..
+            },
+        ) => {
+            ty1 == ty2
+                && param1.ty == param2.ty
+                && fv1.values().eq(fv2.values())
+                && AlphaEq {
+                    envs: (fv1, fv2),
+                    bound: vec![(param1.name, param2.name)],
+                }
+                .eq(body1, body2)
+        }
+        _ => false,
+    }
+}
+
+/// Compares the bodies of two closures
+struct AlphaEq<'a> {
+    envs: (&'a FreeVars, &'a FreeVars),
+    /// Pairs of corresponding binders, innermost last
+    bound: Vec<(Symbol, Symbol)>,
+}
+
+impl AlphaEq<'_> {
+    fn env_index(env: &FreeVars, name: Symbol) -> Option<usize> {
+        env.get_full(&name).map(|(idx, ..)| idx)
+    }
+
+    /// Whether `name1` and `name2` refer to the same captured variable
+    fn env_eq(&self, name1: Symbol, name2: Symbol) -> bool {
+        Self::env_index(self.envs.0, name1) == Self::env_index(self.envs.1, name2)
+    }
+
+    /// Whether `name1` and `name2` refer to corresponding binders, the same
+    /// captured variable, or (if neither) the same global
+    fn var_eq(&self, name1: Symbol, name2: Symbol) -> bool {
+        match self
+            .bound
+            .iter()
+            .rev()
+            .find(|(bound1, bound2)| *bound1 == name1 || *bound2 == name2)
+        {
+            Some(pair) => *pair == (name1, name2),
+            None => match (
+                Self::env_index(self.envs.0, name1),
+                Self::env_index(self.envs.1, name2),
+            ) {
+                (None, None) => name1 == name2,
+                (idx1, idx2) => idx1 == idx2,
+            },
+        }
+    }
+
+    fn eq(&mut self, lhs: &CExpr, rhs: &CExpr) -> bool {
+        if lhs.ty() != rhs.ty() {
+            return false;
+        }
+
+        match (lhs, rhs) {
+            (CExpr::Lit { val: val1, .. }, CExpr::Lit { val: val2, .. }) => val1 == val2,
+            (CExpr::Var { name: name1, .. }, CExpr::Var { name: name2, .. }) => {
+                self.var_eq(*name1, *name2)
+            }
+            (CExpr::EnvRef { name: name1, .. }, CExpr::EnvRef { name: name2, .. }) => {
+                self.env_eq(*name1, *name2)
+            }
+            (
+                CExpr::Binop {
+                    lhs: lhs1,
+                    rhs: rhs1,
+                    op: op1,
+                    ..
+                },
+                CExpr::Binop {
+                    lhs: lhs2,
+                    rhs: rhs2,
+                    op: op2,
+                    ..
+                },
+            ) => op1 == op2 && self.eq(lhs1, lhs2) && self.eq(rhs1, rhs2),
+            (
+                CExpr::Unop {
+                    op: op1,
+                    operand: operand1,
+                    ..
+                },
+                CExpr::Unop {
+                    op: op2,
+                    operand: operand2,
+                    ..
+                },
+            ) => op1 == op2 && self.eq(operand1, operand2),
+            (
+                CExpr::If {
+                    test: test1,
+                    then: then1,
+                    els: els1,
+                    ..
+                },
+                CExpr::If {
+                    test: test2,
+                    then: then2,
+                    els: els2,
+                    ..
+                },
+            ) => self.eq(test1, test2) && self.eq(then1, then2) && self.eq(els1, els2),
+            (
+                CExpr::Let {
+                    binding: binding1,
+                    body: body1,
+                    ..
+                },
+                CExpr::Let {
+                    binding: binding2,
+                    body: body2,
+                    ..
+                },
+            ) => {
+                if binding1.ty != binding2.ty || !self.eq(&binding1.val, &binding2.val) {
+                    return false;
+                }
+                self.bound.push((binding1.name, binding2.name));
+                let eq = self.eq(body1, body2);
+                self.bound.pop();
+                eq
+            }
+            (
+                CExpr::Letrec {
+                    bindings: bindings1,
+                    body: body1,
+                    ..
+                },
+                CExpr::Letrec {
+                    bindings: bindings2,
+                    body: body2,
+                    ..
+                },
+            ) => {
+                if bindings1.len() != bindings2.len() {
+                    return false;
+                }
+                let len = self.bound.len();
+                self.bound.extend(
+                    bindings1
+                        .iter()
+                        .zip(bindings2)
+                        .map(|(binding1, binding2)| (binding1.name, binding2.name)),
+                );
+                let eq = bindings1.iter().zip(bindings2).all(|(binding1, binding2)| {
+                    binding1.ty == binding2.ty && self.eq(&binding1.val, &binding2.val)
+                }) && self.eq(body1, body2);
+                self.bound.truncate(len);
+                eq
+            }
+            (
+                CExpr::MkClosure {
+                    param: param1,
+                    free_vars: fv1,
+                    body: body1,
+                    ..
+                },
+                CExpr::MkClosure {
+                    param: param2,
+                    free_vars: fv2,
+                    body: body2,
+                    ..
+                },
+            ) => {
+                // the captured variables are loaded from the enclosing scope
+                param1.ty == param2.ty
+                    && fv1.len() == fv2.len()
+                    && fv1.iter().zip(fv2).all(|((name1, ty1), (name2, ty2))| {
+                        ty1 == ty2 && self.var_eq(*name1, *name2)
+                    })
+                    && AlphaEq {
+                        envs: (fv1, fv2),
+                        bound: vec![(param1.name, param2.name)],
+                    }
+                    .eq(body1, body2)
+            }
+            (
+                CExpr::App {
+                    func: func1,
+                    arg: arg1,
+                    tail: tail1,
+                    ..
+                },
+                CExpr::App {
+                    func: func2,
+                    arg: arg2,
+                    tail: tail2,
+                    ..
+                },
+            ) => tail1 == tail2 && self.eq(func1, func2) && self.eq(arg1, arg2),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{codegen::closure::convert, types::parse_and_type};
+
+    fn closure(src: &str) -> CExpr {
+        convert(parse_and_type(src).unwrap())
+    }
+
+    #[test]
+    fn alpha_eq() {
+        let eq = |lhs, rhs| alpha_eq_closures(&closure(lhs), &closure(rhs));
+
+        assert!(eq(r"\x -> x + 1", r"\y -> y + 1"));
+        assert!(eq(r"\x -> let y = x in y", r"\a -> let b = a in b"));
+        assert!(eq(r"\x -> \y -> x + y", r"\a -> \b -> a + b"));
+
+        assert!(!eq(r"\x -> x + 1", r"\y -> y + 2"));
+        assert!(!eq(r"\x -> x + 1", r"\x -> x - 1"));
+        assert!(!eq(r"\x -> \y -> x + y", r"\x -> \y -> y + y"));
+        assert!(!eq(r"\x -> x", r"\x: Int -> x"));
+    }
+
+    #[test]
+    fn dedup_codes() {
+        let expr = dedup_closures(closure(
+            r"let f = \x -> x + 1, g = \y -> y + 1, h = \z -> z * 2 in h (f (g 1))",
+        ));
+
+        let mut closures = Vec::new();
+        collect_closures(&expr, &mut closures);
+        let codes: Vec<_> = closures
+            .iter()
+            .map(|closure| match closure {
+                CExpr::MkClosure { code, .. } => *code,
+                _ => unreachable!(),
+            })
+            .collect();
+
+        let shared = Some(intern("shared_lambda.0"));
+        assert_eq!(codes, vec![shared, shared, None]);
+    }
+}
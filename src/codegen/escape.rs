@@ -0,0 +1,243 @@
+use super::closure::{CExpr, LetBinding, Symbol};
+
+/// Mark every closure that cannot outlive the function that creates it, so
+/// that the backend can allocate its env on the stack rather than the heap.
+/// The analysis is conservative: a closure is only marked if it is applied
+/// immediately, or bound by a `let` and then only ever applied.
+/// Calls of marked closures are no longer tail calls, since the callee reads
+/// its env from the caller's stack frame.
+/// Assumes that every binder has a distinct name (see `rename`)
+pub fn mark_stack_envs(expr: CExpr) -> CExpr {
+    mark(expr, &mut Vec::new())
+}
+
+/// `stack_closures` are the variables in scope that are bound to closures
+/// with stack allocated envs
+fn mark(expr: CExpr, stack_closures: &mut Vec<Symbol>) -> CExpr {
+    match expr {
+        CExpr::Lit { .. } | CExpr::Var { .. } | CExpr::EnvRef { .. } => expr,
+        CExpr::Binop { ty, lhs, rhs, op } => CExpr::Binop {
+            ty,
+            lhs: box mark(*lhs, stack_closures),
+            rhs: box mark(*rhs, stack_closures),
+            op,
+        },
+        CExpr::Unop { ty, op, operand } => CExpr::Unop {
+            ty,
+            op,
+            operand: box mark(*operand, stack_closures),
+        },
+        CExpr::If {
+            ty,
+            test,
+            then,
+            els,
+        } => CExpr::If {
+            ty,
+            test: box mark(*test, stack_closures),
+            then: box mark(*then, stack_closures),
+            els: box mark(*els, stack_closures),
+        },
+        CExpr::Let { ty, binding, body } => {
+            let on_stack = matches!(*binding.val, CExpr::MkClosure { .. })
+                && only_applied(binding.name, &body);
+            let mut val = mark(*binding.val, stack_closures);
+            if on_stack {
+                set_stack_env(&mut val);
+                stack_closures.push(binding.name);
+            }
+            let body = mark(*body, stack_closures);
+            if on_stack {
+                stack_closures.pop();
+            }
+            CExpr::Let {
+                ty,
+                binding: LetBinding {
+                    val: box val,
+                    ..binding
+                },
+                body: box body,
+            }
+        }
+        CExpr::Letrec { ty, bindings, body } => CExpr::Letrec {
+            ty,
+            bindings: bindings
+                .into_iter()
+                .map(|binding| LetBinding {
+                    val: box mark(*binding.val, stack_closures),
+                    ..binding
+                })
+                .collect(),
+            body: box mark(*body, stack_closures),
+        },
+        // the body is a different function, which cannot refer to the stack
+        // closures in this one, since they are never captured
+        CExpr::MkClosure {
+            ty,
+            param,
+            free_vars,
+            body,
+            code,
+            stack_env,
+        } => CExpr::MkClosure {
+            ty,
+            param,
+            free_vars,
+            body: box mark(*body, &mut Vec::new()),
+            code,
+            stack_env,
+        },
+        CExpr::App {
+            ty,
+            func,
+            arg,
+            tail,
+        } => {
+            let on_stack = match &*func {
+                CExpr::MkClosure { .. } => true,
+                CExpr::Var { name, .. } => stack_closures.contains(name),
+                _ => false,
+            };
+            let mut func = mark(*func, stack_closures);
+            set_stack_env(&mut func);
+            CExpr::App {
+                ty,
+                func: box func,
+                arg: box mark(*arg, stack_closures),
+                tail: tail && !on_stack,
+            }
+        }
+    }
+}
+
+fn set_stack_env(closure: &mut CExpr) {
+    if let CExpr::MkClosure { stack_env, .. } = closure {
+        *stack_env = true;
+    }
+}
+
+/// Whether every use of `name` in `expr` is as the function of an `App`, so
+/// that its value cannot be returned, passed to another function, or
+/// captured by another closure
+fn only_applied(name: Symbol, expr: &CExpr) -> bool {
+    match expr {
+        CExpr::Lit { .. } => true,
+        CExpr::Var { name: var, .. } | CExpr::EnvRef { name: var, .. } => *var != name,
+        CExpr::Binop { lhs, rhs, .. } => only_applied(name, lhs) && only_applied(name, rhs),
+        CExpr::Unop { operand, .. } => only_applied(name, operand),
+        CExpr::If {
+            test, then, els, ..
+        } => only_applied(name, test) && only_applied(name, then) && only_applied(name, els),
+        CExpr::Let { binding, body, .. } => {
+            only_applied(name, &binding.val) && only_applied(name, body)
+        }
+        CExpr::Letrec { bindings, body, .. } => {
+            bindings
+                .iter()
+                .all(|binding| only_applied(name, &binding.val))
+                && only_applied(name, body)
+        }
+        CExpr::MkClosure { free_vars, .. } => !free_vars.contains_key(&name),
+        CExpr::App { func, arg, .. } => {
+            let applied = matches!(&**func, CExpr::Var { name: var, .. } if *var == name);
+            (applied || only_applied(name, func)) && only_applied(name, arg)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        codegen::{closure::convert, rename::rename, tail::mark_tail_calls},
+        types::parse_and_type,
+    };
+
+    /// Whether the env of each closure in `expr` is on the stack, in pre-order
+    fn stack_envs(expr: &CExpr, envs: &mut Vec<bool>) {
+        match expr {
+            CExpr::Lit { .. } | CExpr::Var { .. } | CExpr::EnvRef { .. } => {}
+            CExpr::Binop { lhs, rhs, .. } => {
+                stack_envs(lhs, envs);
+                stack_envs(rhs, envs);
+            }
+            CExpr::Unop { operand, .. } => stack_envs(operand, envs),
+            CExpr::If {
+                test, then, els, ..
+            } => {
+                stack_envs(test, envs);
+                stack_envs(then, envs);
+                stack_envs(els, envs);
+            }
+            CExpr::Let { binding, body, .. } => {
+                stack_envs(&binding.val, envs);
+                stack_envs(body, envs);
+            }
+            CExpr::Letrec { bindings, body, .. } => {
+                for binding in bindings {
+                    stack_envs(&binding.val, envs);
+                }
+                stack_envs(body, envs);
+            }
+            CExpr::MkClosure {
+                body, stack_env, ..
+            } => {
+                envs.push(*stack_env);
+                stack_envs(body, envs);
+            }
+            CExpr::App { func, arg, .. } => {
+                stack_envs(func, envs);
+                stack_envs(arg, envs);
+            }
+        }
+    }
+
+    #[track_caller]
+    fn test_stack_envs(src: &str, expected: &[bool]) {
+        let expr = rename(parse_and_type(src).unwrap());
+        let cexpr = mark_stack_envs(mark_tail_calls(convert(expr)));
+        let mut envs = Vec::new();
+        stack_envs(&cexpr, &mut envs);
+        assert_eq!(envs, expected);
+    }
+
+    #[test]
+    fn stack_envs_local() {
+        test_stack_envs(r"let x = 1 in let f = \y -> x + y in f 5", &[true]);
+        test_stack_envs(r"let x = 1 in (\y -> x + y) 5", &[true]);
+        test_stack_envs(r"let x = 1 in let f = \y -> x + y in f (f 5)", &[true]);
+        test_stack_envs(r"\x -> let f = \y -> x + y in f 1", &[false, true]);
+    }
+
+    #[test]
+    fn stack_envs_escaping() {
+        // returned
+        test_stack_envs(r"let x = 1 in \y -> x + y", &[false]);
+        test_stack_envs(r"let x = 1 in let f = \y -> x + y in f", &[false]);
+        // passed to another function
+        test_stack_envs(r"let x = 1 in let f = \y -> x + y in (\g -> g 1) f", &[
+            false, false,
+        ]);
+        // captured by another closure
+        test_stack_envs(r"let x = 1 in let f = \y -> x + y in \z -> f z", &[
+            false, false,
+        ]);
+        test_stack_envs(r"letrec f = \x -> f x in f 1", &[false]);
+    }
+
+    #[test]
+    fn stack_env_calls_are_not_tail_calls() {
+        let expr = rename(parse_and_type(r"let x = 1 in let f = \y -> x + y in f 5").unwrap());
+        match mark_stack_envs(mark_tail_calls(convert(expr))) {
+            CExpr::Let {
+                body:
+                    box CExpr::Let {
+                        body: box CExpr::App { tail, .. },
+                        ..
+                    },
+                ..
+            } => assert!(!tail),
+            cexpr => panic!("Unexpected expr: {:?}", cexpr),
+        }
+    }
+}
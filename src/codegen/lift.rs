@@ -0,0 +1,215 @@
+use crate::hir::{
+    visit::{free_vars, walk_fold, ExprFolder, FreeVars},
+    Expr, Param, Symbol, Type,
+};
+use simple_symbol::intern;
+use std::collections::{HashMap, HashSet};
+
+/// Hoist every lambda in `expr` out to a top-level function, returning the
+/// functions and what is left of `expr`.
+/// The variables a lambda captures become extra parameters of its function,
+/// before its own parameter, and the lambda is replaced by a partial
+/// application of the function to the captured variables. Functions defined
+/// by a `letrec` capture the free variables of the whole group, and refer to
+/// each other directly, so no `letrec`s are left afterwards.
+/// Functions are named `lambda.N`, which cannot clash with a name in the
+/// source. `expr` should already have been renamed by `rename::rename`
+pub fn lambda_lift(expr: Expr) -> (Vec<(Symbol, Expr)>, Expr) {
+    let mut lifter = Lifter::default();
+    let expr = lifter.fold_expr(expr);
+    (lifter.funcs, expr)
+}
+
+#[derive(Default)]
+struct Lifter {
+    funcs: Vec<(Symbol, Expr)>,
+    /// The names in `funcs`. These are never captured
+    globals: HashSet<Symbol>,
+}
+
+impl Lifter {
+    fn fresh(&mut self) -> Symbol {
+        let name = intern(format!("lambda.{}", self.globals.len()));
+        self.globals.insert(name);
+        name
+    }
+
+    /// The free variables of `expr`, other than functions that have already
+    /// been lifted
+    fn captures(&self, expr: &Expr) -> FreeVars {
+        let mut fv = free_vars(expr);
+        fv.retain(|name, _| !self.globals.contains(name));
+        fv
+    }
+
+    /// Lift the body of `lambda`, and add it as a function named `name`
+    /// taking `captured` as extra parameters
+    fn lift(&mut self, name: Symbol, lambda: Expr, captured: &FreeVars) {
+        let func = match lambda {
+            Expr::Lambda { ty, param, body } => Expr::Lambda {
+                ty,
+                param,
+                body: box self.fold_expr(*body),
+            },
+            _ => unreachable!(),
+        };
+        let func = captured.iter().rev().fold(func, |body, (name, ty)| Expr::Lambda {
+            ty: Type::Fn(box ty.clone(), box body.ty()),
+            param: Param {
+                ty: ty.clone(),
+                name: *name,
+                ann: None,
+            },
+            body: box body,
+        });
+        self.funcs.push((name, func));
+    }
+}
+
+impl ExprFolder for Lifter {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Lambda { .. } => {
+                let name = self.fresh();
+                let captured = self.captures(&expr);
+                let ty = expr.ty();
+                self.lift(name, expr, &captured);
+                partial_app(name, ty, &captured)
+            }
+            Expr::Letrec { bindings, body, .. } => {
+                let group: HashSet<_> = bindings.iter().map(|binding| binding.name).collect();
+                let mut captured = FreeVars::new();
+                for binding in &bindings {
+                    captured.extend(self.captures(&binding.val));
+                }
+                captured.retain(|name, _| !group.contains(name));
+
+                let names: Vec<_> = bindings.iter().map(|_| self.fresh()).collect();
+                let subst: HashMap<_, _> = bindings
+                    .iter()
+                    .zip(&names)
+                    .map(|(binding, name)| {
+                        let app = partial_app(*name, binding.ty.clone(), &captured);
+                        (binding.name, app)
+                    })
+                    .collect();
+
+                for (binding, name) in bindings.into_iter().zip(names) {
+                    let lambda = substitute(*binding.val, &subst);
+                    self.lift(name, lambda, &captured);
+                }
+                self.fold_expr(substitute(*body, &subst))
+            }
+            _ => walk_fold(self, expr),
+        }
+    }
+}
+
+/// `name captured1 ... capturedN`, where the result has type `ty`
+fn partial_app(name: Symbol, ty: Type, captured: &FreeVars) -> Expr {
+    let func_ty = captured
+        .values()
+        .rev()
+        .fold(ty, |ret, arg| Type::Fn(box arg.clone(), box ret));
+    captured.iter().fold(
+        Expr::Var {
+            ty: func_ty,
+            name,
+        },
+        |func, (name, ty)| Expr::App {
+            ty: match func.ty() {
+                Type::Fn(_, ret) => *ret,
+                _ => unreachable!(),
+            },
+            func: box func,
+            arg: box Expr::Var {
+                ty: ty.clone(),
+                name: *name,
+            },
+        },
+    )
+}
+
+/// Replace the variables in `expr` that are keys of `subst`. Binders are
+/// assumed to be unique, so there is no shadowing to worry about
+fn substitute(expr: Expr, subst: &HashMap<Symbol, Expr>) -> Expr {
+    struct Substitute<'a>(&'a HashMap<Symbol, Expr>);
+
+    impl ExprFolder for Substitute<'_> {
+        fn fold_expr(&mut self, expr: Expr) -> Expr {
+            match expr {
+                Expr::Var { name, .. } if self.0.contains_key(&name) => self.0[&name].clone(),
+                _ => walk_fold(self, expr),
+            }
+        }
+    }
+
+    Substitute(subst).fold_expr(expr)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{codegen::rename::rename, hir::PrettyConfig, ty, types::parse_and_type};
+
+    #[track_caller]
+    fn test_lift(src: &str, expected_funcs: &[(&str, &str)], expected: &str) {
+        let cfg = PrettyConfig {
+            width: 120,
+            ..PrettyConfig::default()
+        };
+        let (funcs, expr) = lambda_lift(rename(parse_and_type(src).unwrap()));
+        let funcs: Vec<_> = funcs
+            .iter()
+            .map(|(name, func)| (name.to_string(), func.pretty_with(&cfg)))
+            .collect();
+        let expected_funcs: Vec<_> = expected_funcs
+            .iter()
+            .map(|(name, func)| (name.to_string(), func.to_string()))
+            .collect();
+        assert_eq!(funcs, expected_funcs);
+        assert_eq!(expr.pretty_with(&cfg), expected);
+    }
+
+    #[test]
+    fn lift_captured_vars() {
+        test_lift(
+            r"let x = 5 in \y -> x + y",
+            &[("lambda.0", r"\x -> \y -> x + y")],
+            "let x = 5 in lambda.0 x",
+        );
+
+        let (funcs, expr) = lambda_lift(parse_and_type(r"let x = 5 in \y -> x + y").unwrap());
+        assert_eq!(funcs[0].1.ty(), ty![Int => Int => Int]);
+        assert_eq!(expr.ty(), ty![Int => Int]);
+    }
+
+    #[test]
+    fn lift_closed_lambda() {
+        test_lift(r"(\x -> x * 2) 21", &[("lambda.0", r"\x -> x * 2")], "lambda.0 21");
+    }
+
+    #[test]
+    fn lift_nested_lambdas() {
+        test_lift(
+            r"\x, y -> x + y",
+            &[
+                ("lambda.1", r"\x -> \y -> x + y"),
+                ("lambda.0", r"\x -> lambda.1 x"),
+            ],
+            "lambda.0",
+        );
+    }
+
+    #[test]
+    fn lift_letrec() {
+        test_lift(
+            r"let k = 1 in letrec f = \n -> if n == 0 then k else f (n - 1) in f 10",
+            &[(
+                "lambda.0",
+                r"\k -> \n -> if n == 0 then k else lambda.0 k (n - 1)",
+            )],
+            "let k = 1 in lambda.0 k 10",
+        );
+    }
+}
@@ -1,10 +1,10 @@
-use super::closure::{Binop, CExpr, FreeVars, LetBinding, Lit, Param, Type};
+use super::closure::{Binop, CExpr, FreeVars, LetBinding, Lit, Param, Type, Unop};
 use inkwell::{
     builder::Builder,
     context::Context,
     module::Module,
     types::{BasicType, BasicTypeEnum},
-    values::{AnyValue, BasicValue, BasicValueEnum, FunctionValue, PointerValue},
+    values::{AnyValue, BasicValue, BasicValueEnum, FunctionValue, IntValue, PointerValue},
     AddressSpace, FloatPredicate, IntPredicate,
 };
 use simple_symbol::{resolve, Symbol};
@@ -22,6 +22,10 @@ pub struct Compiler<'ctx> {
 #[derive(Debug, Clone)]
 pub struct Ctx<'a> {
     env: Env<'a>,
+    /// The functions generated for `let`-bound closures, so that applications
+    /// of them can call the function directly, rather than through the
+    /// closure's code pointer
+    fns: HashMap<Symbol, FunctionValue<'a>>,
     parent: FunctionValue<'a>,
     name: Option<&'a str>,
 }
@@ -30,6 +34,7 @@ impl<'a> Ctx<'a> {
     pub fn new(parent: FunctionValue<'a>) -> Self {
         Self {
             env: Env::new(),
+            fns: HashMap::new(),
             name: None,
             parent,
         }
@@ -39,10 +44,12 @@ impl<'a> Ctx<'a> {
 impl Type {
     fn llvm_type<'a>(&self, compiler: &Compiler<'a>) -> BasicTypeEnum<'a> {
         match self {
-            Self::Bool => compiler.llvm.bool_type().into(),
+            // `Unit` has only one value, so it is an `i1` that is always 0
+            Self::Unit | Self::Bool => compiler.llvm.bool_type().into(),
             Self::Int => compiler.llvm.i64_type().into(),
             Self::Float => compiler.llvm.f64_type().into(),
             Self::Fn(..) => compiler.closure_ty(),
+            Self::Tuple(_) => unreachable!("tuples are rejected by `compile`"),
             Self::Var(_) => panic!("Cannot instantiate type {}", self),
         }
     }
@@ -96,11 +103,64 @@ impl<'ctx> Compiler<'ctx> {
         &self.module
     }
 
+    /// Add a `main` function that prints the result of `toplevel`, so that
+    /// the module can be linked into an executable. `ty` is the type of the
+    /// expression given to `compile_toplevel`, which must be called first
+    pub fn compile_main(&self, ty: &Type) {
+        let i32_ty = self.llvm.i32_type();
+        let str_ty = self.llvm.i8_type().ptr_type(AddressSpace::Generic);
+        let printf = self.module.get_function("printf").unwrap_or_else(|| {
+            let printf_ty = i32_ty.fn_type(&[str_ty.into()], true);
+            self.module.add_function("printf", printf_ty, None)
+        });
+
+        let main = self
+            .module
+            .add_function("main", i32_ty.fn_type(&[], false), None);
+        let entry = self.llvm.append_basic_block(main, "main_entry");
+        self.builder.position_at_end(entry);
+
+        let toplevel = self.module.get_function("toplevel").unwrap();
+        let result = self
+            .builder
+            .build_call(toplevel, &[], "result")
+            .try_as_basic_value()
+            .left()
+            .unwrap();
+
+        let (fmt, arg) = match ty {
+            Type::Int => ("%ld\n", Some(result)),
+            Type::Float => ("%f\n", Some(result)),
+            // `i1` is not a valid vararg, so widen it to an `int`
+            Type::Bool => {
+                let result =
+                    self.builder
+                        .build_int_z_extend(result.into_int_value(), i32_ty, "result");
+                ("%d\n", Some(result.into()))
+            }
+            Type::Unit => ("()\n", None),
+            Type::Fn(..) => ("<closure>\n", None),
+            Type::Tuple(_) | Type::Var(_) => {
+                unreachable!("`compile` rejects tuples and type variables")
+            }
+        };
+        let fmt = self
+            .builder
+            .build_global_string_ptr(fmt, "fmt")
+            .as_pointer_value();
+
+        let mut args: Vec<BasicValueEnum> = vec![fmt.into()];
+        args.extend(arg);
+        self.builder.build_call(printf, &args, "printf");
+        self.builder.build_return(Some(&i32_ty.const_int(0, false)));
+    }
+
     fn compile_expr(&self, ctx: &Ctx<'ctx>, expr: &CExpr) -> BasicValueEnum {
         match expr {
             CExpr::Lit { val, .. } => self.compile_lit(val),
             CExpr::Var { name, .. } | CExpr::EnvRef { name, .. } => self.compile_var(ctx, *name),
             CExpr::Binop { lhs, rhs, op, .. } => self.compile_binop(ctx, lhs, rhs, *op),
+            CExpr::Unop { op, operand, .. } => self.compile_unop(ctx, *op, operand),
             CExpr::If {
                 test, then, els, ..
             } => self.compile_if(ctx, test, then, els),
@@ -109,15 +169,26 @@ impl<'ctx> Compiler<'ctx> {
                 param,
                 free_vars,
                 body,
+                code,
+                stack_env,
                 ..
-            } => self.compile_lambda(ctx, param, free_vars, body),
-            CExpr::App { func, arg, ty } => self.compile_app(ctx, ty, func, arg),
-            _ => todo!(),
+            } => {
+                self.compile_lambda(ctx, param, free_vars, body, *code, *stack_env)
+                    .1
+            }
+            CExpr::App {
+                func,
+                arg,
+                ty,
+                tail,
+            } => self.compile_app(ctx, ty, func, arg, *tail),
+            CExpr::Letrec { .. } => unreachable!("`compile` rejects `letrec`s"),
         }
     }
 
     fn compile_lit(&self, val: &Lit) -> BasicValueEnum {
         match *val {
+            Lit::Unit => self.llvm.bool_type().const_zero().into(),
             Lit::Bool(b) => self
                 .llvm
                 .bool_type()
@@ -166,13 +237,25 @@ impl<'ctx> Compiler<'ctx> {
             IntAdd => int_op!(build_int_add, "add"),
             IntSub => int_op!(build_int_sub, "sub"),
             IntMul => int_op!(build_int_mul, "mul"),
-            IntDiv => int_op!(build_int_exact_signed_div, "div"),
+            IntDiv => self.compile_int_div(ctx, lhs_val, rhs_val),
 
             IntLt => int_cmp!(IntPredicate::SLT, "cmp"),
             IntLeq => int_cmp!(IntPredicate::SLE, "cmp"),
             IntGt => int_cmp!(IntPredicate::SGT, "cmp"),
             IntGeq => int_cmp!(IntPredicate::SGE, "cmp"),
 
+            IntAnd => int_op!(build_and, "and"),
+            IntOr => int_op!(build_or, "or"),
+            IntXor => int_op!(build_xor, "xor"),
+            IntShl => self
+                .builder
+                .build_left_shift(lhs_val.into_int_value(), self.shift_amount(rhs_val), "shl")
+                .into(),
+            IntShr => self
+                .builder
+                .build_right_shift(lhs_val.into_int_value(), self.shift_amount(rhs_val), true, "shr")
+                .into(),
+
             FloatAdd => float_op!(build_float_add, "add"),
             FloatSub => float_op!(build_float_sub, "sub"),
             FloatMul => float_op!(build_float_mul, "mul"),
@@ -183,20 +266,113 @@ impl<'ctx> Compiler<'ctx> {
             FloatGt => float_cmp!(FloatPredicate::OGT, "cmp"),
             FloatGeq => float_cmp!(FloatPredicate::OGE, "cmp"),
 
+            FloatPow => self.call_intrinsic("llvm.pow.f64", &[lhs_val, rhs_val]),
+
             Eq => match lhs.ty() {
-                Type::Bool | Type::Int => int_cmp!(IntPredicate::EQ, "cmp"),
+                Type::Unit | Type::Bool | Type::Int => int_cmp!(IntPredicate::EQ, "cmp"),
                 Type::Float => float_cmp!(FloatPredicate::OEQ, "cmp"),
                 _ => todo!(),
             },
 
+            // unordered, so that `nan != nan` is true, as IEEE 754 requires. Every
+            // other float comparison is ordered, and so false when given NaN
             Neq => match lhs.ty() {
-                Type::Bool | Type::Int => int_cmp!(IntPredicate::NE, "cmp"),
+                Type::Unit | Type::Bool | Type::Int => int_cmp!(IntPredicate::NE, "cmp"),
                 Type::Float => float_cmp!(FloatPredicate::UNE, "cmp"),
                 _ => todo!(),
             },
         }
     }
 
+    /// `rhs_val` mod 64. Shifting by 64 or more gives poison in LLVM, so the
+    /// amount is masked, as `eval` and WebAssembly do
+    fn shift_amount(&self, rhs_val: BasicValueEnum<'ctx>) -> IntValue<'ctx> {
+        let mask = self.llvm.i64_type().const_int(63, false);
+        self.builder.build_and(rhs_val.into_int_value(), mask, "amount")
+    }
+
+    /// Signed division, rounding towards zero. Dividing by zero, or
+    /// `i64::MIN` by -1, traps rather than being undefined behaviour
+    fn compile_int_div(
+        &self,
+        ctx: &Ctx<'ctx>,
+        lhs_val: BasicValueEnum<'ctx>,
+        rhs_val: BasicValueEnum<'ctx>,
+    ) -> BasicValueEnum {
+        let i64_ty = self.llvm.i64_type();
+        let (lhs, rhs) = (lhs_val.into_int_value(), rhs_val.into_int_value());
+        let is_zero = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, rhs, i64_ty.const_zero(), "is_zero");
+
+        let is_minus_one = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            rhs,
+            i64_ty.const_all_ones(),
+            "is_minus_one",
+        );
+        let is_min = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            lhs,
+            i64_ty.const_int(1 << 63, false),
+            "is_min",
+        );
+        let overflows = self.builder.build_and(is_minus_one, is_min, "overflows");
+        let traps = self.builder.build_or(is_zero, overflows, "traps");
+
+        let trap_bb = self.llvm.append_basic_block(ctx.parent, "div_trap");
+        let div_bb = self.llvm.append_basic_block(ctx.parent, "div");
+        self.builder.build_conditional_branch(traps, trap_bb, div_bb);
+
+        self.builder.position_at_end(trap_bb);
+        let trap = self.module.get_function("llvm.trap").unwrap_or_else(|| {
+            let trap_ty = self.llvm.void_type().fn_type(&[], false);
+            self.module.add_function("llvm.trap", trap_ty, None)
+        });
+        self.builder.build_call(trap, &[], "trap");
+        self.builder.build_unreachable();
+
+        self.builder.position_at_end(div_bb);
+        self.builder.build_int_signed_div(lhs, rhs, "div").into()
+    }
+
+    fn compile_unop(&self, ctx: &Ctx<'ctx>, op: Unop, operand: &CExpr) -> BasicValueEnum {
+        let operand_val = self.compile_expr(ctx, operand);
+        match op {
+            Unop::Not => self
+                .builder
+                .build_not(operand_val.into_int_value(), "not")
+                .into(),
+            Unop::IntNeg => self
+                .builder
+                .build_int_neg(operand_val.into_int_value(), "neg")
+                .into(),
+            Unop::FloatNeg => self
+                .builder
+                .build_float_neg(operand_val.into_float_value(), "neg")
+                .into(),
+            Unop::FloatSqrt => self.call_intrinsic("llvm.sqrt.f64", &[operand_val]),
+            Unop::FloatAbs => self.call_intrinsic("llvm.fabs.f64", &[operand_val]),
+            Unop::FloatFloor => self.call_intrinsic("llvm.floor.f64", &[operand_val]),
+        }
+    }
+
+    /// Call the LLVM intrinsic `name`, which takes `args.len()` doubles and
+    /// returns a double
+    fn call_intrinsic(&self, name: &str, args: &[BasicValueEnum]) -> BasicValueEnum {
+        let f64_ty = self.llvm.f64_type();
+        let intrinsic = self.module.get_function(name).unwrap_or_else(|| {
+            let params: Vec<BasicTypeEnum> = vec![f64_ty.into(); args.len()];
+            self.module
+                .add_function(name, f64_ty.fn_type(&params, false), None)
+        });
+        self.builder
+            .build_call(intrinsic, args, "call")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+    }
+
     fn compile_if(
         &self,
         ctx: &Ctx<'ctx>,
@@ -252,7 +428,22 @@ impl<'ctx> Compiler<'ctx> {
 
         let old_name = ctx.name;
         ctx.name = Some(binding_name);
-        let value = self.compile_expr(&ctx, &binding.val);
+        let value = match &*binding.val {
+            CExpr::MkClosure {
+                param,
+                free_vars,
+                body,
+                code,
+                stack_env,
+                ..
+            } => {
+                let (fn_val, value) =
+                    self.compile_lambda(&ctx, param, free_vars, body, *code, *stack_env);
+                ctx.fns.insert(binding.name, fn_val);
+                value
+            }
+            val => self.compile_expr(&ctx, val),
+        };
         self.builder.build_store(alloca, value);
         ctx.env.insert(binding.name, alloca);
 
@@ -266,9 +457,14 @@ impl<'ctx> Compiler<'ctx> {
         param: &Param,
         free_vars: &FreeVars,
         body: &CExpr,
-    ) -> BasicValueEnum {
+        code: Option<Symbol>,
+        stack_env: bool,
+    ) -> (FunctionValue<'ctx>, BasicValueEnum<'ctx>) {
         let env_ty = self.env_ty(free_vars);
-        let fn_val = self.compile_function(ctx, free_vars, env_ty, param, body);
+        let fn_val = match code.and_then(|code| self.module.get_function(resolve(code))) {
+            Some(fn_val) => fn_val,
+            None => self.compile_function(ctx, code, free_vars, env_ty, param, body),
+        };
         self.builder
             .position_at_end(ctx.parent.get_last_basic_block().unwrap());
 
@@ -278,18 +474,22 @@ impl<'ctx> Compiler<'ctx> {
             .builder
             .build_struct_gep(closure, 0, "closure.code")
             .unwrap();
-        let fn_val = fn_val.as_any_value_enum().into_pointer_value();
-        let fn_val = self
+        let code_val = fn_val.as_any_value_enum().into_pointer_value();
+        let code_val = self
             .builder
-            .build_bitcast(fn_val, self.void_ptr_ty(), "closure.code");
+            .build_bitcast(code_val, self.void_ptr_ty(), "closure.code");
         self.builder
-            .build_store(code_gep, fn_val.as_any_value_enum().into_pointer_value());
+            .build_store(code_gep, code_val.as_any_value_enum().into_pointer_value());
 
         let env_gep = self
             .builder
             .build_struct_gep(closure, 1, "closure.env")
             .unwrap();
-        let env_val = self.builder.build_malloc(env_ty, "closure.env").unwrap();
+        let env_val = if stack_env {
+            self.builder.build_alloca(env_ty, "closure.env")
+        } else {
+            self.builder.build_malloc(env_ty, "closure.env").unwrap()
+        };
 
         for (idx, (name, _)) in free_vars.iter().enumerate() {
             let sname = &format!("env.{}", resolve(*name));
@@ -308,18 +508,19 @@ impl<'ctx> Compiler<'ctx> {
             .build_bitcast(env_val, self.void_ptr_ty(), "closure.env");
         self.builder.build_store(env_gep, env_val);
 
-        self.builder.build_load(closure, "closure")
+        (fn_val, self.builder.build_load(closure, "closure"))
     }
 
     fn compile_function(
         &self,
         ctx: &Ctx<'ctx>,
+        code: Option<Symbol>,
         free_vars: &FreeVars,
         env_ty: BasicTypeEnum<'ctx>,
         param: &Param,
         body: &CExpr,
-    ) -> FunctionValue {
-        let fn_name = ctx.name.unwrap_or("lambda");
+    ) -> FunctionValue<'ctx> {
+        let fn_name = code.map(resolve).or(ctx.name).unwrap_or("lambda");
         let fn_ty = body
             .ty()
             .llvm_type(self)
@@ -386,26 +587,38 @@ impl<'ctx> Compiler<'ctx> {
         result_ty: &Type,
         func: &CExpr,
         arg: &CExpr,
+        tail: bool,
     ) -> BasicValueEnum {
         let closure = self.compile_expr(ctx, func);
         let closure_alloca = self.builder.build_alloca(self.closure_ty(), "closure");
         self.builder.build_store(closure_alloca, closure);
-        let fn_gep = self
-            .builder
-            .build_struct_gep(closure_alloca, 0, "closure.fn")
-            .unwrap();
-        let fn_val = self.builder.build_load(fn_gep, "closure.fn");
-        let fn_val = self
-            .builder
-            .build_bitcast(
-                fn_val,
-                result_ty
-                    .llvm_type(self)
-                    .fn_type(&[self.void_ptr_ty(), arg.ty().llvm_type(self)], false)
-                    .ptr_type(AddressSpace::Generic),
-                "closure.fn",
-            )
-            .into_pointer_value();
+
+        // a closure bound by a `let` is known to call the function generated
+        // for it, so there is no need to go through its code pointer
+        let known_fn = match func {
+            CExpr::Var { name, .. } | CExpr::EnvRef { name, .. } => ctx.fns.get(name).copied(),
+            _ => None,
+        };
+        let fn_val = match known_fn {
+            Some(fn_val) => fn_val.as_global_value().as_pointer_value(),
+            None => {
+                let fn_gep = self
+                    .builder
+                    .build_struct_gep(closure_alloca, 0, "closure.fn")
+                    .unwrap();
+                let fn_val = self.builder.build_load(fn_gep, "closure.fn");
+                self.builder
+                    .build_bitcast(
+                        fn_val,
+                        result_ty
+                            .llvm_type(self)
+                            .fn_type(&[self.void_ptr_ty(), arg.ty().llvm_type(self)], false)
+                            .ptr_type(AddressSpace::Generic),
+                        "closure.fn",
+                    )
+                    .into_pointer_value()
+            }
+        };
 
         let env_gep = self
             .builder
@@ -414,9 +627,9 @@ impl<'ctx> Compiler<'ctx> {
         let env_val = self.builder.build_load(env_gep, "closure.env");
         let arg_val = self.compile_expr(ctx, arg);
 
-        self.builder
-            .build_call(fn_val, &[env_val, arg_val], "call")
-            .try_as_basic_value()
+        let call = self.builder.build_call(fn_val, &[env_val, arg_val], "call");
+        call.set_tail_call(tail);
+        call.try_as_basic_value()
             .left()
             .unwrap()
     }
@@ -1,5 +1,137 @@
+use self::{
+    closure::convert, dedup::dedup_closures, escape::mark_stack_envs, llvm::Compiler,
+    prelude::{link_prelude, specialize_builtins},
+    rename::rename,
+    tail::mark_tail_calls,
+};
+use crate::{
+    hir::{
+        visit::{walk_visit, ExprVisitor},
+        Expr, Type,
+    },
+    opt::eliminate_dead_lets,
+};
+use derive_more::Display;
+use inkwell::{context::Context, module::Module};
+
 mod closure;
+mod dedup;
+mod escape;
+pub mod lift;
 mod llvm;
+mod pp;
+pub(crate) mod prelude;
+pub mod rename;
+mod tail;
+pub mod wasm;
 
 #[cfg(test)]
 mod test;
+
+#[derive(Debug, Clone, PartialEq, Eq, Display)]
+pub enum CodegenError {
+    /// Inference left a type variable in the program, eg in `\x -> x`, so
+    /// there is no way to know how to represent its values
+    #[display(fmt = "Ambiguous type: {}", _0)]
+    AmbiguousType(Type),
+    /// A feature of the language that `wasm::compile_wat` cannot compile yet,
+    /// eg closures
+    #[display(fmt = "Unsupported by the wasm backend: {}", _0)]
+    WasmUnsupported(String),
+    /// A type containing a tuple, eg from an annotation, which the LLVM
+    /// backend cannot represent yet
+    #[display(fmt = "Tuples are not supported: {}", _0)]
+    UnsupportedTuple(Type),
+    /// The LLVM backend cannot compile `letrec`s yet
+    #[display(fmt = "`letrec` is not supported by the LLVM backend")]
+    UnsupportedLetrec,
+}
+
+impl std::error::Error for CodegenError {}
+
+/// Run the backend passes on a typed expression (eg from `infer_and_apply`),
+/// and compile it to a module named `name`, containing a `toplevel` function
+/// that evaluates it. Builtins such as `add` are defined by the prelude, and
+/// polymorphic ones such as `id` are specialized to each type they are used at.
+/// Dead `let`s are removed before closure conversion, so that closures do not
+/// capture variables that are only used by them. Closures that do not escape
+/// have their envs allocated on the stack.
+/// Fails if any type in `expr` is not monomorphic, or contains a tuple, or if
+/// `expr` contains a `letrec`
+pub fn compile<'ctx>(
+    llvm: &'ctx Context,
+    name: &str,
+    expr: Expr,
+) -> Result<Module<'ctx>, CodegenError> {
+    check_monomorphic(&expr)?;
+    if let Some(ty) = expr.fold_types(None, |found, ty| {
+        found.or_else(|| if has_tuple(ty) { Some(ty.clone()) } else { None })
+    }) {
+        return Err(CodegenError::UnsupportedTuple(ty));
+    }
+    if contains_letrec(&expr) {
+        return Err(CodegenError::UnsupportedLetrec);
+    }
+
+    let expr = eliminate_dead_lets(link_prelude(specialize_builtins(expr)));
+    let cexpr = dedup_closures(mark_stack_envs(mark_tail_calls(convert(rename(expr)))));
+    let compiler = Compiler {
+        llvm,
+        module: llvm.create_module(name),
+        builder: llvm.create_builder(),
+    };
+    compiler.compile_toplevel(&cexpr);
+    Ok(compiler.module)
+}
+
+/// Like `compile`, but also adds a `main` function that prints the value of
+/// `toplevel`, so that the module can be linked into an executable
+pub fn compile_program<'ctx>(
+    llvm: &'ctx Context,
+    name: &str,
+    expr: Expr,
+) -> Result<Module<'ctx>, CodegenError> {
+    let ty = expr.ty();
+    let compiler = Compiler {
+        llvm,
+        module: compile(llvm, name, expr)?,
+        builder: llvm.create_builder(),
+    };
+    compiler.compile_main(&ty);
+    Ok(compiler.module)
+}
+
+/// Fail with the first type in `expr` that is not monomorphic
+fn check_monomorphic(expr: &Expr) -> Result<(), CodegenError> {
+    match expr.fold_types(None, |found, ty| {
+        found.or_else(|| if ty.is_monomorphic() { None } else { Some(ty.clone()) })
+    }) {
+        Some(ty) => Err(CodegenError::AmbiguousType(ty)),
+        None => Ok(()),
+    }
+}
+
+fn has_tuple(ty: &Type) -> bool {
+    match ty {
+        Type::Tuple(_) => true,
+        Type::Fn(arg, ret) => has_tuple(arg) || has_tuple(ret),
+        Type::Unit | Type::Int | Type::Bool | Type::Float | Type::Var(_) => false,
+    }
+}
+
+fn contains_letrec(expr: &Expr) -> bool {
+    struct ContainsLetrec(bool);
+
+    impl ExprVisitor for ContainsLetrec {
+        fn visit_expr(&mut self, expr: &Expr) {
+            match expr {
+                Expr::Letrec { .. } => self.0 = true,
+                _ => walk_visit(self, expr),
+            }
+        }
+    }
+
+    let mut visitor = ContainsLetrec(false);
+    visitor.visit_expr(expr);
+    visitor.0
+}
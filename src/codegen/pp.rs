@@ -0,0 +1,127 @@
+use crate::{
+    codegen::closure::{CExpr, LetBinding},
+    hir::PrettyConfig,
+};
+use pretty::RcDoc;
+
+impl CExpr {
+    /// Every expression and binder is annotated with its type, and every
+    /// closure with the types of the free variables it captures.
+    /// References to the captured variables are printed as `env.name`
+    pub fn to_doc_typed(&self) -> RcDoc<()> {
+        self.to_doc_typed_with(&PrettyConfig::default())
+    }
+
+    pub fn to_doc_typed_with(&self, cfg: &PrettyConfig) -> RcDoc<()> {
+        let doc = match self {
+            Self::Lit { val, .. } => RcDoc::as_string(val),
+            Self::Var { name, .. } => RcDoc::as_string(name),
+            Self::EnvRef { name, .. } => RcDoc::text(format!("env.{}", name)),
+            Self::Binop { lhs, rhs, op, .. } if op.is_builtin() => RcDoc::text(op.symbol())
+                .append(RcDoc::space())
+                .append(lhs.to_doc_typed_with(cfg))
+                .append(RcDoc::space())
+                .append(rhs.to_doc_typed_with(cfg)),
+            Self::Binop { lhs, rhs, op, .. } => lhs
+                .to_doc_typed_with(cfg)
+                .append(RcDoc::text(format!(" {} ", op.symbol())))
+                .append(rhs.to_doc_typed_with(cfg)),
+            Self::Unop { op, operand, .. } if op.is_builtin() => RcDoc::text(op.symbol())
+                .append(RcDoc::space())
+                .append(operand.to_doc_typed_with(cfg)),
+            Self::Unop { op, operand, .. } => {
+                RcDoc::text(op.symbol()).append(operand.to_doc_typed_with(cfg))
+            }
+            Self::If {
+                test, then, els, ..
+            } => RcDoc::text("if ")
+                .append(test.to_doc_typed_with(cfg))
+                .append(
+                    RcDoc::line()
+                        .append(RcDoc::text("then "))
+                        .append(then.to_doc_typed_with(cfg))
+                        .append(RcDoc::line())
+                        .append(RcDoc::text("else "))
+                        .append(els.to_doc_typed_with(cfg))
+                        .nest(cfg.indent),
+                )
+                .group(),
+            Self::Let { binding, body, .. } => RcDoc::text("let ")
+                .append(binding.to_doc_typed_with(cfg))
+                .append(RcDoc::text(" in"))
+                .append(RcDoc::line().append(body.to_doc_typed_with(cfg)).nest(cfg.indent))
+                .group(),
+            Self::Letrec { bindings, body, .. } => RcDoc::text("letrec ")
+                .append(
+                    RcDoc::intersperse(
+                        bindings.iter().map(|binding| binding.to_doc_typed_with(cfg)),
+                        RcDoc::text(",").append(RcDoc::hardline()),
+                    )
+                    .nest(7),
+                )
+                .append(RcDoc::line())
+                .append(RcDoc::text("in"))
+                .append(RcDoc::line().append(body.to_doc_typed_with(cfg)).nest(cfg.indent))
+                .group(),
+            Self::MkClosure {
+                param,
+                free_vars,
+                body,
+                ..
+            } => RcDoc::text("\\")
+                .append(RcDoc::as_string(param.name))
+                .append(RcDoc::text(format!(": {} [", param.ty)))
+                .append(RcDoc::intersperse(
+                    free_vars
+                        .iter()
+                        .map(|(name, ty)| RcDoc::text(format!("{}: {}", name, ty))),
+                    RcDoc::text(", "),
+                ))
+                .append(RcDoc::text("] -> "))
+                .append(body.to_doc_typed_with(cfg)),
+            Self::App { func, arg, .. } => func
+                .to_doc_typed_with(cfg)
+                .append(RcDoc::space())
+                .append(arg.to_doc_typed_with(cfg)),
+        };
+
+        RcDoc::text("(")
+            .append(doc)
+            .append(RcDoc::text(format!(": {})", self.ty())))
+    }
+
+    pub fn pretty_typed(&self) -> String {
+        self.pretty_typed_with(&PrettyConfig::default())
+    }
+
+    pub fn pretty_typed_with(&self, cfg: &PrettyConfig) -> String {
+        let mut w = Vec::new();
+        self.to_doc_typed_with(cfg).render(cfg.width, &mut w).unwrap();
+        String::from_utf8(w).unwrap()
+    }
+}
+
+impl LetBinding {
+    fn to_doc_typed_with(&self, cfg: &PrettyConfig) -> RcDoc<()> {
+        RcDoc::as_string(self.name)
+            .append(RcDoc::text(format!(": {} = ", self.ty)))
+            .append(self.val.to_doc_typed_with(cfg))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{codegen::closure::convert, types::parse_and_type};
+    use insta::assert_snapshot;
+
+    #[track_caller]
+    fn test_pp_typed(src: &str) {
+        let cexpr = convert(parse_and_type(src).unwrap());
+        assert_snapshot!(cexpr.pretty_typed())
+    }
+
+    #[test]
+    fn pp_closure_typed() {
+        test_pp_typed(r"let x = 5 in \ignored -> x");
+    }
+}
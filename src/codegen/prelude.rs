@@ -0,0 +1,234 @@
+use crate::{
+    hir::{
+        visit::{free_vars, walk_fold, ExprFolder},
+        Binop, Expr, LetBinding, Param, Symbol, Type, Unop,
+    },
+    types::{parse_and_type, unify, Constraint},
+};
+use simple_symbol::intern;
+
+/// Definitions of the monomorphic builtins in `TypeEnv::default()`
+const PRELUDE: &[(&str, &str)] = &[
+    ("add", r"\x: Int, y: Int -> x + y"),
+    ("sub", r"\x: Int, y: Int -> x - y"),
+    ("mul", r"\x: Int, y: Int -> x * y"),
+    ("is_zero", r"\x: Int -> x == 0"),
+    ("not", r"\b: Bool -> !b"),
+];
+
+/// Definitions of the polymorphic builtins in `TypeEnv::default()`. They are
+/// not linked by `link_prelude`, since each use may need a different type, see
+/// `specialize_builtins`
+pub(crate) const POLYMORPHIC_PRELUDE: &[(&str, &str)] = &[
+    ("if_then_else", r"\b, x, y -> if b then x else y"),
+    ("id", r"\x -> x"),
+    ("const", r"\x, y -> x"),
+    ("compose", r"\f, g, x -> f (g x)"),
+];
+
+/// Builtins that cannot be written in SiMPL, and are instead defined by an
+/// operator that the backend compiles to an LLVM intrinsic
+const INTRINSICS: &[(&str, Intrinsic)] = &[
+    ("sqrt", Intrinsic::Unop(Unop::FloatSqrt)),
+    ("abs", Intrinsic::Unop(Unop::FloatAbs)),
+    ("floor", Intrinsic::Unop(Unop::FloatFloor)),
+    ("pow", Intrinsic::Binop(Binop::FloatPow)),
+];
+
+#[derive(Debug, Copy, Clone)]
+enum Intrinsic {
+    Unop(Unop),
+    Binop(Binop),
+}
+
+impl Intrinsic {
+    /// `\x: Float -> op x` or `\x: Float, y: Float -> op x y`
+    fn definition(self) -> Expr {
+        let var = |name| Expr::Var {
+            ty: Type::Float,
+            name: intern(name),
+        };
+        let lambda = |param, body: Expr| Expr::Lambda {
+            ty: Type::Fn(box Type::Float, box body.ty()),
+            param: Param {
+                ty: Type::Float,
+                name: intern(param),
+                ann: Some(Type::Float),
+            },
+            body: box body,
+        };
+
+        match self {
+            Self::Unop(op) => lambda(
+                "x",
+                Expr::Unop {
+                    ty: Type::Float,
+                    op,
+                    operand: box var("x"),
+                },
+            ),
+            Self::Binop(op) => lambda(
+                "x",
+                lambda(
+                    "y",
+                    Expr::Binop {
+                        ty: Type::Float,
+                        lhs: box var("x"),
+                        rhs: box var("y"),
+                        op,
+                    },
+                ),
+            ),
+        }
+    }
+}
+
+/// The names of the builtins, in the order they are bound
+fn builtins() -> impl DoubleEndedIterator<Item = &'static str> {
+    let prelude = PRELUDE.iter().map(|(name, _)| *name);
+    prelude.chain(INTRINSICS.iter().map(|(name, _)| *name))
+}
+
+/// The typed definition of the builtin `name`
+fn definition(name: &str) -> Expr {
+    if let Some((_, src)) = PRELUDE.iter().find(|(builtin, _)| *builtin == name) {
+        return parse_and_type(src).expect("the prelude is well typed");
+    }
+    let (_, intrinsic) = INTRINSICS
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .expect("no such builtin");
+    intrinsic.definition()
+}
+
+/// Bind the builtins that are free in `expr` around it, so that the backend
+/// only sees closed programs. `expr` should already be typed
+pub fn link_prelude(expr: Expr) -> Expr {
+    let fv = free_vars(&expr);
+    let mut expr = expr;
+
+    for builtin in builtins().rev() {
+        let name = intern(builtin);
+        if !fv.contains_key(&name) {
+            continue;
+        }
+
+        let val = definition(builtin);
+        expr = Expr::Let {
+            ty: expr.ty(),
+            binding: LetBinding {
+                ty: val.ty(),
+                name,
+                ann: None,
+                val: box val,
+            },
+            body: box expr,
+        };
+    }
+
+    expr
+}
+
+/// Replace every free use of a polymorphic builtin (eg `id`) in `expr` with
+/// its definition, specialized to the type it is used at, so that the backend
+/// only sees monomorphic code. `expr` should already be typed and monomorphic
+pub fn specialize_builtins(expr: Expr) -> Expr {
+    let fv = free_vars(&expr);
+    POLYMORPHIC_PRELUDE
+        .iter()
+        .filter(|(name, _)| fv.contains_key(&intern(*name)))
+        .fold(expr, |expr, (name, src)| specialize_uses(expr, intern(*name), src))
+}
+
+/// Replace the free uses of the builtin `name` in `expr` with `src`
+fn specialize_uses(expr: Expr, name: Symbol, src: &'static str) -> Expr {
+    struct Specialize {
+        name: Symbol,
+        src: &'static str,
+    }
+
+    impl ExprFolder for Specialize {
+        fn fold_expr(&mut self, expr: Expr) -> Expr {
+            match expr {
+                Expr::Var { ty, name } if name == self.name => specialize(self.src, &ty),
+                Expr::Let { ty, binding, body } if binding.name == self.name => Expr::Let {
+                    ty,
+                    binding: LetBinding {
+                        val: box self.fold_expr(*binding.val),
+                        ..binding
+                    },
+                    body,
+                },
+                Expr::Letrec { ref bindings, .. }
+                    if bindings.iter().any(|binding| binding.name == self.name) =>
+                {
+                    expr
+                }
+                Expr::Lambda { ref param, .. } if param.name == self.name => expr,
+                _ => walk_fold(self, expr),
+            }
+        }
+    }
+
+    Specialize { name, src }.fold_expr(expr)
+}
+
+/// The typed definition `src`, with its type variables solved so that it has
+/// type `ty`
+fn specialize(src: &str, ty: &Type) -> Expr {
+    let def = parse_and_type(src).expect("the prelude is well typed");
+    let subst = unify(&[Constraint(def.ty(), ty.clone())])
+        .expect("builtins are only used at instances of their type");
+    def.apply(&subst)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::{ty::TypeEnv, type_of_in};
+
+    #[test]
+    fn prelude_matches_type_env() {
+        let tenv = TypeEnv::default();
+        for name in builtins() {
+            let def = definition(name);
+            assert_eq!(tenv.get(intern(name)).unwrap(), &def.ty().into(), "{}", name);
+            assert_eq!(type_of_in(&def, &TypeEnv::empty()), Ok(def.ty()), "{}", name);
+        }
+    }
+
+    #[test]
+    fn polymorphic_prelude_matches_type_env() {
+        let tenv = TypeEnv::default();
+        for (name, src) in POLYMORPHIC_PRELUDE {
+            let def = parse_and_type(src).unwrap();
+            let expected = tenv.get(intern(*name)).unwrap().ty.normalize_vars();
+            assert_eq!(def.ty().normalize_vars(), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn specialize_polymorphic_builtins() {
+        let expr = specialize_builtins(parse_and_type("compose id id 1 == id 1").unwrap());
+        assert!(free_vars(&expr).is_empty());
+        assert!(expr.fold_types(true, |ok, ty| ok && ty.is_monomorphic()));
+        assert_eq!(type_of_in(&expr, &TypeEnv::empty()), Ok(Type::Bool));
+
+        // a local binding shadows the builtin
+        let src = r"let id = \x -> x + 1 in id 1";
+        let expr = parse_and_type(src).unwrap();
+        assert_eq!(specialize_builtins(expr.clone()), expr);
+    }
+
+    #[test]
+    fn link_used_builtins() {
+        let expr = link_prelude(parse_and_type("not (is_zero (add 1 2))").unwrap());
+        let names: Vec<_> = free_vars(&expr).keys().copied().collect();
+        assert!(names.is_empty(), "{:?}", names);
+        assert_eq!(type_of_in(&expr, &TypeEnv::empty()), Ok(Type::Bool));
+
+        // only the builtins that are used are linked
+        let expr = link_prelude(parse_and_type("is_zero 1").unwrap());
+        assert_eq!(expr.size(), parse_and_type("is_zero 1").unwrap().size() + 5);
+    }
+}
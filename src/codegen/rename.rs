@@ -0,0 +1,439 @@
+use crate::hir::{
+    visit::{walk_fold, ExprFolder},
+    Expr, LetBinding, Param, Symbol,
+};
+use simple_symbol::intern;
+use std::collections::HashMap;
+
+/// Give every binder in `expr` a distinct name, so that later passes don't
+/// have to worry about shadowing. Should be run after type inference, and
+/// before closure conversion.
+/// The first binder of each name keeps it, later ones get a suffix (eg `x.1`)
+/// that cannot clash with a name in the source
+pub fn rename(expr: Expr) -> Expr {
+    Renamer::default().fold_expr(expr)
+}
+
+#[derive(Default)]
+struct Renamer {
+    /// How many binders of each name have been seen so far
+    seen: HashMap<Symbol, usize>,
+    /// The new names of the variables in scope, innermost last
+    scope: Vec<(Symbol, Symbol)>,
+}
+
+impl Renamer {
+    fn fresh(&mut self, name: Symbol) -> Symbol {
+        let count = self.seen.entry(name).or_insert(0);
+        let new_name = if *count == 0 {
+            name
+        } else {
+            intern(format!("{}.{}", name, count))
+        };
+        *count += 1;
+        new_name
+    }
+
+    fn lookup(&self, name: Symbol) -> Symbol {
+        self.scope
+            .iter()
+            .rev()
+            .find(|(old_name, _)| *old_name == name)
+            .map_or(name, |(_, new_name)| *new_name)
+    }
+}
+
+impl ExprFolder for Renamer {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Var { ty, name } => Expr::Var {
+                ty,
+                name: self.lookup(name),
+            },
+            Expr::Let { ty, binding, body } => {
+                let val = self.fold_expr(*binding.val);
+                let name = self.fresh(binding.name);
+                self.scope.push((binding.name, name));
+                let body = self.fold_expr(*body);
+                self.scope.pop();
+                Expr::Let {
+                    ty,
+                    binding: LetBinding {
+                        name,
+                        val: box val,
+                        ..binding
+                    },
+                    body: box body,
+                }
+            }
+            Expr::Letrec { ty, bindings, body } => {
+                let len = self.scope.len();
+                let names: Vec<_> = bindings
+                    .iter()
+                    .map(|binding| self.fresh(binding.name))
+                    .collect();
+                self.scope.extend(
+                    bindings
+                        .iter()
+                        .zip(&names)
+                        .map(|(binding, name)| (binding.name, *name)),
+                );
+                let bindings = bindings
+                    .into_iter()
+                    .zip(names)
+                    .map(|(binding, name)| LetBinding {
+                        name,
+                        val: box self.fold_expr(*binding.val),
+                        ..binding
+                    })
+                    .collect();
+                let body = self.fold_expr(*body);
+                self.scope.truncate(len);
+                Expr::Letrec {
+                    ty,
+                    bindings,
+                    body: box body,
+                }
+            }
+            Expr::Lambda { ty, param, body } => {
+                let name = self.fresh(param.name);
+                self.scope.push((param.name, name));
+                let body = self.fold_expr(*body);
+                self.scope.pop();
+                Expr::Lambda {
+                    ty,
+                    param: Param { name, ..param },
+                    body: box body,
+                }
+            }
+            _ => walk_fold(self, expr),
+        }
+    }
+}
+
+/// Whether `lhs` and `rhs` are the same up to consistent renaming of bound
+/// variables. Types are ignored, annotations are not
+pub fn is_alpha_eq(lhs: &Expr, rhs: &Expr) -> bool {
+    AlphaEq::default().eq(lhs, rhs)
+}
+
+/// Each bound name is mapped to the depth of its binder, so that two
+/// variables are equal if they were bound at the same depth
+#[derive(Default)]
+struct AlphaEq {
+    lhs: HashMap<Symbol, usize>,
+    rhs: HashMap<Symbol, usize>,
+    depth: usize,
+}
+
+impl AlphaEq {
+    /// Compare `lhs` and `rhs` with the names in `binders` bound
+    fn eq_in_scope(&mut self, binders: &[(Symbol, Symbol)], lhs: &Expr, rhs: &Expr) -> bool {
+        let old_depth = self.depth;
+        let mut shadowed = Vec::new();
+        for (name1, name2) in binders {
+            shadowed.push((
+                self.lhs.insert(*name1, self.depth),
+                self.rhs.insert(*name2, self.depth),
+            ));
+            self.depth += 1;
+        }
+
+        let eq = self.eq(lhs, rhs);
+
+        for ((name1, name2), (old1, old2)) in binders.iter().zip(shadowed).rev() {
+            restore(&mut self.lhs, *name1, old1);
+            restore(&mut self.rhs, *name2, old2);
+        }
+        self.depth = old_depth;
+        eq
+    }
+
+    fn eq_bindings(&mut self, lhs: &LetBinding, rhs: &LetBinding) -> bool {
+        lhs.ann == rhs.ann && self.eq(&lhs.val, &rhs.val)
+    }
+
+    fn eq(&mut self, lhs: &Expr, rhs: &Expr) -> bool {
+        match (lhs, rhs) {
+            (Expr::Lit { val: val1, .. }, Expr::Lit { val: val2, .. }) => val1 == val2,
+            (Expr::Var { name: name1, .. }, Expr::Var { name: name2, .. }) => {
+                match (self.lhs.get(name1), self.rhs.get(name2)) {
+                    (Some(depth1), Some(depth2)) => depth1 == depth2,
+                    (None, None) => name1 == name2,
+                    _ => false,
+                }
+            }
+            (
+                Expr::Binop {
+                    lhs: lhs1,
+                    rhs: rhs1,
+                    op: op1,
+                    ..
+                },
+                Expr::Binop {
+                    lhs: lhs2,
+                    rhs: rhs2,
+                    op: op2,
+                    ..
+                },
+            ) => op1 == op2 && self.eq(lhs1, lhs2) && self.eq(rhs1, rhs2),
+            (
+                Expr::Unop {
+                    op: op1,
+                    operand: operand1,
+                    ..
+                },
+                Expr::Unop {
+                    op: op2,
+                    operand: operand2,
+                    ..
+                },
+            ) => op1 == op2 && self.eq(operand1, operand2),
+            (
+                Expr::If {
+                    test: test1,
+                    then: then1,
+                    els: els1,
+                    ..
+                },
+                Expr::If {
+                    test: test2,
+                    then: then2,
+                    els: els2,
+                    ..
+                },
+            ) => self.eq(test1, test2) && self.eq(then1, then2) && self.eq(els1, els2),
+            (
+                Expr::Let {
+                    binding: binding1,
+                    body: body1,
+                    ..
+                },
+                Expr::Let {
+                    binding: binding2,
+                    body: body2,
+                    ..
+                },
+            ) => {
+                self.eq_bindings(binding1, binding2)
+                    && self.eq_in_scope(&[(binding1.name, binding2.name)], body1, body2)
+            }
+            (
+                Expr::Letrec {
+                    bindings: bindings1,
+                    body: body1,
+                    ..
+                },
+                Expr::Letrec {
+                    bindings: bindings2,
+                    body: body2,
+                    ..
+                },
+            ) => {
+                if bindings1.len() != bindings2.len() {
+                    return false;
+                }
+                let binders: Vec<_> = bindings1
+                    .iter()
+                    .zip(bindings2)
+                    .map(|(binding1, binding2)| (binding1.name, binding2.name))
+                    .collect();
+
+                // every binding is in scope for every binding, so compare each
+                // value with the whole group bound
+                bindings1.iter().zip(bindings2).all(|(binding1, binding2)| {
+                    binding1.ann == binding2.ann
+                        && self.eq_in_scope(&binders, &binding1.val, &binding2.val)
+                }) && self.eq_in_scope(&binders, body1, body2)
+            }
+            (
+                Expr::Lambda {
+                    param: param1,
+                    body: body1,
+                    ..
+                },
+                Expr::Lambda {
+                    param: param2,
+                    body: body2,
+                    ..
+                },
+            ) => {
+                param1.ann == param2.ann
+                    && self.eq_in_scope(&[(param1.name, param2.name)], body1, body2)
+            }
+            (
+                Expr::App {
+                    func: func1,
+                    arg: arg1,
+                    ..
+                },
+                Expr::App {
+                    func: func2,
+                    arg: arg2,
+                    ..
+                },
+            ) => self.eq(func1, func2) && self.eq(arg1, arg2),
+            _ => false,
+        }
+    }
+}
+
+fn restore(map: &mut HashMap<Symbol, usize>, name: Symbol, old: Option<usize>) {
+    match old {
+        Some(depth) => map.insert(name, depth),
+        None => map.remove(&name),
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hir::visit::{walk_visit, ExprVisitor};
+    use std::{collections::HashSet, str::FromStr};
+
+    #[derive(Default)]
+    struct Binders(Vec<Symbol>);
+    impl ExprVisitor for Binders {
+        fn visit_expr(&mut self, expr: &Expr) {
+            match expr {
+                Expr::Let { binding, .. } => self.0.push(binding.name),
+                Expr::Letrec { bindings, .. } => {
+                    self.0.extend(bindings.iter().map(|binding| binding.name));
+                }
+                Expr::Lambda { param, .. } => self.0.push(param.name),
+                _ => {}
+            }
+            walk_visit(self, expr)
+        }
+    }
+
+    #[track_caller]
+    fn test_rename(src: &str) {
+        let expr = Expr::from_str(src).unwrap();
+        let renamed = rename(expr.clone());
+        assert!(is_alpha_eq(&expr, &renamed));
+
+        let mut binders = Binders::default();
+        binders.visit_expr(&renamed);
+        let names: HashSet<_> = binders.0.iter().collect();
+        assert_eq!(names.len(), binders.0.len(), "{:?}", binders.0);
+    }
+
+    #[test]
+    fn rename_shadowing() {
+        test_rename("let x = 5 in let x = 10 in x");
+        test_rename("let x = 5 in let x = x + 1 in x");
+        test_rename(r"\x -> \x -> x");
+        test_rename(r"let f = \x -> x, x = 1 in f x");
+        test_rename(r"letrec f = \x -> g x, g = \x -> f x in let f = 1 in f");
+        // the same name bound many times, as by a normalization pass that
+        // names every temporary `tmp`
+        test_rename("let tmp = mul 2 4 in let tmp = mul 2 8 in let tmp = add tmp tmp in tmp");
+
+        let renamed = rename(Expr::from_str("let x = 5 in let x = 10 in x").unwrap());
+        match renamed {
+            Expr::Let {
+                binding,
+                body:
+                    box Expr::Let {
+                        binding: inner,
+                        body: box Expr::Var { name, .. },
+                        ..
+                    },
+                ..
+            } => {
+                assert_eq!(binding.name, intern("x"));
+                assert_eq!(inner.name, intern("x.1"));
+                assert_eq!(name, intern("x.1"));
+            }
+            expr => panic!("Unexpected expr: {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn rename_letrec() {
+        // the outer `f` and `g` keep their names, so the `letrec` ones are
+        // renamed, and must refer to each other by their new names
+        let renamed = rename(
+            Expr::from_str(r"let f = 1, g = 2 in letrec f = \x -> g x, g = \y -> f y in f")
+                .unwrap(),
+        );
+        let (bindings, body) = match renamed {
+            Expr::Let {
+                body:
+                    box Expr::Let {
+                        body: box Expr::Letrec { bindings, body, .. },
+                        ..
+                    },
+                ..
+            } => (bindings, body),
+            expr => panic!("Unexpected expr: {:?}", expr),
+        };
+        let (f, g) = (intern("f.1"), intern("g.1"));
+        assert_eq!(bindings[0].name, f);
+        assert_eq!(bindings[1].name, g);
+
+        let callee = |binding: &LetBinding| match &*binding.val {
+            Expr::Lambda {
+                body: box Expr::App {
+                    func: box Expr::Var { name, .. },
+                    ..
+                },
+                ..
+            } => *name,
+            expr => panic!("Unexpected expr: {:?}", expr),
+        };
+        assert_eq!(callee(&bindings[0]), g);
+        assert_eq!(callee(&bindings[1]), f);
+        assert!(matches!(*body, Expr::Var { name, .. } if name == f));
+    }
+
+    #[track_caller]
+    fn alpha_eq(lhs: &str, rhs: &str) -> bool {
+        is_alpha_eq(&Expr::from_str(lhs).unwrap(), &Expr::from_str(rhs).unwrap())
+    }
+
+    #[test]
+    fn alpha_eq_lambda() {
+        assert!(alpha_eq(r"\x -> x", r"\y -> y"));
+        assert!(!alpha_eq(r"\x -> x", r"\x -> y"));
+        assert!(alpha_eq(r"\x, y -> x", r"\a, b -> a"));
+        assert!(!alpha_eq(r"\x, y -> x", r"\a, b -> b"));
+        assert!(!alpha_eq(r"\x: Int -> x", r"\y -> y"));
+    }
+
+    #[test]
+    fn alpha_eq_let() {
+        assert!(alpha_eq("let x = 1 in x", "let y = 1 in y"));
+        assert!(!alpha_eq("let x = 1 in x", "let y = 2 in y"));
+        // the binding is not in scope in its own value
+        assert!(!alpha_eq("let x = x in x", "let y = y in y"));
+        assert!(alpha_eq(
+            "let x = 1 in let x = x in x",
+            "let a = 1 in let b = a in b"
+        ));
+        assert!(!alpha_eq(
+            "let x = 1 in let x = 2 in x",
+            "let a = 1 in let b = 2 in a"
+        ));
+    }
+
+    #[test]
+    fn alpha_eq_letrec() {
+        assert!(alpha_eq(
+            r"letrec f = \x -> g x, g = \y -> f y in f 0",
+            r"letrec a = \x -> b x, b = \y -> a y in a 0",
+        ));
+        assert!(!alpha_eq(
+            r"letrec f = \x -> g x, g = \y -> f y in f 0",
+            r"letrec a = \x -> a x, b = \y -> a y in a 0",
+        ));
+    }
+
+    #[test]
+    fn alpha_eq_free_vars() {
+        assert!(alpha_eq("add 1 2", "add 1 2"));
+        assert!(!alpha_eq("add 1 2", "sub 1 2"));
+        assert!(!alpha_eq(r"\x -> add", r"\add -> add"));
+    }
+}
@@ -0,0 +1,154 @@
+use super::closure::{CExpr, LetBinding};
+
+/// Mark every `App` in tail position, so that the backend can emit it as a
+/// tail call.
+/// `expr` is treated as the body of a function (`toplevel`)
+pub fn mark_tail_calls(expr: CExpr) -> CExpr {
+    mark(expr, true)
+}
+
+fn mark(expr: CExpr, tail: bool) -> CExpr {
+    match expr {
+        CExpr::Lit { .. } | CExpr::Var { .. } | CExpr::EnvRef { .. } => expr,
+        CExpr::Binop { ty, lhs, rhs, op } => CExpr::Binop {
+            ty,
+            lhs: box mark(*lhs, false),
+            rhs: box mark(*rhs, false),
+            op,
+        },
+        CExpr::Unop { ty, op, operand } => CExpr::Unop {
+            ty,
+            op,
+            operand: box mark(*operand, false),
+        },
+        CExpr::If {
+            ty,
+            test,
+            then,
+            els,
+        } => CExpr::If {
+            ty,
+            test: box mark(*test, false),
+            then: box mark(*then, tail),
+            els: box mark(*els, tail),
+        },
+        CExpr::Let { ty, binding, body } => CExpr::Let {
+            ty,
+            binding: mark_binding(binding),
+            body: box mark(*body, tail),
+        },
+        CExpr::Letrec { ty, bindings, body } => CExpr::Letrec {
+            ty,
+            bindings: bindings.into_iter().map(mark_binding).collect(),
+            body: box mark(*body, tail),
+        },
+        CExpr::MkClosure {
+            ty,
+            param,
+            free_vars,
+            body,
+            code,
+            stack_env,
+        } => CExpr::MkClosure {
+            ty,
+            param,
+            free_vars,
+            body: box mark(*body, true),
+            code,
+            stack_env,
+        },
+        CExpr::App { ty, func, arg, .. } => CExpr::App {
+            ty,
+            func: box mark(*func, false),
+            arg: box mark(*arg, false),
+            tail,
+        },
+    }
+}
+
+fn mark_binding(binding: LetBinding) -> LetBinding {
+    LetBinding {
+        val: box mark(*binding.val, false),
+        ..binding
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{codegen::closure::convert, types::parse_and_type};
+    use simple_symbol::{intern, Symbol};
+
+    /// The name of the function and the tail flag of every `App` whose function
+    /// is a variable, in evaluation order
+    fn tail_calls(expr: &CExpr, calls: &mut Vec<(Symbol, bool)>) {
+        match expr {
+            CExpr::Lit { .. } | CExpr::Var { .. } | CExpr::EnvRef { .. } => {}
+            CExpr::Binop { lhs, rhs, .. } => {
+                tail_calls(lhs, calls);
+                tail_calls(rhs, calls);
+            }
+            CExpr::Unop { operand, .. } => tail_calls(operand, calls),
+            CExpr::If {
+                test, then, els, ..
+            } => {
+                tail_calls(test, calls);
+                tail_calls(then, calls);
+                tail_calls(els, calls);
+            }
+            CExpr::Let { binding, body, .. } => {
+                tail_calls(&binding.val, calls);
+                tail_calls(body, calls);
+            }
+            CExpr::Letrec { bindings, body, .. } => {
+                for binding in bindings {
+                    tail_calls(&binding.val, calls);
+                }
+                tail_calls(body, calls);
+            }
+            CExpr::MkClosure { body, .. } => tail_calls(body, calls),
+            CExpr::App {
+                func, arg, tail, ..
+            } => {
+                if let CExpr::Var { name, .. } | CExpr::EnvRef { name, .. } = &**func {
+                    calls.push((*name, *tail));
+                }
+                tail_calls(func, calls);
+                tail_calls(arg, calls);
+            }
+        }
+    }
+
+    #[track_caller]
+    fn test_tail_calls(src: &str, expected: &[(&str, bool)]) {
+        let cexpr = mark_tail_calls(convert(parse_and_type(src).unwrap()));
+        let mut calls = Vec::new();
+        tail_calls(&cexpr, &mut calls);
+
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(name, tail)| (intern(name), *tail))
+            .collect();
+        assert_eq!(calls, expected);
+    }
+
+    #[test]
+    fn tail_call_recursive() {
+        test_tail_calls(
+            r"letrec loop = \x -> if is_zero x then 0 else loop (x - 1) in loop 1000000",
+            &[("is_zero", false), ("loop", true), ("loop", true)],
+        );
+    }
+
+    #[test]
+    fn tail_call_positions() {
+        test_tail_calls(r"\f -> f 1", &[("f", true)]);
+        test_tail_calls(r"\f -> f 1 + 1", &[("f", false)]);
+        test_tail_calls(r"\f -> f (f 1)", &[("f", true), ("f", false)]);
+        test_tail_calls(r"\f -> let x = f 1 in f x", &[("f", false), ("f", true)]);
+        test_tail_calls(r"\f -> if f true then f false else true", &[
+            ("f", false),
+            ("f", true),
+        ]);
+    }
+}
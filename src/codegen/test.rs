@@ -1,5 +1,10 @@
-use super::{closure::convert, llvm::Compiler};
-use crate::{hir::Expr, types::infer_and_apply};
+use super::{compile, compile_program, CodegenError};
+use crate::{
+    eval::{eval, Value},
+    hir::{Expr, Lit, Type},
+    opt::fold_constants,
+    types::{default_type_vars, infer_and_apply, parse_and_type},
+};
 use inkwell::{context::Context, OptimizationLevel};
 use insta::assert_snapshot;
 use std::str::FromStr;
@@ -7,20 +12,10 @@ use std::str::FromStr;
 #[track_caller]
 fn test_compile(src: &str) {
     let expr = Expr::from_str(src).unwrap();
-    let expr = infer_and_apply(&expr);
-    let cexpr = convert(expr);
+    let expr = infer_and_apply(&expr).unwrap();
 
     let ctx = Context::create();
-    let builder = ctx.create_builder();
-    let module = ctx.create_module("test_compile");
-
-    let compiler = Compiler {
-        llvm: &ctx,
-        module,
-        builder,
-    };
-
-    let module = compiler.compile_toplevel(&cexpr);
+    let module = compile(&ctx, "test_compile", expr).unwrap();
 
     match module.verify() {
         Ok(()) => {}
@@ -37,20 +32,10 @@ fn test_compile(src: &str) {
 #[track_caller]
 fn test_compile_and_execute<T: std::fmt::Debug + PartialEq>(src: &str, expected: T) {
     let expr = Expr::from_str(src).unwrap();
-    let expr = infer_and_apply(&expr);
-    let cexpr = convert(expr);
+    let expr = infer_and_apply(&expr).unwrap();
 
     let ctx = Context::create();
-    let builder = ctx.create_builder();
-    let module = ctx.create_module("test_compile");
-
-    let compiler = Compiler {
-        llvm: &ctx,
-        module,
-        builder,
-    };
-
-    let module = compiler.compile_toplevel(&cexpr);
+    let module = compile(&ctx, "test_compile", expr).unwrap();
 
     match module.verify() {
         Ok(()) => {}
@@ -83,6 +68,9 @@ fn compile_lit() {
 #[test]
 fn compile_vars() {
     test_compile_and_execute("let x = 5 in x", 5);
+    test_compile_and_execute("let x = 5 in let x = 10 in x", 10);
+    test_compile_and_execute("let x = 5 in let x = x + 1 in x", 6);
+    test_compile_and_execute(r"let x = 5, f = \x -> x in f 10", 10);
 }
 
 #[test]
@@ -93,6 +81,16 @@ fn compile_if() {
     test_compile_and_execute(r"let f = \b -> if b then 5 else 10 in f false", 10);
 }
 
+#[test]
+fn compile_if_with_lets_in_branches() {
+    test_compile_and_execute("if true then (let x = 1 in x) else 0", 1);
+    test_compile_and_execute("if false then 0 else let y = 2 in y * y", 4);
+    test_compile_and_execute(
+        "let z = 3 in if z > 2 then let a = z + 1, b = a * 2 in b else let c = z in c",
+        8,
+    );
+}
+
 #[test]
 fn compile_lambda() {
     test_compile(r"let x = 5 in \y: Int -> x");
@@ -148,11 +146,312 @@ in myFn 5
     );
 }
 
+#[test]
+fn compile_stack_envs() {
+    let ir = |src: &str| {
+        let expr = infer_and_apply(&Expr::from_str(src).unwrap()).unwrap();
+        let ctx = Context::create();
+        let module = compile(&ctx, "test_compile", expr).unwrap();
+        module.verify().unwrap();
+        module.print_to_string().to_string()
+    };
+
+    // the closure is only applied, so its env does not outlive `toplevel`
+    let src = r"let x = 10 in let f = \y -> x + y in f 5";
+    assert!(!ir(src).contains("malloc"), "{}", ir(src));
+    test_compile_and_execute(src, 15);
+
+    // the closure is returned, so its env must be on the heap
+    let src = r"let x = 10 in let f = \y -> x + y in f";
+    assert!(ir(src).contains("malloc"), "{}", ir(src));
+}
+
+#[test]
+fn compile_direct_calls() {
+    let src = r"let f = \x -> x + 1 in f 41";
+    let expr = infer_and_apply(&Expr::from_str(src).unwrap()).unwrap();
+    let ctx = Context::create();
+    let module = compile(&ctx, "test_compile", expr).unwrap();
+    module.verify().unwrap();
+
+    // `f` is called directly, not through the code pointer of its closure
+    let ir = module.print_to_string().to_string();
+    assert!(ir.contains("call i64 @f("), "{}", ir);
+    assert!(!ir.contains("closure.fn"), "{}", ir);
+    test_compile_and_execute(src, 42);
+
+    // the function of a closure passed as an argument is not known
+    test_compile_and_execute(r"let apply = \g -> g 1 in apply (\x -> x + 1)", 2);
+}
+
 #[test]
 fn compile_ops() {
+    test_compile_and_execute("1 + 2", 3);
     test_compile_and_execute("1 * 2 + 3 / 4", 2); // (1 * 2) + (3 / 4)
     test_compile_and_execute("1 + 2 * 3 - 4", 3); // 1 + (2 * 3) - 4
     test_compile_and_execute("1 == 1", true);
     test_compile_and_execute("1 != 1", false);
     test_compile_and_execute("let x = 6, y = 7 in x * y", 42);
 }
+
+#[test]
+fn compile_int_div() {
+    // division truncates towards zero
+    test_compile_and_execute("3 / 4", 0);
+    test_compile_and_execute("7 / 2", 3);
+    test_compile_and_execute("-7 / 2", -3);
+    test_compile_and_execute("7 / -2", -3);
+    test_compile_and_execute(r"(\x -> 7 / x) 2", 3);
+}
+
+#[test]
+fn compile_int_div_overflow() {
+    test_compile_and_execute("-9223372036854775807 / -1", i64::MAX);
+    test_compile_and_execute("-9223372036854775808 / 1", i64::MIN);
+    // `i64::MIN / -1` branches to the trap, so it is only compiled here
+    let src = r"(\x, y -> if false then x / y else 0) (-9223372036854775808) (-1)";
+    test_compile_and_execute(src, 0);
+
+    let expr = infer_and_apply(&Expr::from_str(r"\x -> x / -1").unwrap()).unwrap();
+    let ctx = Context::create();
+    let ir = compile(&ctx, "test_compile", expr).unwrap().print_to_string().to_string();
+    assert!(ir.contains("div_trap"), "{}", ir);
+    assert!(ir.contains("is_min"), "{}", ir);
+}
+
+#[test]
+fn compile_bitwise_ops() {
+    test_compile_and_execute("6 & 3", 2);
+    test_compile_and_execute("6 | 3", 7);
+    test_compile_and_execute("6 ^ 3", 5);
+    test_compile_and_execute("1 << 4", 16);
+    test_compile_and_execute("-8 >> 1", -4);
+    test_compile_and_execute("6 & 3 == 2", true);
+    test_compile_and_execute(r"let shr = \x, n -> x >> n in shr (-8) 1", -4);
+}
+
+#[test]
+fn shift_amount_mod_64() {
+    // compiled code, the interpreter and constant folding all agree
+    for &(src, expected) in &[("1 << 65", 2), ("-8 >> 65", -4), ("1 << -1", i64::MIN)] {
+        test_compile_and_execute(src, expected);
+        let expr = parse_and_type(src).unwrap();
+        assert_eq!(eval(&expr), Ok(Value::Int(expected)), "{}", src);
+        assert!(
+            matches!(fold_constants(expr), Expr::Lit { val: Lit::Int(x), .. } if x == expected),
+            "{}",
+            src
+        );
+    }
+    test_compile_and_execute(r"let shl = \x, n -> x << n in shl 1 65", 2);
+}
+
+#[test]
+fn compile_float_ops() {
+    test_compile_and_execute("1.5 *. 2.0", 3.0);
+    test_compile_and_execute("1.0 +. 2.0 /. 4.0", 1.5);
+    test_compile_and_execute("1.0 <. 2.0", true);
+    test_compile_and_execute("1.0 >=. 2.0", false);
+    test_compile_and_execute("2.0 <=. 2.0", true);
+    test_compile_and_execute("1.5 == 1.5", true);
+    test_compile_and_execute("1.5 != 1.5", false);
+    test_compile_and_execute("true != false", true);
+    test_compile_and_execute(r"let lt = \x, y -> x <. y in lt 2.5 1.5", false);
+}
+
+#[test]
+fn compile_unops() {
+    test_compile_and_execute("!(1 == 2)", true);
+    test_compile_and_execute("!true", false);
+    test_compile_and_execute(r"let neg = \x -> -x in neg 5", -5);
+    test_compile_and_execute("-.(1.5 +. 1.0)", -2.5);
+}
+
+#[test]
+fn compile_builtins() {
+    test_compile_and_execute("add 1 2", 3);
+    test_compile_and_execute("mul (sub 5 2) 4", 12);
+    test_compile_and_execute("is_zero 0", true);
+    test_compile_and_execute("not (is_zero 1)", true);
+    test_compile_and_execute(r"let inc = add 1 in inc (inc 1)", 3);
+    // user definitions shadow the prelude
+    test_compile_and_execute(r"let add = \x, y -> x * y in add 2 3", 6);
+}
+
+#[test]
+fn compile_float_nan_comparisons() {
+    let nan = "let nan = 0.0 /. 0.0 in";
+    test_compile_and_execute(&format!("{} nan == nan", nan), false);
+    test_compile_and_execute(&format!("{} nan != nan", nan), true);
+    test_compile_and_execute(&format!("{} nan <. nan", nan), false);
+    test_compile_and_execute(&format!("{} nan >=. 1.0", nan), false);
+    test_compile_and_execute(&format!("{} 1.0 != nan", nan), true);
+}
+
+#[test]
+fn compile_float_builtins() {
+    test_compile_and_execute("sqrt 16.0", 4.0);
+    test_compile_and_execute("abs -2.5", 2.5);
+    test_compile_and_execute("floor 2.5", 2.0);
+    test_compile_and_execute("floor -2.5", -3.0);
+    test_compile_and_execute("pow 2.0 10.0", 1024.0);
+    test_compile_and_execute(r"let hyp = \x, y -> sqrt (x *. x +. y *. y) in hyp 3.0 4.0", 5.0);
+}
+
+#[test]
+fn compile_shared_closures() {
+    let src = r"let f = \x -> x + 1, g = \y -> y + 1 in f (g 1)";
+    test_compile_and_execute(src, 3);
+
+    let expr = infer_and_apply(&Expr::from_str(src).unwrap()).unwrap();
+
+    let ctx = Context::create();
+    let module = compile(&ctx, "test_compile", expr).unwrap();
+    assert!(module.verify().is_ok());
+
+    // `toplevel`, and one function for both lambdas
+    let defined = module
+        .get_functions()
+        .filter(|f| f.count_basic_blocks() > 0)
+        .count();
+    assert_eq!(defined, 2);
+}
+
+#[test]
+fn compile_main() {
+    for (src, fmt) in &[
+        ("1 + 2", "%ld"),
+        ("1.5 *. 2.0", "%f"),
+        ("1 == 2", "%d"),
+        (r"\x -> x + 1", "<closure>"),
+    ] {
+        let expr = infer_and_apply(&Expr::from_str(src).unwrap()).unwrap();
+        let ctx = Context::create();
+        let module = compile_program(&ctx, "test_compile", expr).unwrap();
+        assert!(module.verify().is_ok());
+
+        let ir = module.print_to_string().to_string();
+        assert!(ir.contains("define i32 @main()"), "{}", ir);
+        assert!(ir.contains(fmt), "{}", ir);
+
+        let exec_engine = module
+            .create_jit_execution_engine(OptimizationLevel::None)
+            .unwrap();
+        let main = unsafe { exec_engine.get_function::<unsafe extern "C" fn() -> i32>("main") };
+        assert_eq!(unsafe { main.unwrap().call() }, 0);
+    }
+}
+
+/// End-to-end runs of the closed, monomorphic fixtures from
+/// `src/types/test.rs`. Fixtures with a function type are applied to an
+/// argument, so that there is a value to check
+mod fixtures {
+    use super::test_compile_and_execute;
+
+    #[test]
+    fn fixture_lit() {
+        test_compile_and_execute("1", 1);
+        test_compile_and_execute("1.0", 1.0);
+        test_compile_and_execute("true", true);
+        test_compile_and_execute("false", false);
+    }
+
+    #[test]
+    fn fixture_negative_lit() {
+        test_compile_and_execute("-5", -5);
+        test_compile_and_execute("-2.5", -2.5);
+        test_compile_and_execute("1 - -2", 3);
+    }
+
+    #[test]
+    fn fixture_inc_fn() {
+        test_compile_and_execute(r"let inc = \x -> x + 1 in inc (inc 1)", 3);
+    }
+
+    #[test]
+    fn fixture_pred_fn() {
+        test_compile_and_execute(
+            r"(\pred -> if pred 1 then 2 else 3) (\x -> x == 1)",
+            2,
+        );
+        test_compile_and_execute(
+            r"(\pred -> if pred 1 then 2 else 3) (\x -> x == 0)",
+            3,
+        );
+    }
+
+    #[test]
+    fn fixture_let_lambda() {
+        test_compile_and_execute(r"let f = \a, b -> a b in f (\x -> x * 2) 21", 42);
+    }
+
+    #[test]
+    fn fixture_annotations() {
+        test_compile_and_execute(r"let idInt: Int -> Int = \a -> a in idInt 7", 7);
+    }
+
+    #[test]
+    fn fixture_operators() {
+        test_compile_and_execute("1 + 2", 3);
+        test_compile_and_execute("1.0 +. 2.0", 3.0);
+        test_compile_and_execute("1 == 2", false);
+        test_compile_and_execute("1.0 == 2.0", false);
+        test_compile_and_execute("true == false", false);
+    }
+
+    #[test]
+    fn fixture_unops() {
+        test_compile_and_execute("!true", false);
+        test_compile_and_execute("!(1 == 2)", true);
+        test_compile_and_execute(r"(\x -> -x) 5", -5);
+    }
+}
+
+#[test]
+fn compile_polymorphic_builtins() {
+    test_compile_and_execute("id 1", 1);
+    test_compile_and_execute("const 1 true", 1);
+    test_compile_and_execute(r"compose (\x -> x + 1) (\x -> x * 2) 5", 11);
+    test_compile_and_execute("if_then_else (id true) 1.5 2.5", 1.5);
+    test_compile_and_execute("id (id 1 == 1)", true);
+}
+
+#[test]
+fn compile_letrec() {
+    let expr = infer_and_apply(&Expr::from_str(r"letrec f = \x -> x + 1 in f 1").unwrap()).unwrap();
+    let ctx = Context::create();
+    let err = compile(&ctx, "test_compile", expr).unwrap_err();
+    assert_eq!(err, CodegenError::UnsupportedLetrec);
+}
+
+#[test]
+fn compile_ambiguous_type() {
+    let expr = infer_and_apply(&Expr::from_str(r"\x -> x").unwrap()).unwrap();
+    let ctx = Context::create();
+    let err = compile(&ctx, "test_compile", expr).unwrap_err();
+    assert!(matches!(err, CodegenError::AmbiguousType(_)), "{}", err);
+}
+
+#[test]
+fn compile_tuple_type() {
+    // there is no syntax for tuples yet, so give the body a tuple type directly
+    let expr = infer_and_apply(&Expr::from_str(r"\x -> x + 1").unwrap()).unwrap();
+    let expr = expr.map_types(|ty| {
+        if *ty == Type::Int { Type::Tuple(vec![Type::Int, Type::Bool]) } else { ty.clone() }
+    });
+    let ctx = Context::create();
+    let err = compile(&ctx, "test_compile", expr.clone()).unwrap_err();
+    assert!(matches!(err, CodegenError::UnsupportedTuple(_)), "{}", err);
+    let err = compile_program(&ctx, "test_compile", expr).unwrap_err();
+    assert!(matches!(err, CodegenError::UnsupportedTuple(_)), "{}", err);
+}
+
+#[test]
+fn compile_defaulted_type_vars() {
+    let expr = infer_and_apply(&Expr::from_str(r"let unused = \x -> x in 5").unwrap()).unwrap();
+    let ctx = Context::create();
+    assert!(compile(&ctx, "test_compile", expr.clone()).is_err());
+
+    let module = compile(&ctx, "test_compile", default_type_vars(&expr)).unwrap();
+    assert!(module.verify().is_ok());
+}
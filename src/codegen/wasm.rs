@@ -0,0 +1,318 @@
+//! An experimental backend that emits WebAssembly text format.
+//! Only first-order code is supported: literals, operators, `if`s, `let`s and
+//! variables. Anything else is rejected with
+//! `CodegenError::WasmUnsupported`. Closures will eventually be compiled to functions in a table,
+//! called with `call_indirect` and passed their environment in linear memory
+
+use crate::{
+    codegen::{
+        check_monomorphic,
+        closure::{convert, CExpr},
+        rename::rename,
+        CodegenError,
+    },
+    hir::{Binop, Expr, Lit, Symbol, Type, Unop},
+};
+
+/// Compile a typed expression (eg from `infer_and_apply`) to a `.wat` module
+/// that exports a `toplevel` function evaluating it.
+/// Ints are `i64`s, floats are `f64`s and bools are `i32`s.
+/// Fails if any type in `expr` is not monomorphic, or if `expr` uses a feature
+/// the backend does not support
+pub fn compile_wat(expr: Expr) -> Result<String, CodegenError> {
+    check_monomorphic(&expr)?;
+
+    let cexpr = convert(rename(expr));
+    let mut compiler = WatCompiler::default();
+    let body = compiler.compile_expr(&cexpr)?;
+
+    let mut func = compiler.locals;
+    func.push(body);
+    let module = Wat::new(
+        "module",
+        vec![Wat::new(
+            format!(
+                "func $toplevel (export \"toplevel\") (result {})",
+                wasm_type(&cexpr.ty())?
+            ),
+            func,
+        )],
+    );
+
+    let mut out = String::new();
+    module.render(0, &mut out);
+    Ok(out)
+}
+
+/// A folded instruction, printed as `(head child1 ... childN)`, one child per
+/// line
+struct Wat {
+    head: String,
+    children: Vec<Self>,
+}
+
+impl Wat {
+    fn new(head: impl Into<String>, children: Vec<Self>) -> Self {
+        Self {
+            head: head.into(),
+            children,
+        }
+    }
+
+    fn leaf(head: impl Into<String>) -> Self {
+        Self::new(head, vec![])
+    }
+
+    fn render(&self, indent: usize, out: &mut String) {
+        out.push_str(&"  ".repeat(indent));
+        out.push('(');
+        out.push_str(&self.head);
+        for child in &self.children {
+            out.push('\n');
+            child.render(indent + 1, out);
+        }
+        out.push(')');
+    }
+}
+
+#[derive(Default)]
+struct WatCompiler {
+    /// `(local ...)` declarations for every `let`-bound variable. Binders are
+    /// unique after `rename`, so each can have its own local
+    locals: Vec<Wat>,
+}
+
+impl WatCompiler {
+    fn compile_expr(&mut self, expr: &CExpr) -> Result<Wat, CodegenError> {
+        let wat = match expr {
+            CExpr::Lit { val, .. } => compile_lit(*val),
+            CExpr::Var { name, .. } => Wat::leaf(format!("local.get {}", local(*name))),
+            CExpr::Binop { lhs, rhs, op, .. } => Wat::new(
+                binop_instr(*op, &lhs.ty())?,
+                vec![self.compile_expr(lhs)?, self.compile_expr(rhs)?],
+            ),
+            CExpr::Unop { op, operand, .. } => {
+                let operand = self.compile_expr(operand)?;
+                match op {
+                    Unop::Not => Wat::new("i32.eqz", vec![operand]),
+                    Unop::IntNeg => Wat::new("i64.sub", vec![Wat::leaf("i64.const 0"), operand]),
+                    Unop::FloatNeg => Wat::new("f64.neg", vec![operand]),
+                    Unop::FloatSqrt => Wat::new("f64.sqrt", vec![operand]),
+                    Unop::FloatAbs => Wat::new("f64.abs", vec![operand]),
+                    Unop::FloatFloor => Wat::new("f64.floor", vec![operand]),
+                }
+            }
+            CExpr::If {
+                ty,
+                test,
+                then,
+                els,
+            } => Wat::new(
+                format!("if (result {})", wasm_type(ty)?),
+                vec![
+                    self.compile_expr(test)?,
+                    Wat::new("then", vec![self.compile_expr(then)?]),
+                    Wat::new("else", vec![self.compile_expr(els)?]),
+                ],
+            ),
+            CExpr::Let { ty, binding, body } => {
+                self.locals.push(Wat::leaf(format!(
+                    "local {} {}",
+                    local(binding.name),
+                    wasm_type(&binding.ty)?
+                )));
+                let set = Wat::new(
+                    format!("local.set {}", local(binding.name)),
+                    vec![self.compile_expr(&binding.val)?],
+                );
+                Wat::new(
+                    format!("block (result {})", wasm_type(ty)?),
+                    vec![set, self.compile_expr(body)?],
+                )
+            }
+            CExpr::EnvRef { .. }
+            | CExpr::Letrec { .. }
+            | CExpr::MkClosure { .. }
+            | CExpr::App { .. } => return Err(unsupported("closures")),
+        };
+        Ok(wat)
+    }
+}
+
+fn local(name: Symbol) -> String {
+    format!("${}", name)
+}
+
+fn unsupported(what: impl Into<String>) -> CodegenError {
+    CodegenError::WasmUnsupported(what.into())
+}
+
+fn wasm_type(ty: &Type) -> Result<&'static str, CodegenError> {
+    match ty {
+        Type::Int => Ok("i64"),
+        Type::Float => Ok("f64"),
+        Type::Unit | Type::Bool => Ok("i32"),
+        Type::Fn(..) | Type::Tuple(_) | Type::Var(_) => Err(unsupported_type(ty)),
+    }
+}
+
+fn unsupported_type(ty: &Type) -> CodegenError {
+    match ty {
+        Type::Var(_) => CodegenError::AmbiguousType(ty.clone()),
+        Type::Fn(..) => unsupported("closures"),
+        _ => unsupported(format!("values of type {}", ty)),
+    }
+}
+
+fn compile_lit(val: Lit) -> Wat {
+    match val {
+        Lit::Unit => Wat::leaf("i32.const 0"),
+        Lit::Bool(b) => Wat::leaf(format!("i32.const {}", if b { 1 } else { 0 })),
+        Lit::Int(i) => Wat::leaf(format!("i64.const {}", i)),
+        Lit::Float(f) if f.is_nan() => Wat::leaf("f64.const nan"),
+        Lit::Float(f) if f.is_infinite() => {
+            Wat::leaf(format!("f64.const {}inf", if f < 0.0 { "-" } else { "" }))
+        }
+        Lit::Float(f) => Wat::leaf(format!("f64.const {:?}", f)),
+    }
+}
+
+fn binop_instr(op: Binop, operand_ty: &Type) -> Result<&'static str, CodegenError> {
+    #![allow(clippy::enum_glob_use)]
+    use Binop::*;
+
+    let instr = match op {
+        IntAdd => "i64.add",
+        IntSub => "i64.sub",
+        IntMul => "i64.mul",
+        IntDiv => "i64.div_s",
+
+        IntLt => "i64.lt_s",
+        IntLeq => "i64.le_s",
+        IntGt => "i64.gt_s",
+        IntGeq => "i64.ge_s",
+
+        IntAnd => "i64.and",
+        IntOr => "i64.or",
+        IntXor => "i64.xor",
+        IntShl => "i64.shl",
+        IntShr => "i64.shr_s",
+
+        FloatAdd => "f64.add",
+        FloatSub => "f64.sub",
+        FloatMul => "f64.mul",
+        FloatDiv => "f64.div",
+
+        FloatLt => "f64.lt",
+        FloatLeq => "f64.le",
+        FloatGt => "f64.gt",
+        FloatGeq => "f64.ge",
+
+        // WebAssembly has no pow instruction
+        FloatPow => return Err(unsupported(format!("`{}`", op.symbol()))),
+
+        Eq => match operand_ty {
+            Type::Int => "i64.eq",
+            Type::Unit | Type::Bool => "i32.eq",
+            Type::Float => "f64.eq",
+            Type::Fn(..) | Type::Tuple(_) | Type::Var(_) => {
+                return Err(unsupported_type(operand_ty))
+            }
+        },
+        Neq => match operand_ty {
+            Type::Int => "i64.ne",
+            Type::Unit | Type::Bool => "i32.ne",
+            Type::Float => "f64.ne",
+            Type::Fn(..) | Type::Tuple(_) | Type::Var(_) => {
+                return Err(unsupported_type(operand_ty))
+            }
+        },
+    };
+    Ok(instr)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::parse_and_type;
+    use insta::assert_snapshot;
+
+    /// Compile `src`, and check that the output is well-formed
+    #[track_caller]
+    fn test_wat(src: &str) -> String {
+        let wat = compile_wat(parse_and_type(src).unwrap()).unwrap();
+        if let Err(err) = wat::parse_str(&wat) {
+            panic!("{}\n{}", err, wat);
+        }
+        wat
+    }
+
+    #[test]
+    fn wat_arith() {
+        assert_snapshot!(test_wat("1 + 2 * 3"));
+    }
+
+    #[test]
+    fn wat_if_and_let() {
+        let wat = test_wat("let x = 1.5 in if x >. 0.0 then x else -.x");
+        assert_eq!(
+            wat,
+            r#"(module
+  (func $toplevel (export "toplevel") (result f64)
+    (local $x f64)
+    (block (result f64)
+      (local.set $x
+        (f64.const 1.5))
+      (if (result f64)
+        (f64.gt
+          (local.get $x)
+          (f64.const 0.0))
+        (then
+          (local.get $x))
+        (else
+          (f64.neg
+            (local.get $x)))))))"#
+        );
+    }
+
+    #[test]
+    fn wat_shadowed_lets() {
+        // each binder gets its own local
+        let wat = test_wat("let x = 1 in let x = x == 1 in !x");
+        assert!(wat.contains("(local $x i64)"));
+        assert!(wat.contains("(local $x.1 i32)"));
+        assert!(wat.contains("(local.set $x.1\n"));
+        assert!(wat.contains("(i32.eqz\n"));
+    }
+
+    #[test]
+    fn wat_parses() {
+        for src in &[
+            "()",
+            "1 / 2 - -3",
+            "(1 << 2) | (8 >> 1) & 3 ^ 1",
+            "1.5 *. 2.0 /. 0.0",
+            "let x = 2.0 in -.(-.x)",
+            "if 1 == 2 then () else ()",
+            "(1.0 != 2.0) == (true != false)",
+            "let x = 1 in let y = x * x in x <= y",
+        ] {
+            test_wat(src);
+        }
+    }
+
+    #[test]
+    fn wat_unsupported() {
+        for src in &[r"\x -> x + 1", r"(\x -> x + 1) 2", r"let f = \x -> x + 1 in f 1"] {
+            let err = compile_wat(parse_and_type(src).unwrap()).unwrap_err();
+            assert_eq!(err, CodegenError::WasmUnsupported(String::from("closures")), "{}", src);
+        }
+        assert_eq!(
+            binop_instr(Binop::FloatPow, &Type::Float),
+            Err(CodegenError::WasmUnsupported(String::from("`pow`")))
+        );
+
+        let expr = parse_and_type(r"let id = \x -> x in 1").unwrap();
+        assert!(matches!(compile_wat(expr), Err(CodegenError::AmbiguousType(_))));
+    }
+}
@@ -0,0 +1,321 @@
+//! A tree-walking interpreter for `hir::Expr`, which agrees with the LLVM
+//! backend on the result of every well-typed program that does not fail at
+//! runtime. Programs that trap in compiled code (dividing by zero, or
+//! `i64::MIN` by -1) fail with an `EvalError` instead
+
+use crate::{
+    codegen::prelude::{link_prelude, POLYMORPHIC_PRELUDE},
+    hir::{Binop, Expr, LetBinding, Lit, Symbol, Unop},
+};
+use derive_more::Display;
+use simple_symbol::intern;
+use std::{convert::TryFrom, rc::Rc, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq, Display)]
+pub enum Value {
+    #[display(fmt = "()")]
+    Unit,
+    #[display(fmt = "{}", _0)]
+    Int(i64),
+    /// Always has a fractional part or an exponent (eg `2.0`, not `2`), so
+    /// that it reads back as a `Float`
+    #[display(fmt = "{:?}", _0)]
+    Float(f64),
+    #[display(fmt = "{}", _0)]
+    Bool(bool),
+    #[display(fmt = "{}", _0)]
+    Closure(Closure),
+}
+
+/// Printed as the lambda it was created from, without its environment
+#[derive(Debug, Clone, PartialEq, Display)]
+#[display(fmt = "\\{} -> {}", param, body)]
+pub struct Closure {
+    pub param: Symbol,
+    pub body: Expr,
+    pub env: Env,
+}
+
+/// Int arithmetic wraps around on overflow, as it does in compiled code.
+/// Int division by zero is an error, and traps in compiled code. Float
+/// division by zero follows IEEE 754, so `1.0 /. 0.0` is infinity
+#[derive(Debug, Clone, PartialEq, Eq, Display)]
+pub enum EvalError {
+    #[display(fmt = "Division by zero")]
+    DivisionByZero,
+    #[display(fmt = "Integer overflow")]
+    Overflow,
+    #[display(fmt = "Unbound variable: {}", _0)]
+    Unbound(Symbol),
+}
+
+impl std::error::Error for EvalError {}
+
+impl From<Lit> for Value {
+    fn from(lit: Lit) -> Self {
+        match lit {
+            Lit::Unit => Self::Unit,
+            Lit::Bool(b) => Self::Bool(b),
+            Lit::Int(i) => Self::Int(i),
+            Lit::Float(f) => Self::Float(f),
+        }
+    }
+}
+
+/// The variables in scope, innermost first
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Env(Option<Rc<Scope>>);
+
+#[derive(Debug, PartialEq)]
+enum Scope {
+    Bind { name: Symbol, val: Value, parent: Env },
+    /// A `letrec` group. Its closures are created when they are looked up,
+    /// with this scope as their environment, so that they can refer to
+    /// themselves
+    Rec { bindings: Vec<LetBinding>, parent: Env },
+}
+
+impl Env {
+    fn bind(&self, name: Symbol, val: Value) -> Self {
+        Self(Some(Rc::new(Scope::Bind {
+            name,
+            val,
+            parent: self.clone(),
+        })))
+    }
+
+    fn bind_rec(&self, bindings: Vec<LetBinding>) -> Self {
+        Self(Some(Rc::new(Scope::Rec {
+            bindings,
+            parent: self.clone(),
+        })))
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<Value> {
+        let mut env = self;
+        while let Some(scope) = &env.0 {
+            match &**scope {
+                Scope::Bind { name: bound, val, .. } if *bound == name => return Some(val.clone()),
+                Scope::Rec { bindings, .. } => {
+                    if let Some(binding) = bindings.iter().find(|binding| binding.name == name) {
+                        return match &*binding.val {
+                            Expr::Lambda { param, body, .. } => Some(Value::Closure(Closure {
+                                param: param.name,
+                                body: (**body).clone(),
+                                env: env.clone(),
+                            })),
+                            _ => unreachable!("letrec bindings are lambdas"),
+                        };
+                    }
+                }
+                Scope::Bind { .. } => {}
+            }
+            env = match &**scope {
+                Scope::Bind { parent, .. } | Scope::Rec { parent, .. } => parent,
+            };
+        }
+        None
+    }
+}
+
+/// Evaluate a well-typed expression. The monomorphic builtins (`add`, `sqrt`,
+/// etc) are linked in first, and the polymorphic ones (`id`, `compose`, etc)
+/// are in scope
+pub fn eval(expr: &Expr) -> Result<Value, EvalError> {
+    eval_in(&link_prelude(expr.clone()), &polymorphic_prelude())
+}
+
+fn polymorphic_prelude() -> Env {
+    POLYMORPHIC_PRELUDE.iter().fold(Env::default(), |env, (name, src)| {
+        let def = Expr::from_str(src).expect("the prelude parses");
+        let val = eval_in(&def, &Env::default()).expect("the prelude is closed");
+        env.bind(intern(*name), val)
+    })
+}
+
+fn eval_in(expr: &Expr, env: &Env) -> Result<Value, EvalError> {
+    match expr {
+        Expr::Lit { val, .. } => Ok(val.clone().into()),
+        Expr::Var { name, .. } => env.lookup(*name).ok_or(EvalError::Unbound(*name)),
+        Expr::Binop { lhs, rhs, op, .. } => {
+            let lhs = eval_in(lhs, env)?;
+            let rhs = eval_in(rhs, env)?;
+            eval_binop(*op, lhs, rhs)
+        }
+        Expr::Unop { op, operand, .. } => Ok(eval_unop(*op, eval_in(operand, env)?)),
+        Expr::If {
+            test, then, els, ..
+        } => match eval_in(test, env)? {
+            Value::Bool(true) => eval_in(then, env),
+            Value::Bool(false) => eval_in(els, env),
+            val => unreachable!("`if` on a non-bool: {:?}", val),
+        },
+        Expr::Let { binding, body, .. } => {
+            let val = eval_in(&binding.val, env)?;
+            eval_in(body, &env.bind(binding.name, val))
+        }
+        Expr::Letrec { bindings, body, .. } => eval_in(body, &env.bind_rec(bindings.clone())),
+        Expr::Lambda { param, body, .. } => Ok(Value::Closure(Closure {
+            param: param.name,
+            body: (**body).clone(),
+            env: env.clone(),
+        })),
+        Expr::App { func, arg, .. } => {
+            let func = eval_in(func, env)?;
+            let arg = eval_in(arg, env)?;
+            match func {
+                Value::Closure(closure) => {
+                    eval_in(&closure.body, &closure.env.bind(closure.param, arg))
+                }
+                val => unreachable!("application of a non-function: {:?}", val),
+            }
+        }
+    }
+}
+
+#[allow(clippy::float_cmp)]
+fn eval_binop(op: Binop, lhs: Value, rhs: Value) -> Result<Value, EvalError> {
+    #![allow(clippy::enum_glob_use)]
+    use Binop::*;
+    use Value::*;
+
+    // the shift amount is taken mod 64, as in compiled code
+    let shift = |y: i64| u32::try_from(y & 63).unwrap();
+
+    let val = match (op, lhs, rhs) {
+        (IntAdd, Int(x), Int(y)) => Int(x.wrapping_add(y)),
+        (IntSub, Int(x), Int(y)) => Int(x.wrapping_sub(y)),
+        (IntMul, Int(x), Int(y)) => Int(x.wrapping_mul(y)),
+        (IntDiv, Int(_), Int(0)) => return Err(EvalError::DivisionByZero),
+        (IntDiv, Int(x), Int(y)) => Int(x.checked_div(y).ok_or(EvalError::Overflow)?),
+        (IntLt, Int(x), Int(y)) => Bool(x < y),
+        (IntLeq, Int(x), Int(y)) => Bool(x <= y),
+        (IntGt, Int(x), Int(y)) => Bool(x > y),
+        (IntGeq, Int(x), Int(y)) => Bool(x >= y),
+        (IntAnd, Int(x), Int(y)) => Int(x & y),
+        (IntOr, Int(x), Int(y)) => Int(x | y),
+        (IntXor, Int(x), Int(y)) => Int(x ^ y),
+        (IntShl, Int(x), Int(y)) => Int(x << shift(y)),
+        (IntShr, Int(x), Int(y)) => Int(x >> shift(y)),
+
+        (FloatAdd, Float(x), Float(y)) => Float(x + y),
+        (FloatSub, Float(x), Float(y)) => Float(x - y),
+        (FloatMul, Float(x), Float(y)) => Float(x * y),
+        (FloatDiv, Float(x), Float(y)) => Float(x / y),
+        (FloatPow, Float(x), Float(y)) => Float(x.powf(y)),
+        (FloatLt, Float(x), Float(y)) => Bool(x < y),
+        (FloatLeq, Float(x), Float(y)) => Bool(x <= y),
+        (FloatGt, Float(x), Float(y)) => Bool(x > y),
+        (FloatGeq, Float(x), Float(y)) => Bool(x >= y),
+
+        (Eq, Unit, Unit) => Bool(true),
+        (Eq, Int(x), Int(y)) => Bool(x == y),
+        (Eq, Float(x), Float(y)) => Bool(x == y),
+        (Eq, Bool(x), Bool(y)) => Bool(x == y),
+        (Neq, Unit, Unit) => Bool(false),
+        (Neq, Int(x), Int(y)) => Bool(x != y),
+        (Neq, Float(x), Float(y)) => Bool(x != y),
+        (Neq, Bool(x), Bool(y)) => Bool(x != y),
+
+        (op, lhs, rhs) => unreachable!("ill-typed operands for {:?}: {:?}, {:?}", op, lhs, rhs),
+    };
+    Ok(val)
+}
+
+fn eval_unop(op: Unop, val: Value) -> Value {
+    match (op, val) {
+        (Unop::Not, Value::Bool(b)) => Value::Bool(!b),
+        (Unop::IntNeg, Value::Int(i)) => Value::Int(i.wrapping_neg()),
+        (Unop::FloatNeg, Value::Float(f)) => Value::Float(-f),
+        (Unop::FloatSqrt, Value::Float(f)) => Value::Float(f.sqrt()),
+        (Unop::FloatAbs, Value::Float(f)) => Value::Float(f.abs()),
+        (Unop::FloatFloor, Value::Float(f)) => Value::Float(f.floor()),
+        (op, val) => unreachable!("ill-typed operand for {:?}: {:?}", op, val),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::parse_and_type;
+
+    #[track_caller]
+    fn test_eval(src: &str, expected: Value) {
+        assert_eq!(eval(&parse_and_type(src).unwrap()), Ok(expected));
+    }
+
+    #[test]
+    fn eval_simple() {
+        test_eval("1 + 2 * 3", Value::Int(7));
+        test_eval("1.5 *. 2.0", Value::Float(3.0));
+        test_eval("if 1 < 2 then true else false", Value::Bool(true));
+        test_eval("let x = 5 in let x = x + 1 in x", Value::Int(6));
+        test_eval(r"(\x, y -> x - y) 10 3", Value::Int(7));
+        test_eval("not (is_zero (add 1 2))", Value::Bool(true));
+        test_eval("sqrt 16.0", Value::Float(4.0));
+        test_eval("if true then ()", Value::Unit);
+        test_eval("() == ()", Value::Bool(true));
+    }
+
+    #[test]
+    fn eval_polymorphic_builtins() {
+        test_eval("id 5", Value::Int(5));
+        test_eval("const 1 2", Value::Int(1));
+        test_eval("if_then_else true 1 0", Value::Int(1));
+        test_eval(r"compose (\x -> x + 1) (\x -> x * 2) 5", Value::Int(11));
+        test_eval("let id = 1 in id", Value::Int(1));
+    }
+
+    #[test]
+    fn eval_closures() {
+        test_eval(r"let k = 10 in let f = \x -> x + k in let k = 0 in f 1", Value::Int(11));
+        test_eval(
+            r"letrec fact = \n -> if n == 0 then 1 else n * fact (n - 1) in fact 10",
+            Value::Int(3_628_800),
+        );
+        test_eval(
+            r"letrec even = \n -> if n == 0 then true else odd (n - 1),
+                     odd = \n -> if n == 0 then false else even (n - 1)
+              in even 7",
+            Value::Bool(false),
+        );
+        assert!(matches!(
+            eval(&parse_and_type(r"\x -> x + 1").unwrap()),
+            Ok(Value::Closure(_))
+        ));
+    }
+
+    #[test]
+    fn display_values() {
+        assert_eq!(Value::Int(42).to_string(), "42");
+        assert_eq!(Value::Int(-1).to_string(), "-1");
+        assert_eq!(Value::Float(2.5).to_string(), "2.5");
+        assert_eq!(Value::Float(2.0).to_string(), "2.0");
+        assert_eq!(Value::Bool(true).to_string(), "true");
+        assert_eq!(Value::Unit.to_string(), "()");
+
+        let closure = eval(&parse_and_type(r"let k = 1 in \x -> x + k").unwrap()).unwrap();
+        assert_eq!(closure.to_string(), r"\x -> x + k");
+    }
+
+    #[test]
+    fn eval_division() {
+        test_eval("7 / 2", Value::Int(3));
+        test_eval("-7 / 2", Value::Int(-3));
+        test_eval("1.0 /. 0.0", Value::Float(f64::INFINITY));
+        test_eval("let nan = 0.0 /. 0.0 in nan == nan", Value::Bool(false));
+        test_eval("let nan = 0.0 /. 0.0 in nan != nan", Value::Bool(true));
+        assert_eq!(
+            eval(&parse_and_type("1 / 0").unwrap()),
+            Err(EvalError::DivisionByZero)
+        );
+        assert_eq!(
+            eval(&parse_and_type(r"let f = \x -> 10 / x in f 0").unwrap()),
+            Err(EvalError::DivisionByZero)
+        );
+        assert_eq!(
+            eval(&parse_and_type("let min = -9223372036854775807 - 1 in min / -1").unwrap()),
+            Err(EvalError::Overflow)
+        );
+    }
+}
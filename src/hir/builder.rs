@@ -0,0 +1,194 @@
+use crate::{
+    hir::{Binop, Expr, LetBinding, Lit, Param},
+    types::ty::TypeVarGen,
+};
+use simple_symbol::intern;
+
+/// Builds `Expr`s, giving every expression and binder a fresh type variable.
+/// Type variables are assigned in the same order as `Expr::from_ast`: first
+/// the expression itself, then its binder (if any), then its children from
+/// left to right. So building the expression for some source code gives the
+/// same `Expr` as parsing it.
+/// Children are passed as closures so that they are built after their parent
+#[derive(Debug)]
+pub struct TypedExprBuilder {
+    gen: TypeVarGen,
+}
+
+impl Default for TypedExprBuilder {
+    fn default() -> Self {
+        Self {
+            gen: TypeVarGen::new(),
+        }
+    }
+}
+
+impl TypedExprBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn lit(&mut self, val: Lit) -> Expr {
+        Expr::Lit {
+            ty: self.gen.next(),
+            val,
+        }
+    }
+
+    pub fn lit_int(&mut self, val: i64) -> Expr {
+        self.lit(Lit::Int(val))
+    }
+
+    pub fn lit_bool(&mut self, val: bool) -> Expr {
+        self.lit(Lit::Bool(val))
+    }
+
+    pub fn lit_float(&mut self, val: f64) -> Expr {
+        self.lit(Lit::Float(val))
+    }
+
+    pub fn var(&mut self, name: &str) -> Expr {
+        Expr::Var {
+            ty: self.gen.next(),
+            name: intern(name),
+        }
+    }
+
+    pub fn binop(
+        &mut self,
+        op: Binop,
+        lhs: impl FnOnce(&mut Self) -> Expr,
+        rhs: impl FnOnce(&mut Self) -> Expr,
+    ) -> Expr {
+        Expr::Binop {
+            ty: self.gen.next(),
+            lhs: box lhs(self),
+            rhs: box rhs(self),
+            op,
+        }
+    }
+
+    pub fn lambda(&mut self, param: &str, body: impl FnOnce(&mut Self) -> Expr) -> Expr {
+        Expr::Lambda {
+            ty: self.gen.next(),
+            param: Param {
+                ty: self.gen.next(),
+                name: intern(param),
+                ann: None,
+            },
+            body: box body(self),
+        }
+    }
+
+    pub fn app(
+        &mut self,
+        func: impl FnOnce(&mut Self) -> Expr,
+        arg: impl FnOnce(&mut Self) -> Expr,
+    ) -> Expr {
+        Expr::App {
+            ty: self.gen.next(),
+            func: box func(self),
+            arg: box arg(self),
+        }
+    }
+
+    pub fn let_(
+        &mut self,
+        name: &str,
+        val: impl FnOnce(&mut Self) -> Expr,
+        body: impl FnOnce(&mut Self) -> Expr,
+    ) -> Expr {
+        Expr::Let {
+            ty: self.gen.next(),
+            binding: LetBinding {
+                ty: self.gen.next(),
+                name: intern(name),
+                ann: None,
+                val: box val(self),
+            },
+            body: box body(self),
+        }
+    }
+
+    /// Unlike the parser, allows any expression (not just lambdas) to be bound
+    pub fn letrec(
+        &mut self,
+        bindings: &[(&str, &dyn Fn(&mut Self) -> Expr)],
+        body: impl FnOnce(&mut Self) -> Expr,
+    ) -> Expr {
+        Expr::Letrec {
+            ty: self.gen.next(),
+            bindings: bindings
+                .iter()
+                .map(|(name, val)| LetBinding {
+                    ty: self.gen.next(),
+                    name: intern(name),
+                    ann: None,
+                    val: box val(self),
+                })
+                .collect(),
+            body: box body(self),
+        }
+    }
+
+    pub fn if_(
+        &mut self,
+        test: impl FnOnce(&mut Self) -> Expr,
+        then: impl FnOnce(&mut Self) -> Expr,
+        els: impl FnOnce(&mut Self) -> Expr,
+    ) -> Expr {
+        Expr::If {
+            ty: self.gen.next(),
+            test: box test(self),
+            then: box then(self),
+            els: box els(self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn builder_matches_from_ast() {
+        let mut b = TypedExprBuilder::new();
+        let compose = b.lambda("f", |b| {
+            b.lambda("g", |b| {
+                b.lambda("x", |b| {
+                    b.app(|b| b.var("f"), |b| b.app(|b| b.var("g"), |b| b.var("x")))
+                })
+            })
+        });
+        assert_eq!(
+            compose,
+            Expr::from_str(r"\f -> \g -> \x -> f (g x)").unwrap()
+        );
+
+        let mut b = TypedExprBuilder::new();
+        let expr = b.let_(
+            "x",
+            |b| b.lit_int(5),
+            |b| b.if_(|b| b.lit_bool(true), |b| b.var("x"), |b| b.lit_int(0)),
+        );
+        assert_eq!(
+            expr,
+            Expr::from_str("let x = 5 in if true then x else 0").unwrap()
+        );
+
+        let mut b = TypedExprBuilder::new();
+        let expr = b.letrec(
+            &[("f", &|b| {
+                b.lambda("x", |b| {
+                    b.binop(Binop::IntSub, |b| b.var("x"), |b| b.lit_int(1))
+                })
+            })],
+            |b| b.app(|b| b.var("f"), |b| b.lit_int(2)),
+        );
+        assert_eq!(
+            expr,
+            Expr::from_str(r"letrec f = \x -> x - 1 in f 2").unwrap()
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use crate::hir::{
+    visit::{walk_visit, ExprVisitor},
+    Expr,
+};
+
+impl Expr {
+    /// The number of nodes in the tree. Binders are not counted separately
+    pub fn size(&self) -> usize {
+        struct Size(usize);
+        impl ExprVisitor for Size {
+            fn visit_expr(&mut self, expr: &Expr) {
+                self.0 += 1;
+                walk_visit(self, expr)
+            }
+        }
+
+        let mut visitor = Size(0);
+        visitor.visit_expr(self);
+        visitor.0
+    }
+
+    /// The number of nodes on the longest path from the root to a leaf
+    pub fn depth(&self) -> usize {
+        #[derive(Default)]
+        struct Depth {
+            current: usize,
+            max: usize,
+        }
+        impl ExprVisitor for Depth {
+            fn visit_expr(&mut self, expr: &Expr) {
+                self.current += 1;
+                self.max = self.max.max(self.current);
+                walk_visit(self, expr);
+                self.current -= 1;
+            }
+        }
+
+        let mut visitor = Depth::default();
+        visitor.visit_expr(self);
+        visitor.max
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hir::Expr;
+    use std::str::FromStr;
+
+    #[track_caller]
+    fn test_metrics(src: &str, size: usize, depth: usize) {
+        let expr = Expr::from_str(src).unwrap();
+        assert_eq!((expr.size(), expr.depth()), (size, depth));
+    }
+
+    #[test]
+    fn metrics() {
+        test_metrics("1", 1, 1);
+        test_metrics("1 + 2", 3, 2);
+        test_metrics("-(1 + 2) * 3", 6, 4);
+        test_metrics(r"\x -> x", 2, 2);
+        test_metrics(r"let x = 1, y = 2 in x", 5, 3);
+        test_metrics(r"letrec f = \x -> f x, g = \y -> y in f g", 10, 4);
+    }
+
+    #[test]
+    fn depth_of_nested_ifs() {
+        test_metrics("if true then 1 else 2", 4, 2);
+        test_metrics("if true then if false then 1 else 2 else 3", 7, 3);
+        test_metrics(
+            "if if true then false else true then 1 else if false then 2 else 3",
+            10,
+            3,
+        );
+    }
+}
@@ -1,10 +1,17 @@
 use crate::{syntax::ast, types::ty::TypeVarGen};
-pub use crate::{syntax::ast::Binop, types::ty::Type};
+pub use crate::types::ty::Type;
 use derive_more::Display;
+pub use pp::PrettyConfig;
 pub use simple_symbol::Symbol;
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr};
+
+pub mod builder;
+mod metrics;
+mod pp;
+pub mod visit;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
     Lit {
         ty: Type,
@@ -12,6 +19,7 @@ pub enum Expr {
     },
     Var {
         ty: Type,
+        #[cfg_attr(feature = "serde", serde(with = "crate::util::symbol_serde"))]
         name: Symbol,
     },
     Binop {
@@ -20,6 +28,11 @@ pub enum Expr {
         lhs: Box<Self>,
         op: Binop,
     },
+    Unop {
+        ty: Type,
+        op: Unop,
+        operand: Box<Self>,
+    },
     If {
         ty: Type,
         test: Box<Self>,
@@ -49,22 +62,29 @@ pub enum Expr {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Param {
     pub ty: Type,
+    #[cfg_attr(feature = "serde", serde(with = "crate::util::symbol_serde"))]
     pub name: Symbol,
     pub ann: Option<Type>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LetBinding {
     pub ty: Type,
+    #[cfg_attr(feature = "serde", serde(with = "crate::util::symbol_serde"))]
     pub name: Symbol,
     pub ann: Option<Type>,
     pub val: Box<Expr>,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Lit {
+    #[display(fmt = "()")]
+    Unit,
     #[display(fmt = "{}", _0)]
     Bool(bool),
     #[display(fmt = "{}", _0)]
@@ -94,6 +114,7 @@ impl From<f64> for Lit {
 impl From<ast::Lit> for Lit {
     fn from(other: ast::Lit) -> Self {
         match other {
+            ast::Lit::Unit => Self::Unit,
             ast::Lit::Int(x) => Self::Int(x),
             ast::Lit::Float(x) => Self::Float(x),
             ast::Lit::Bool(x) => Self::Bool(x),
@@ -101,11 +122,152 @@ impl From<ast::Lit> for Lit {
     }
 }
 
+/// The operators of `ast::Binop`, plus those with no syntax of their own
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Binop {
+    IntAdd,
+    IntSub,
+    IntMul,
+    IntDiv,
+    IntLt,
+    IntLeq,
+    IntGt,
+    IntGeq,
+    IntAnd,
+    IntOr,
+    IntXor,
+    IntShl,
+    IntShr,
+    FloatAdd,
+    FloatSub,
+    FloatMul,
+    FloatDiv,
+    FloatLt,
+    FloatLeq,
+    FloatGt,
+    FloatGeq,
+    /// `pow x y` is `x` raised to the power `y`. See `is_builtin`
+    FloatPow,
+    Eq,
+    Neq,
+}
+
+/// The operators of `ast::Unop`, plus those with no syntax of their own
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Unop {
+    Not,
+    IntNeg,
+    FloatNeg,
+    /// The square root. See `is_builtin`
+    FloatSqrt,
+    /// The absolute value. See `is_builtin`
+    FloatAbs,
+    /// Round towards negative infinity. See `is_builtin`
+    FloatFloor,
+}
+
+impl From<ast::Binop> for Binop {
+    fn from(other: ast::Binop) -> Self {
+        match other {
+            ast::Binop::IntAdd => Self::IntAdd,
+            ast::Binop::IntSub => Self::IntSub,
+            ast::Binop::IntMul => Self::IntMul,
+            ast::Binop::IntDiv => Self::IntDiv,
+            ast::Binop::IntLt => Self::IntLt,
+            ast::Binop::IntLeq => Self::IntLeq,
+            ast::Binop::IntGt => Self::IntGt,
+            ast::Binop::IntGeq => Self::IntGeq,
+            ast::Binop::IntAnd => Self::IntAnd,
+            ast::Binop::IntOr => Self::IntOr,
+            ast::Binop::IntXor => Self::IntXor,
+            ast::Binop::IntShl => Self::IntShl,
+            ast::Binop::IntShr => Self::IntShr,
+            ast::Binop::FloatAdd => Self::FloatAdd,
+            ast::Binop::FloatSub => Self::FloatSub,
+            ast::Binop::FloatMul => Self::FloatMul,
+            ast::Binop::FloatDiv => Self::FloatDiv,
+            ast::Binop::FloatLt => Self::FloatLt,
+            ast::Binop::FloatLeq => Self::FloatLeq,
+            ast::Binop::FloatGt => Self::FloatGt,
+            ast::Binop::FloatGeq => Self::FloatGeq,
+            ast::Binop::Eq => Self::Eq,
+            ast::Binop::Neq => Self::Neq,
+        }
+    }
+}
+
+impl From<ast::Unop> for Unop {
+    fn from(other: ast::Unop) -> Self {
+        match other {
+            ast::Unop::Not => Self::Not,
+            ast::Unop::IntNeg => Self::IntNeg,
+            ast::Unop::FloatNeg => Self::FloatNeg,
+        }
+    }
+}
+
+impl Binop {
+    pub const fn symbol(self) -> &'static str {
+        match self {
+            Self::IntAdd => "+",
+            Self::IntSub => "-",
+            Self::IntMul => "*",
+            Self::IntDiv => "/",
+            Self::IntLt => "<",
+            Self::IntLeq => "<=",
+            Self::IntGt => ">",
+            Self::IntGeq => ">=",
+            Self::IntAnd => "&",
+            Self::IntOr => "|",
+            Self::IntXor => "^",
+            Self::IntShl => "<<",
+            Self::IntShr => ">>",
+            Self::FloatAdd => "+.",
+            Self::FloatSub => "-.",
+            Self::FloatMul => "*.",
+            Self::FloatDiv => "/.",
+            Self::FloatLt => "<.",
+            Self::FloatLeq => "<=.",
+            Self::FloatGt => ">.",
+            Self::FloatGeq => ">=.",
+            Self::FloatPow => "pow",
+            Self::Eq => "==",
+            Self::Neq => "!=",
+        }
+    }
+
+    /// Operators with no syntax of their own. They only appear in the bodies
+    /// of builtins linked in by the backend, and are printed as an
+    /// application of the builtin named by `symbol`
+    pub const fn is_builtin(self) -> bool {
+        matches!(self, Self::FloatPow)
+    }
+}
+
+impl Unop {
+    pub const fn symbol(self) -> &'static str {
+        match self {
+            Self::Not => "!",
+            Self::IntNeg => "-",
+            Self::FloatNeg => "-.",
+            Self::FloatSqrt => "sqrt",
+            Self::FloatAbs => "abs",
+            Self::FloatFloor => "floor",
+        }
+    }
+
+    /// See `Binop::is_builtin`
+    pub const fn is_builtin(self) -> bool {
+        matches!(self, Self::FloatSqrt | Self::FloatAbs | Self::FloatFloor)
+    }
+}
+
 impl FromStr for Expr {
     type Err = String;
     fn from_str(src: &str) -> Result<Self, Self::Err> {
-        // TODO: return a trait object instead of unwrapping
-        let ast = crate::syntax::parse(src).unwrap();
+        let ast = crate::syntax::parse(src).map_err(|err| err.to_string())?;
         Ok(Self::from_ast(ast))
     }
 }
@@ -115,12 +277,26 @@ impl Expr {
     /// Expands nested let/lambda, and attatches fresh type variables to every
     /// expression/binder
     pub fn from_ast(ast: ast::Expr) -> Self {
-        let mut gen = TypeVarGen::new();
-        Self::from_ast_inner(ast, &mut gen)
+        Self::from_ast_inner(ast.into(), &mut TypeVarGen::new(), None)
     }
 
-    fn from_ast_inner(ast: ast::Expr, gen: &mut TypeVarGen) -> Self {
-        match ast {
+    /// Like `from_ast`, but also gives the type variable attatched to the
+    /// expression at each span of the source
+    pub fn from_spanned_ast(ast: ast::Spanned<ast::Expr>) -> (Self, HashMap<ast::Span, Type>) {
+        let mut spans = HashMap::new();
+        let expr = Self::from_ast_inner(ast, &mut TypeVarGen::new(), Some(&mut spans));
+        (expr, spans)
+    }
+
+    /// The type variable of each expression is recorded in `spans`, if it is
+    /// given
+    fn from_ast_inner(
+        ast: ast::Spanned<ast::Expr>,
+        gen: &mut TypeVarGen,
+        mut spans: Option<&mut HashMap<ast::Span, Type>>,
+    ) -> Self {
+        let ast::Spanned { node, span } = ast;
+        let expr = match node {
             ast::Expr::Lit { val } => Self::Lit {
                 val: val.into(),
                 ty: gen.next(),
@@ -131,15 +307,27 @@ impl Expr {
             },
             ast::Expr::Binop { lhs, rhs, op } => Self::Binop {
                 ty: gen.next(),
-                lhs: box Self::from_ast_inner(*lhs, gen),
-                rhs: box Self::from_ast_inner(*rhs, gen),
-                op,
+                lhs: box Self::from_ast_inner(*lhs, gen, spans.as_deref_mut()),
+                rhs: box Self::from_ast_inner(*rhs, gen, spans.as_deref_mut()),
+                op: op.into(),
+            },
+            ast::Expr::Unop { op, operand } => Self::Unop {
+                ty: gen.next(),
+                op: op.into(),
+                operand: box Self::from_ast_inner(*operand, gen, spans.as_deref_mut()),
             },
             ast::Expr::If { test, then, els } => Self::If {
                 ty: gen.next(),
-                test: box Self::from_ast_inner(*test, gen),
-                then: box Self::from_ast_inner(*then, gen),
-                els: box Self::from_ast_inner(*els, gen),
+                test: box Self::from_ast_inner(*test, gen, spans.as_deref_mut()),
+                then: box Self::from_ast_inner(*then, gen, spans.as_deref_mut()),
+                // a missing `else` is `()`, which has no span in the source
+                els: box match els {
+                    Some(els) => Self::from_ast_inner(*els, gen, spans.as_deref_mut()),
+                    None => Self::Lit {
+                        val: Lit::Unit,
+                        ty: gen.next(),
+                    },
+                },
             },
             ast::Expr::Let { bindings, body } => {
                 let (binding, body) = expand_let(&bindings, *body);
@@ -148,10 +336,10 @@ impl Expr {
                     binding: LetBinding {
                         ty: gen.next(),
                         name: binding.name,
-                        ann: binding.ann,
-                        val: box Self::from_ast_inner(*binding.val, gen),
+                        ann: freshen_ann(binding.ann, gen),
+                        val: box Self::from_ast_inner(*binding.val, gen, spans.as_deref_mut()),
                     },
-                    body: box Self::from_ast_inner(body, gen),
+                    body: box Self::from_ast_inner(body, gen, spans.as_deref_mut()),
                 }
             }
             ast::Expr::Letrec { bindings, body } => Self::Letrec {
@@ -161,11 +349,11 @@ impl Expr {
                     .map(|binding| LetBinding {
                         ty: gen.next(),
                         name: binding.name,
-                        ann: binding.ann,
-                        val: box Self::from_ast_inner(*binding.val, gen),
+                        ann: freshen_ann(binding.ann, gen),
+                        val: box Self::from_ast_inner(*binding.val, gen, spans.as_deref_mut()),
                     })
                     .collect(),
-                body: box Self::from_ast_inner(*body, gen),
+                body: box Self::from_ast_inner(*body, gen, spans.as_deref_mut()),
             },
             ast::Expr::Lambda { params, body } => {
                 let (param, body) = expand_lambda(&params, *body);
@@ -173,18 +361,24 @@ impl Expr {
                     ty: gen.next(),
                     param: Param {
                         name: param.name,
-                        ann: param.ann,
+                        ann: freshen_ann(param.ann, gen),
                         ty: gen.next(),
                     },
-                    body: box Self::from_ast_inner(body, gen),
+                    body: box Self::from_ast_inner(body, gen, spans.as_deref_mut()),
                 }
             }
             ast::Expr::App { func, arg } => Self::App {
                 ty: gen.next(),
-                func: box Self::from_ast_inner(*func, gen),
-                arg: box Self::from_ast_inner(*arg, gen),
+                func: box Self::from_ast_inner(*func, gen, spans.as_deref_mut()),
+                arg: box Self::from_ast_inner(*arg, gen, spans.as_deref_mut()),
             },
+        };
+        // nodes added by expanding lets and lambdas have the same span as their
+        // body, which is visited first and so takes precedence
+        if let Some(spans) = spans {
+            spans.entry(span).or_insert_with(|| expr.ty());
         }
+        expr
     }
 
     pub fn ty(&self) -> Type {
@@ -192,6 +386,7 @@ impl Expr {
             Self::Lit { ty, .. }
             | Self::Var { ty, .. }
             | Self::Binop { ty, .. }
+            | Self::Unop { ty, .. }
             | Self::If { ty, .. }
             | Self::Let { ty, .. }
             | Self::Letrec { ty, .. }
@@ -199,38 +394,117 @@ impl Expr {
             | Self::App { ty, .. } => ty.clone(),
         }
     }
+
+    /// The immediate sub-expressions of `self`, in evaluation order
+    pub fn children(&self) -> Vec<&Self> {
+        match self {
+            Self::Lit { .. } | Self::Var { .. } => vec![],
+            Self::Binop { lhs, rhs, .. } => vec![&**lhs, &**rhs],
+            Self::Unop { operand, .. } => vec![&**operand],
+            Self::If {
+                test, then, els, ..
+            } => vec![&**test, &**then, &**els],
+            Self::Let { binding, body, .. } => vec![&*binding.val, &**body],
+            Self::Letrec { bindings, body, .. } => bindings
+                .iter()
+                .map(|binding| &*binding.val)
+                .chain(std::iter::once(&**body))
+                .collect(),
+            Self::Lambda { body, .. } => vec![&**body],
+            Self::App { func, arg, .. } => vec![&**func, &**arg],
+        }
+    }
+
+    /// Structural equality that ignores every `Type`, including the types in
+    /// annotations (but not whether there is an annotation).
+    /// Useful for comparing expressions before and after type inference
+    pub fn eq_ignoring_types(&self, other: &Self) -> bool {
+        self.map_types(|_| Type::Int) == other.map_types(|_| Type::Int)
+    }
+
+    /// Split a curried application `f a b c` into its head `f` and arguments
+    /// `[a, b, c]`. Anything other than an `App` is a head with no arguments
+    pub fn uncurry_app(&self) -> (&Self, Vec<&Self>) {
+        let mut head = self;
+        let mut args = Vec::new();
+        while let Self::App { func, arg, .. } = head {
+            args.push(&**arg);
+            head = func;
+        }
+        args.reverse();
+        (head, args)
+    }
+
+    /// Split a curried lambda `\a -> \b -> body` into its parameters `[a, b]`
+    /// and innermost body. Anything other than a `Lambda` is a body with no
+    /// parameters
+    pub fn uncurry_lambda(&self) -> (Vec<&Param>, &Self) {
+        let mut body = self;
+        let mut params = Vec::new();
+        while let Self::Lambda {
+            param, body: inner, ..
+        } = body
+        {
+            params.push(param);
+            body = inner;
+        }
+        (params, body)
+    }
 }
 
-fn expand_lambda(params: &[ast::Param], body: ast::Expr) -> (ast::Param, ast::Expr) {
+fn expand_lambda(
+    params: &[ast::Param],
+    body: ast::Spanned<ast::Expr>,
+) -> (ast::Param, ast::Spanned<ast::Expr>) {
     assert!(!params.is_empty());
     if params.len() == 1 {
         (params[0].clone(), body)
     } else {
         let param = &params[0];
         let (rest_param, rest_body) = expand_lambda(&params[1..], body);
+        // not in the source, so it takes the span of its body
+        let span = rest_body.span.clone();
         (
             param.clone(),
-            ast::Expr::Lambda {
-                params: vec![rest_param],
-                body: box rest_body,
-            },
+            ast::Spanned::new(
+                ast::Expr::Lambda {
+                    params: vec![rest_param],
+                    body: box rest_body,
+                },
+                span,
+            ),
         )
     }
 }
 
-fn expand_let(bindings: &[ast::LetBinding], body: ast::Expr) -> (ast::LetBinding, ast::Expr) {
+/// Type variables in annotations are written by the user (eg `a` is `t0`),
+/// so they would clash with the ones attached by `from_ast`. Each annotation
+/// gets its own fresh variables instead
+fn freshen_ann(ann: Option<Type>, gen: &mut TypeVarGen) -> Option<Type> {
+    ann.map(|ann| ann.freshen_vars(gen))
+}
+
+fn expand_let(
+    bindings: &[ast::LetBinding],
+    body: ast::Spanned<ast::Expr>,
+) -> (ast::LetBinding, ast::Spanned<ast::Expr>) {
     assert!(!bindings.is_empty());
     if bindings.len() == 1 {
         (bindings[0].clone(), body)
     } else {
         let binding = &bindings[0];
         let (rest_bindings, rest_body) = expand_let(&bindings[1..], body);
+        // not in the source, so it takes the span of its body
+        let span = rest_body.span.clone();
         (
             binding.clone(),
-            ast::Expr::Let {
-                bindings: vec![rest_bindings],
-                body: box rest_body,
-            },
+            ast::Spanned::new(
+                ast::Expr::Let {
+                    bindings: vec![rest_bindings],
+                    body: box rest_body,
+                },
+                span,
+            ),
         )
     }
 }
@@ -253,7 +527,7 @@ mod test {
                 ann: None,
             },
         ];
-        let body = Expr::Lit { val: Lit::Int(0) };
+        let body = Expr::Lit { val: Lit::Int(0) }.into();
 
         assert_eq!(
             expand_lambda(&params, body),
@@ -267,46 +541,219 @@ mod test {
                         name: intern("y"),
                         ann: None
                     }],
-                    body: box Expr::Lit { val: Lit::Int(0) }
+                    body: box Expr::Lit { val: Lit::Int(0) }.into()
                 }
+                .into()
             ),
         )
     }
 
+    #[track_caller]
+    fn test_binop(src: &str, expected: Binop) {
+        match super::Expr::from_str(src).unwrap() {
+            super::Expr::Binop { op, .. } => assert_eq!(op, expected),
+            expr => panic!("Expected a Binop, got {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn test_from_ast_binops() {
+        test_binop("1 + 2", Binop::IntAdd);
+        test_binop("1 - 2", Binop::IntSub);
+        test_binop("1 * 2", Binop::IntMul);
+        test_binop("1 / 2", Binop::IntDiv);
+        test_binop("1 < 2", Binop::IntLt);
+        test_binop("1 <= 2", Binop::IntLeq);
+        test_binop("1 > 2", Binop::IntGt);
+        test_binop("1 >= 2", Binop::IntGeq);
+        test_binop("1 & 2", Binop::IntAnd);
+        test_binop("1 | 2", Binop::IntOr);
+        test_binop("1 ^ 2", Binop::IntXor);
+        test_binop("1 << 2", Binop::IntShl);
+        test_binop("1 >> 2", Binop::IntShr);
+
+        test_binop("1.0 +. 2.0", Binop::FloatAdd);
+        test_binop("1.0 -. 2.0", Binop::FloatSub);
+        test_binop("1.0 *. 2.0", Binop::FloatMul);
+        test_binop("1.0 /. 2.0", Binop::FloatDiv);
+        test_binop("1.0 <. 2.0", Binop::FloatLt);
+        test_binop("1.0 <=. 2.0", Binop::FloatLeq);
+        test_binop("1.0 >. 2.0", Binop::FloatGt);
+        test_binop("1.0 >=. 2.0", Binop::FloatGeq);
+
+        test_binop("1 == 2", Binop::Eq);
+        test_binop("1.0 != 2.0", Binop::Neq);
+    }
+
+    #[test]
+    fn test_from_ast_multi_let() {
+        let expr = super::Expr::from_str("let x = 1, y = add x 1 in y").unwrap();
+        match expr {
+            super::Expr::Let {
+                binding,
+                body: box super::Expr::Let { binding: inner, .. },
+                ..
+            } => {
+                assert_eq!(binding.name, intern("x"));
+                assert_eq!(inner.name, intern("y"));
+                assert!(matches!(*inner.val, super::Expr::App { .. }));
+            }
+            expr => panic!("Expected nested Lets, got {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn test_from_ast_multi_param_lambda() {
+        let expr = super::Expr::from_str(r"\x, y -> x").unwrap();
+        match expr {
+            super::Expr::Lambda {
+                param,
+                body: box super::Expr::Lambda { param: inner, .. },
+                ..
+            } => {
+                assert_eq!(param.name, intern("x"));
+                assert_eq!(inner.name, intern("y"));
+            }
+            expr => panic!("Expected nested Lambdas, got {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn test_uncurry_app() {
+        let expr = super::Expr::from_str("f (g x) 2 true").unwrap();
+        let (head, args) = expr.uncurry_app();
+        assert!(matches!(head, super::Expr::Var { name, .. } if *name == intern("f")));
+        assert_eq!(args.len(), 3);
+        assert!(matches!(args[0], super::Expr::App { .. }));
+        assert!(matches!(args[1], super::Expr::Lit { val: super::Lit::Int(2), .. }));
+        assert!(matches!(args[2], super::Expr::Lit { val: super::Lit::Bool(true), .. }));
+
+        // the head of an application need not be a variable
+        let expr = super::Expr::from_str(r"(\x, y -> x) 1 2").unwrap();
+        let (head, args) = expr.uncurry_app();
+        assert!(matches!(head, super::Expr::Lambda { .. }));
+        assert_eq!(args.len(), 2);
+
+        let expr = super::Expr::from_str("f").unwrap();
+        assert_eq!(expr.uncurry_app(), (&expr, vec![]));
+    }
+
+    #[test]
+    fn test_uncurry_lambda() {
+        let expr = super::Expr::from_str(r"\x, y, z -> x y").unwrap();
+        let (params, body) = expr.uncurry_lambda();
+        let names: Vec<_> = params.iter().map(|param| param.name).collect();
+        assert_eq!(names, vec![intern("x"), intern("y"), intern("z")]);
+        assert!(matches!(body, super::Expr::App { .. }));
+
+        let expr = super::Expr::from_str("1").unwrap();
+        assert_eq!(expr.uncurry_lambda(), (vec![], &expr));
+    }
+
+    #[test]
+    fn test_eq_ignoring_types() {
+        let src = r"letrec f = \x: Int -> if x == 0 then 0 else f (x - 1)
+                    in let y = -(1 + 2) in f y";
+        let expr1 = super::Expr::from_str(src).unwrap();
+        let ast = crate::syntax::parse(src).unwrap();
+        let expr2 = super::Expr::from_ast_inner(
+            ast.into(),
+            &mut TypeVarGen::starting_at(100),
+            None,
+        );
+        assert_ne!(expr1, expr2);
+        assert!(expr1.eq_ignoring_types(&expr2));
+        assert!(expr2.eq_ignoring_types(&expr1));
+
+        let typed = crate::types::parse_and_type(src).unwrap();
+        assert!(typed.eq_ignoring_types(&expr1));
+
+        let other = super::Expr::from_str(r"letrec f = \x -> f x in f 0").unwrap();
+        assert!(!expr1.eq_ignoring_types(&other));
+        let other = super::Expr::from_str("1 + 2").unwrap();
+        assert!(!other.eq_ignoring_types(&super::Expr::from_str("1 - 2").unwrap()));
+        assert!(!other.eq_ignoring_types(&super::Expr::from_str("1 + 3").unwrap()));
+    }
+
+    #[test]
+    fn test_children() {
+        let children = |src| {
+            let expr = super::Expr::from_str(src).unwrap();
+            expr.children().into_iter().map(super::Expr::pretty).collect::<Vec<_>>()
+        };
+
+        assert!(children("1").is_empty());
+        assert!(children("x").is_empty());
+        assert_eq!(children("if a then b else c"), vec!["a", "b", "c"]);
+        assert_eq!(children("1 + 2 * 3"), vec!["1", "2 * 3"]);
+        assert_eq!(children("-x"), vec!["x"]);
+        assert_eq!(children("let x = 1 in x"), vec!["1", "x"]);
+        assert_eq!(children(r"letrec f = \x -> x, g = \y -> y in f g"), vec![
+            r"\x -> x",
+            r"\y -> y",
+            "f g"
+        ]);
+        assert_eq!(children(r"\x -> x"), vec!["x"]);
+        assert_eq!(children("f a b"), vec!["f a", "b"]);
+    }
+
     #[test]
     fn test_expand_let() {
         let bindings = vec![
             ast::LetBinding {
                 name: intern("x"),
-                val: box Expr::Lit { val: Lit::Int(1) },
+                val: box Expr::Lit { val: Lit::Int(1) }.into(),
                 ann: None,
             },
             ast::LetBinding {
                 name: intern("y"),
-                val: box Expr::Lit { val: Lit::Int(2) },
+                val: box Expr::Lit { val: Lit::Int(2) }.into(),
                 ann: None,
             },
         ];
 
-        let body = Expr::Lit { val: Lit::Int(0) };
+        let body = Expr::Lit { val: Lit::Int(0) }.into();
 
         assert_eq!(
             expand_let(&bindings, body),
             (
                 ast::LetBinding {
                     name: intern("x"),
-                    val: box Expr::Lit { val: Lit::Int(1) },
+                    val: box Expr::Lit { val: Lit::Int(1) }.into(),
                     ann: None,
                 },
                 Expr::Let {
                     bindings: vec![ast::LetBinding {
                         name: intern("y"),
-                        val: box Expr::Lit { val: Lit::Int(2) },
+                        val: box Expr::Lit { val: Lit::Int(2) }.into(),
                         ann: None,
                     }],
-                    body: box Expr::Lit { val: Lit::Int(0) }
+                    body: box Expr::Lit { val: Lit::Int(0) }.into()
                 }
+                .into()
             ),
         )
     }
+
+    #[test]
+    fn annotation_vars_are_fresh() {
+        let expr = super::Expr::from_str(r"\x: t0 -> x").unwrap();
+        match &expr {
+            super::Expr::Lambda { ty, param, .. } => {
+                let ann = param.ann.clone().unwrap();
+                assert_ne!(ann, *ty);
+                assert_ne!(ann, param.ty);
+            }
+            expr => panic!("expected a lambda, found {:?}", expr),
+        }
+        assert_eq!(
+            crate::types::type_of(&expr).unwrap().normalize_vars(),
+            Type::Fn(box Type::Var(0), box Type::Var(0))
+        );
+
+        // `t0` in one annotation is unrelated to `t0` in another
+        let expr =
+            super::Expr::from_str(r"let f: t0 -> t0 = \x -> x + 1, g: t0 = true in g").unwrap();
+        assert_eq!(crate::types::type_of(&expr), Ok(Type::Bool));
+    }
 }
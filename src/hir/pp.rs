@@ -0,0 +1,453 @@
+use crate::hir::{Expr, LetBinding, Lit};
+use pretty::RcDoc;
+use std::fmt;
+
+/// Layout options for the pretty-printers
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PrettyConfig {
+    /// The width that lines are kept within, where possible
+    pub width: usize,
+    /// How far nested lines are indented
+    pub indent: isize,
+}
+
+impl Default for PrettyConfig {
+    fn default() -> Self {
+        Self {
+            width: 40,
+            indent: 4,
+        }
+    }
+}
+
+impl Expr {
+    pub fn to_doc(&self) -> RcDoc<()> {
+        self.to_doc_with(&PrettyConfig::default())
+    }
+
+    pub fn to_doc_with(&self, cfg: &PrettyConfig) -> RcDoc<()> {
+        self.to_doc_prec(cfg, prec::EXPR)
+    }
+
+    /// Parenthesize `self` if it binds more loosely than `min_prec`
+    fn to_doc_prec(&self, cfg: &PrettyConfig, min_prec: u8) -> RcDoc<()> {
+        let (doc, prec) = match self {
+            Self::Lit { val, .. } => (RcDoc::as_string(val), prec::ATOM),
+            Self::Var { name, .. } => (RcDoc::as_string(name), prec::ATOM),
+            Self::Binop { lhs, rhs, op, .. } if op.is_builtin() => {
+                let doc = RcDoc::text(op.symbol())
+                    .append(RcDoc::space())
+                    .append(lhs.to_doc_prec(cfg, prec::ATOM))
+                    .append(RcDoc::space())
+                    .append(rhs.to_doc_prec(cfg, prec::ATOM));
+                (doc, prec::APP)
+            }
+            Self::Binop { lhs, rhs, op, .. } => {
+                let prec = prec::binop(*op);
+                // comparisons are not associative, the rest are left associative
+                let lhs_prec = if prec == prec::COMPARE { prec + 1 } else { prec };
+                let doc = lhs
+                    .to_doc_prec(cfg, lhs_prec)
+                    .append(RcDoc::text(format!(" {} ", op.symbol())))
+                    .append(rhs.to_doc_prec(cfg, prec + 1));
+                (doc, prec)
+            }
+            Self::Unop { op, operand, .. } if op.is_builtin() => {
+                let doc = RcDoc::text(op.symbol())
+                    .append(RcDoc::space())
+                    .append(operand.to_doc_prec(cfg, prec::ATOM));
+                (doc, prec::APP)
+            }
+            Self::Unop { op, operand, .. } => {
+                // `--` starts a line comment, so `-(-1)` must not print as `--1`
+                let sep = if op.symbol().starts_with('-') && operand.starts_with_minus() {
+                    RcDoc::space()
+                } else {
+                    RcDoc::nil()
+                };
+                let doc = RcDoc::text(op.symbol())
+                    .append(sep)
+                    .append(operand.to_doc_prec(cfg, prec::UNARY));
+                (doc, prec::UNARY)
+            }
+            Self::If {
+                test, then, els, ..
+            } => {
+                let doc = RcDoc::text("if ")
+                    .append(test.to_doc_with(cfg))
+                    .append(
+                        RcDoc::line()
+                            .append(RcDoc::text("then "))
+                            .append(then.to_doc_with(cfg))
+                            .append(RcDoc::line())
+                            .append(RcDoc::text("else "))
+                            .append(els.to_doc_with(cfg))
+                            .nest(cfg.indent),
+                    )
+                    .group();
+                (doc, prec::EXPR)
+            }
+            Self::Let { binding, body, .. } => {
+                let doc = RcDoc::text("let ")
+                    .append(binding.to_doc_with(cfg))
+                    .append(RcDoc::text(" in"))
+                    .append(RcDoc::line().append(body.to_doc_with(cfg)).nest(cfg.indent))
+                    .group();
+                (doc, prec::EXPR)
+            }
+            Self::Letrec { bindings, body, .. } => {
+                let doc = RcDoc::text("letrec ")
+                    .append(
+                        RcDoc::intersperse(
+                            bindings.iter().map(|binding| binding.to_doc_with(cfg)),
+                            RcDoc::text(",").append(RcDoc::hardline()),
+                        )
+                        .nest(7),
+                    )
+                    .append(RcDoc::line())
+                    .append(RcDoc::text("in"))
+                    .append(RcDoc::line().append(body.to_doc_with(cfg)).nest(cfg.indent))
+                    .group();
+                (doc, prec::EXPR)
+            }
+            Self::Lambda { param, body, .. } => {
+                let doc = RcDoc::text("\\")
+                    .append(RcDoc::as_string(param.name))
+                    .append(RcDoc::text(" -> "))
+                    .append(body.to_doc_with(cfg));
+                (doc, prec::EXPR)
+            }
+            Self::App { .. } => {
+                let (head, args) = self.uncurry_app();
+                let doc = head
+                    .to_doc_prec(cfg, prec::APP)
+                    .append(RcDoc::space())
+                    .append(RcDoc::intersperse(
+                        args.into_iter().map(|arg| arg.to_doc_prec(cfg, prec::ATOM)),
+                        RcDoc::space(),
+                    ));
+                (doc, prec::APP)
+            }
+        };
+
+        if prec < min_prec {
+            RcDoc::text("(").append(doc).append(RcDoc::text(")"))
+        } else {
+            doc
+        }
+    }
+
+    /// Whether the unparenthesized output of `to_doc_prec(_, prec::UNARY)`
+    /// starts with a `-`
+    fn starts_with_minus(&self) -> bool {
+        match self {
+            Self::Lit {
+                val: Lit::Int(x), ..
+            } => *x < 0,
+            Self::Lit {
+                val: Lit::Float(x), ..
+            } => x.is_sign_negative(),
+            Self::Unop { op, .. } => !op.is_builtin() && op.symbol().starts_with('-'),
+            Self::App { .. } => self.uncurry_app().0.starts_with_minus(),
+            _ => false,
+        }
+    }
+
+    /// Like `to_doc`, but every expression and binder is annotated with its
+    /// type. Most useful after `infer_and_apply`
+    pub fn to_doc_typed(&self) -> RcDoc<()> {
+        self.to_doc_typed_with(&PrettyConfig::default())
+    }
+
+    pub fn to_doc_typed_with(&self, cfg: &PrettyConfig) -> RcDoc<()> {
+        let doc = match self {
+            Self::Lit { val, .. } => RcDoc::as_string(val),
+            Self::Var { name, .. } => RcDoc::as_string(name),
+            Self::Binop { lhs, rhs, op, .. } if op.is_builtin() => RcDoc::text(op.symbol())
+                .append(RcDoc::space())
+                .append(lhs.to_doc_typed_with(cfg))
+                .append(RcDoc::space())
+                .append(rhs.to_doc_typed_with(cfg)),
+            Self::Binop { lhs, rhs, op, .. } => lhs
+                .to_doc_typed_with(cfg)
+                .append(RcDoc::text(format!(" {} ", op.symbol())))
+                .append(rhs.to_doc_typed_with(cfg)),
+            Self::Unop { op, operand, .. } if op.is_builtin() => RcDoc::text(op.symbol())
+                .append(RcDoc::space())
+                .append(operand.to_doc_typed_with(cfg)),
+            Self::Unop { op, operand, .. } => {
+                RcDoc::text(op.symbol()).append(operand.to_doc_typed_with(cfg))
+            }
+            Self::If {
+                test, then, els, ..
+            } => RcDoc::text("if ")
+                .append(test.to_doc_typed_with(cfg))
+                .append(
+                    RcDoc::line()
+                        .append(RcDoc::text("then "))
+                        .append(then.to_doc_typed_with(cfg))
+                        .append(RcDoc::line())
+                        .append(RcDoc::text("else "))
+                        .append(els.to_doc_typed_with(cfg))
+                        .nest(cfg.indent),
+                )
+                .group(),
+            Self::Let { binding, body, .. } => RcDoc::text("let ")
+                .append(binding.to_doc_typed_with(cfg))
+                .append(RcDoc::text(" in"))
+                .append(RcDoc::line().append(body.to_doc_typed_with(cfg)).nest(cfg.indent))
+                .group(),
+            Self::Letrec { bindings, body, .. } => RcDoc::text("letrec ")
+                .append(
+                    RcDoc::intersperse(
+                        bindings.iter().map(|binding| binding.to_doc_typed_with(cfg)),
+                        RcDoc::text(",").append(RcDoc::hardline()),
+                    )
+                    .nest(7),
+                )
+                .append(RcDoc::line())
+                .append(RcDoc::text("in"))
+                .append(RcDoc::line().append(body.to_doc_typed_with(cfg)).nest(cfg.indent))
+                .group(),
+            Self::Lambda { param, body, .. } => RcDoc::text("\\")
+                .append(RcDoc::as_string(param.name))
+                .append(RcDoc::text(format!(": {} -> ", param.ty)))
+                .append(body.to_doc_typed_with(cfg)),
+            Self::App { func, arg, .. } => func
+                .to_doc_typed_with(cfg)
+                .append(RcDoc::space())
+                .append(arg.to_doc_typed_with(cfg)),
+        };
+
+        RcDoc::text("(")
+            .append(doc)
+            .append(RcDoc::text(format!(": {})", self.ty())))
+    }
+
+    pub fn pretty(&self) -> String {
+        self.pretty_with(&PrettyConfig::default())
+    }
+
+    pub fn pretty_with(&self, cfg: &PrettyConfig) -> String {
+        let mut w = Vec::new();
+        self.to_doc_with(cfg).render(cfg.width, &mut w).unwrap();
+        String::from_utf8(w).unwrap()
+    }
+
+    pub fn pretty_typed(&self) -> String {
+        self.pretty_typed_with(&PrettyConfig::default())
+    }
+
+    pub fn pretty_typed_with(&self, cfg: &PrettyConfig) -> String {
+        let mut w = Vec::new();
+        self.to_doc_typed_with(cfg).render(cfg.width, &mut w).unwrap();
+        String::from_utf8(w).unwrap()
+    }
+}
+
+/// Same as `pretty`
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.pretty())
+    }
+}
+
+/// Precedence levels, loosest first, following the grammar
+mod prec {
+    use crate::hir::Binop;
+
+    /// Lambdas, `if`s and `let`s extend as far to the right as possible
+    pub const EXPR: u8 = 0;
+    pub const COMPARE: u8 = 1;
+    pub const BIT_OR: u8 = 2;
+    pub const BIT_XOR: u8 = 3;
+    pub const BIT_AND: u8 = 4;
+    pub const SHIFT: u8 = 5;
+    pub const ADD: u8 = 6;
+    pub const MULT: u8 = 7;
+    pub const UNARY: u8 = 8;
+    pub const APP: u8 = 9;
+    /// Literals, variables, and parenthesized expressions
+    pub const ATOM: u8 = 10;
+
+    pub fn binop(op: Binop) -> u8 {
+        match op {
+            Binop::IntLt
+            | Binop::IntLeq
+            | Binop::IntGt
+            | Binop::IntGeq
+            | Binop::FloatLt
+            | Binop::FloatLeq
+            | Binop::FloatGt
+            | Binop::FloatGeq
+            | Binop::Eq
+            | Binop::Neq => COMPARE,
+            Binop::IntOr => BIT_OR,
+            Binop::IntXor => BIT_XOR,
+            Binop::IntAnd => BIT_AND,
+            Binop::IntShl | Binop::IntShr => SHIFT,
+            Binop::IntAdd | Binop::IntSub | Binop::FloatAdd | Binop::FloatSub => ADD,
+            Binop::IntMul | Binop::IntDiv | Binop::FloatMul | Binop::FloatDiv => MULT,
+            Binop::FloatPow => APP,
+        }
+    }
+}
+
+impl LetBinding {
+    fn to_doc_with(&self, cfg: &PrettyConfig) -> RcDoc<()> {
+        RcDoc::as_string(self.name)
+            .append(RcDoc::text(" = "))
+            .append(self.val.to_doc_with(cfg))
+    }
+
+    fn to_doc_typed_with(&self, cfg: &PrettyConfig) -> RcDoc<()> {
+        RcDoc::as_string(self.name)
+            .append(RcDoc::text(format!(": {} = ", self.ty)))
+            .append(self.val.to_doc_typed_with(cfg))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        hir::{Expr, PrettyConfig},
+        types::parse_and_type,
+    };
+    use insta::assert_snapshot;
+    use std::str::FromStr;
+
+    #[track_caller]
+    fn test_pp(src: &str) {
+        let expr = Expr::from_str(src).unwrap();
+        assert_snapshot!(expr.pretty())
+    }
+
+    #[track_caller]
+    fn test_pp_typed(src: &str) {
+        let expr = parse_and_type(src).unwrap();
+        assert_snapshot!(expr.pretty_typed())
+    }
+
+    #[test]
+    fn pp_lit() {
+        test_pp("123");
+    }
+
+    #[test]
+    fn pp_bool_roundtrip() {
+        for src in &["true", "false"] {
+            let expr = Expr::from_str(src).unwrap();
+            assert_eq!(&expr.pretty(), src);
+            assert_eq!(Expr::from_str(&expr.pretty()).unwrap(), expr);
+        }
+    }
+
+    #[test]
+    fn pp_var() {
+        test_pp("add");
+    }
+
+    #[test]
+    fn pp_if() {
+        test_pp("if true then 1 else 0");
+        test_pp(
+            "if if false then true else true
+             then if false then 1 else 0
+             else if false then 420 else 69",
+        );
+    }
+
+    #[test]
+    fn pp_app() {
+        test_pp("if not false then 1 else 0");
+        test_pp("if not (is_zero (add 1 1)) then 50 else 100");
+    }
+
+    #[test]
+    fn pp_lambda() {
+        test_pp(r"if not false then \x -> x else \x -> not x");
+        test_pp(r"if not false then \a, b -> a else \x, y -> y");
+    }
+
+    #[test]
+    fn pp_let() {
+        test_pp("let x = 5 in x");
+        test_pp(r"let id = \x -> x, first = \a, b -> a in id not (first true 1)");
+    }
+
+    #[test]
+    fn pp_letrec() {
+        test_pp(r"letrec f = \x -> f x in f 0");
+        test_pp(r"letrec f = \x -> x, g = \y -> y in f g");
+        test_pp(r"letrec f1 = \a -> a, f2 = \b -> b, f3 = \c -> c, f4 = \d -> d in f1 f2 f3 f4");
+    }
+
+    #[test]
+    fn pp_minimal_parens() {
+        test_pp("f (g x)");
+        test_pp("(f g) x");
+        test_pp("1 + 2 * 3");
+        test_pp("(1 + 2) * 3");
+    }
+
+    #[test]
+    fn pp_parens_roundtrip() {
+        for src in &[
+            "f (g x)",
+            "(1 + 2) * 3",
+            "1 - (2 - 3)",
+            "(1 < 2) == true",
+            "-(f x)",
+            "(1 | 2) & 3",
+            r"(\x -> x) 1",
+            r"f (\x -> x)",
+            "(if true then f else g) 1",
+            "(let x = 1 in x) + 1",
+            "-(-1)",
+            "-(-x)",
+            "-.(-.x)",
+            "-.(-1.5)",
+        ] {
+            let expr = Expr::from_str(src).unwrap();
+            assert_eq!(Expr::from_str(&expr.pretty()).unwrap(), expr, "{}", expr.pretty());
+        }
+    }
+
+    #[test]
+    fn pp_typed() {
+        test_pp_typed(r"\x -> x + 1");
+        test_pp_typed(r"let id = \x -> x in id");
+    }
+
+    #[test]
+    fn pp_with_config() {
+        let expr = Expr::from_str("let x = 1 in let y = 2 in let z = x + y in z * 2").unwrap();
+        let narrow = PrettyConfig {
+            width: 20,
+            indent: 4,
+        };
+        let wide = PrettyConfig {
+            width: 120,
+            indent: 4,
+        };
+
+        assert_eq!(expr.pretty_with(&wide).lines().count(), 1);
+        assert_eq!(expr.pretty_with(&narrow).lines().count(), 4);
+        assert_eq!(expr.pretty_with(&PrettyConfig::default()), expr.pretty());
+
+        let shallow = PrettyConfig {
+            indent: 2,
+            ..narrow
+        };
+        let lines: Vec<_> = expr.pretty_with(&shallow).lines().map(String::from).collect();
+        assert_eq!(lines[1], "  let y = 2 in");
+        assert_eq!(lines[3], "      z * 2");
+    }
+
+    #[test]
+    fn pp_display() {
+        let expr = parse_and_type("let x = 5 in x").unwrap();
+        assert!(format!("{}", expr).contains("let x = 5 in"));
+        assert_eq!(expr.to_string(), expr.pretty());
+    }
+}
@@ -0,0 +1,350 @@
+use crate::hir::{Expr, LetBinding, Param, Symbol, Type};
+use indexmap::IndexMap;
+
+/// Rebuild an `Expr` bottom-up.
+/// Override `fold_expr` to rewrite particular variants (calling `walk_fold`
+/// for the rest), or `fold_ty` to rewrite every type in the tree.
+/// User-written annotations are left alone
+pub trait ExprFolder {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        walk_fold(self, expr)
+    }
+
+    fn fold_ty(&mut self, ty: Type) -> Type {
+        ty
+    }
+
+    fn fold_binding(&mut self, binding: LetBinding) -> LetBinding {
+        LetBinding {
+            ty: self.fold_ty(binding.ty),
+            val: box self.fold_expr(*binding.val),
+            ..binding
+        }
+    }
+
+    fn fold_param(&mut self, param: Param) -> Param {
+        Param {
+            ty: self.fold_ty(param.ty),
+            ..param
+        }
+    }
+}
+
+/// Fold the children of `expr`
+pub fn walk_fold<F: ExprFolder + ?Sized>(folder: &mut F, expr: Expr) -> Expr {
+    match expr {
+        Expr::Lit { ty, val } => Expr::Lit {
+            ty: folder.fold_ty(ty),
+            val,
+        },
+        Expr::Var { ty, name } => Expr::Var {
+            ty: folder.fold_ty(ty),
+            name,
+        },
+        Expr::Binop { ty, lhs, rhs, op } => Expr::Binop {
+            ty: folder.fold_ty(ty),
+            lhs: box folder.fold_expr(*lhs),
+            rhs: box folder.fold_expr(*rhs),
+            op,
+        },
+        Expr::Unop { ty, op, operand } => Expr::Unop {
+            ty: folder.fold_ty(ty),
+            op,
+            operand: box folder.fold_expr(*operand),
+        },
+        Expr::If {
+            ty,
+            test,
+            then,
+            els,
+        } => Expr::If {
+            ty: folder.fold_ty(ty),
+            test: box folder.fold_expr(*test),
+            then: box folder.fold_expr(*then),
+            els: box folder.fold_expr(*els),
+        },
+        Expr::Let { ty, binding, body } => Expr::Let {
+            ty: folder.fold_ty(ty),
+            binding: folder.fold_binding(binding),
+            body: box folder.fold_expr(*body),
+        },
+        Expr::Letrec { ty, bindings, body } => Expr::Letrec {
+            ty: folder.fold_ty(ty),
+            bindings: bindings
+                .into_iter()
+                .map(|binding| folder.fold_binding(binding))
+                .collect(),
+            body: box folder.fold_expr(*body),
+        },
+        Expr::Lambda { ty, param, body } => Expr::Lambda {
+            ty: folder.fold_ty(ty),
+            param: folder.fold_param(param),
+            body: box folder.fold_expr(*body),
+        },
+        Expr::App { ty, func, arg } => Expr::App {
+            ty: folder.fold_ty(ty),
+            func: box folder.fold_expr(*func),
+            arg: box folder.fold_expr(*arg),
+        },
+    }
+}
+
+struct MapTypes<F>(F);
+
+impl<F: FnMut(&Type) -> Type> ExprFolder for MapTypes<F> {
+    fn fold_ty(&mut self, ty: Type) -> Type {
+        (self.0)(&ty)
+    }
+
+    fn fold_binding(&mut self, binding: LetBinding) -> LetBinding {
+        LetBinding {
+            ty: self.fold_ty(binding.ty),
+            ann: binding.ann.map(|ann| self.fold_ty(ann)),
+            val: box self.fold_expr(*binding.val),
+            ..binding
+        }
+    }
+
+    fn fold_param(&mut self, param: Param) -> Param {
+        Param {
+            ty: self.fold_ty(param.ty),
+            ann: param.ann.map(|ann| self.fold_ty(ann)),
+            ..param
+        }
+    }
+}
+
+impl Expr {
+    /// Rebuild `self` with `f` applied to every type in the tree, including
+    /// annotations
+    pub fn map_types(&self, f: impl Fn(&Type) -> Type) -> Self {
+        MapTypes(f).fold_expr(self.clone())
+    }
+
+    /// Combine every type in the tree, including annotations, in pre-order.
+    /// The tree is only borrowed, not rebuilt
+    pub fn fold_types<B>(&self, init: B, f: impl FnMut(B, &Type) -> B) -> B {
+        let mut visitor = FoldTypes {
+            acc: Some(init),
+            f,
+        };
+        visitor.visit_expr(self);
+        visitor.acc.unwrap()
+    }
+}
+
+struct FoldTypes<B, F> {
+    /// Only `None` while `f` is running
+    acc: Option<B>,
+    f: F,
+}
+
+impl<B, F: FnMut(B, &Type) -> B> FoldTypes<B, F> {
+    fn visit_ty(&mut self, ty: &Type) {
+        let acc = self.acc.take().unwrap();
+        self.acc = Some((self.f)(acc, ty));
+    }
+
+    fn visit_ann(&mut self, ty: &Type, ann: Option<&Type>) {
+        self.visit_ty(ty);
+        if let Some(ann) = ann {
+            self.visit_ty(ann);
+        }
+    }
+}
+
+impl<B, F: FnMut(B, &Type) -> B> ExprVisitor for FoldTypes<B, F> {
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Lit { ty, .. }
+            | Expr::Var { ty, .. }
+            | Expr::Binop { ty, .. }
+            | Expr::Unop { ty, .. }
+            | Expr::If { ty, .. }
+            | Expr::App { ty, .. } => self.visit_ty(ty),
+            Expr::Let { ty, binding, .. } => {
+                self.visit_ty(ty);
+                self.visit_ann(&binding.ty, binding.ann.as_ref());
+            }
+            Expr::Letrec { ty, bindings, .. } => {
+                self.visit_ty(ty);
+                for binding in bindings {
+                    self.visit_ann(&binding.ty, binding.ann.as_ref());
+                }
+            }
+            Expr::Lambda { ty, param, .. } => {
+                self.visit_ty(ty);
+                self.visit_ann(&param.ty, param.ann.as_ref());
+            }
+        }
+        walk_visit(self, expr)
+    }
+}
+
+/// Read-only traversal of an `Expr`.
+/// Override `visit_expr` for the variants of interest, calling `walk_visit`
+/// for the rest
+pub trait ExprVisitor {
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_visit(self, expr)
+    }
+}
+
+/// Visit the children of `expr`, left to right
+pub fn walk_visit<V: ExprVisitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Lit { .. } | Expr::Var { .. } => {}
+        Expr::Binop { lhs, rhs, .. } => {
+            visitor.visit_expr(lhs);
+            visitor.visit_expr(rhs);
+        }
+        Expr::Unop { operand, .. } => visitor.visit_expr(operand),
+        Expr::If {
+            test, then, els, ..
+        } => {
+            visitor.visit_expr(test);
+            visitor.visit_expr(then);
+            visitor.visit_expr(els);
+        }
+        Expr::Let { binding, body, .. } => {
+            visitor.visit_expr(&binding.val);
+            visitor.visit_expr(body);
+        }
+        Expr::Letrec { bindings, body, .. } => {
+            for binding in bindings {
+                visitor.visit_expr(&binding.val);
+            }
+            visitor.visit_expr(body);
+        }
+        Expr::Lambda { body, .. } => visitor.visit_expr(body),
+        Expr::App { func, arg, .. } => {
+            visitor.visit_expr(func);
+            visitor.visit_expr(arg);
+        }
+    }
+}
+
+pub type FreeVars = IndexMap<Symbol, Type>;
+
+/// The variables referenced but not bound in `expr`, in order of first
+/// occurence
+pub fn free_vars(expr: &Expr) -> FreeVars {
+    let mut visitor = FreeVarsVisitor::default();
+    visitor.visit_expr(expr);
+    visitor.free
+}
+
+#[derive(Default)]
+struct FreeVarsVisitor {
+    bound: Vec<Symbol>,
+    free: FreeVars,
+}
+
+impl FreeVarsVisitor {
+    fn visit_in_scope(&mut self, names: &[Symbol], expr: &Expr) {
+        let len = self.bound.len();
+        self.bound.extend(names);
+        self.visit_expr(expr);
+        self.bound.truncate(len);
+    }
+}
+
+impl ExprVisitor for FreeVarsVisitor {
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Var { name, ty } => {
+                if !self.bound.contains(name) {
+                    self.free.entry(*name).or_insert_with(|| ty.clone());
+                }
+            }
+            Expr::Let { binding, body, .. } => {
+                self.visit_expr(&binding.val);
+                self.visit_in_scope(&[binding.name], body);
+            }
+            // the body is visited first, so that its variables come first in
+            // the closure environment
+            Expr::Letrec { bindings, body, .. } => {
+                let names: Vec<_> = bindings.iter().map(|binding| binding.name).collect();
+                self.visit_in_scope(&names, body);
+                for binding in bindings {
+                    self.visit_in_scope(&names, &binding.val);
+                }
+            }
+            Expr::Lambda { param, body, .. } => self.visit_in_scope(&[param.name], body),
+            _ => walk_visit(self, expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::parse_and_type;
+    use simple_symbol::intern;
+    use std::str::FromStr;
+
+    struct Identity;
+    impl ExprFolder for Identity {}
+
+    #[test]
+    fn identity_folder() {
+        for src in &[
+            r"let x = 5 in x + 1",
+            r"if true then \x -> x else \y -> y",
+            r"letrec f = \x -> g x, g = \y -> f y in f 0",
+        ] {
+            let expr = Expr::from_str(src).unwrap();
+            assert_eq!(Identity.fold_expr(expr.clone()), expr);
+
+            let expr = parse_and_type(src).unwrap();
+            assert_eq!(Identity.fold_expr(expr.clone()), expr);
+        }
+    }
+
+    #[test]
+    fn map_types() {
+        let src = r"let f: Bool -> Bool = \x: Bool -> x, y: t0 = 1.0 in f";
+        let expr = Expr::from_str(src).unwrap().map_types(|_| Type::Int);
+        assert!(expr.fold_types(true, |all, ty| all && *ty == Type::Int));
+
+        let anns = match expr {
+            Expr::Let {
+                binding,
+                body: box Expr::Let { binding: inner, .. },
+                ..
+            } => match *binding.val {
+                Expr::Lambda { param, .. } => vec![binding.ann, param.ann, inner.ann],
+                val => panic!("expected a lambda, found {:?}", val),
+            },
+            expr => panic!("expected a let, found {:?}", expr),
+        };
+        assert_eq!(anns, vec![Some(Type::Int); 3]);
+    }
+
+    #[test]
+    fn fold_types() {
+        let expr = Expr::from_str(r"\x: Bool -> x").unwrap();
+        // the lambda, its param and annotation, and the body
+        assert_eq!(expr.fold_types(0, |n, _| n + 1), 4);
+
+        let bools = expr.fold_types(0, |n, ty| if *ty == Type::Bool { n + 1 } else { n });
+        assert_eq!(bools, 1);
+    }
+
+    #[track_caller]
+    fn test_free_vars(src: &str, expected: &[&str]) {
+        let expr = Expr::from_str(src).unwrap();
+        let fv: Vec<_> = free_vars(&expr).keys().copied().collect();
+        let expected: Vec<_> = expected.iter().map(|name| intern(name)).collect();
+        assert_eq!(fv, expected);
+    }
+
+    #[test]
+    fn free_vars_scoping() {
+        test_free_vars(r"\x -> x", &[]);
+        test_free_vars(r"\x -> y x z y", &["y", "z"]);
+        test_free_vars(r"let x = y in x z", &["y", "z"]);
+        test_free_vars(r"let x = x in x", &["x"]);
+        test_free_vars(r"letrec f = \x -> g a, g = \y -> f b in f c", &["c", "a", "b"]);
+    }
+}
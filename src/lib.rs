@@ -7,10 +7,14 @@
 #![allow(dead_code)]
 
 pub mod codegen;
+pub mod eval;
 pub mod hir;
+pub mod opt;
 pub mod syntax;
 pub mod types;
 pub(crate) mod util;
 
+pub use util::stack::with_large_stack;
+
 #[macro_use]
 extern crate lalrpop_util;
@@ -0,0 +1,174 @@
+use crate::hir::{
+    visit::{walk_fold, ExprFolder},
+    Binop, Expr, Lit, Unop,
+};
+use std::convert::TryFrom;
+
+/// Evaluate operators whose operands are all literals, and `if`s whose test
+/// is a literal.
+/// Works bottom-up, so `1 + 2 * 3` folds to `7`. The folded literal keeps the
+/// type of the expression it replaces, so `expr` should already be typed
+pub fn fold_constants(expr: Expr) -> Expr {
+    ConstantFolder.fold_expr(expr)
+}
+
+struct ConstantFolder;
+
+impl ExprFolder for ConstantFolder {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        let expr = walk_fold(self, expr);
+        if let Some(val) = eval_operator(&expr) {
+            return Expr::Lit { ty: expr.ty(), val };
+        }
+
+        match expr {
+            Expr::If {
+                test: box Expr::Lit {
+                    val: Lit::Bool(test),
+                    ..
+                },
+                then,
+                els,
+                ..
+            } => {
+                if test {
+                    *then
+                } else {
+                    *els
+                }
+            }
+            expr => expr,
+        }
+    }
+}
+
+/// The value of an operator whose operands are all literals
+fn eval_operator(expr: &Expr) -> Option<Lit> {
+    match expr {
+        Expr::Binop {
+            lhs: box Expr::Lit { val: lhs, .. },
+            rhs: box Expr::Lit { val: rhs, .. },
+            op,
+            ..
+        } => eval_binop(*op, *lhs, *rhs),
+        Expr::Unop {
+            op,
+            operand: box Expr::Lit { val, .. },
+            ..
+        } => eval_unop(*op, *val),
+        _ => None,
+    }
+}
+
+/// `None` if the operation would fail at runtime (eg division by zero), or
+/// the operands are ill-typed
+#[allow(clippy::float_cmp)]
+fn eval_binop(op: Binop, lhs: Lit, rhs: Lit) -> Option<Lit> {
+    #![allow(clippy::enum_glob_use)]
+    use Binop::*;
+    use Lit::*;
+
+    // the shift amount is taken mod 64
+    let shift = |y: i64| u32::try_from(y & 63).unwrap();
+
+    let val = match (op, lhs, rhs) {
+        (IntAdd, Int(x), Int(y)) => Int(x.checked_add(y)?),
+        (IntSub, Int(x), Int(y)) => Int(x.checked_sub(y)?),
+        (IntMul, Int(x), Int(y)) => Int(x.checked_mul(y)?),
+        (IntDiv, Int(x), Int(y)) => Int(x.checked_div(y)?),
+        (IntLt, Int(x), Int(y)) => Bool(x < y),
+        (IntLeq, Int(x), Int(y)) => Bool(x <= y),
+        (IntGt, Int(x), Int(y)) => Bool(x > y),
+        (IntGeq, Int(x), Int(y)) => Bool(x >= y),
+        (IntAnd, Int(x), Int(y)) => Int(x & y),
+        (IntOr, Int(x), Int(y)) => Int(x | y),
+        (IntXor, Int(x), Int(y)) => Int(x ^ y),
+        (IntShl, Int(x), Int(y)) => Int(x.wrapping_shl(shift(y))),
+        (IntShr, Int(x), Int(y)) => Int(x.wrapping_shr(shift(y))),
+
+        (FloatAdd, Float(x), Float(y)) => Float(x + y),
+        (FloatSub, Float(x), Float(y)) => Float(x - y),
+        (FloatMul, Float(x), Float(y)) => Float(x * y),
+        (FloatDiv, Float(x), Float(y)) => Float(x / y),
+        (FloatPow, Float(x), Float(y)) => Float(x.powf(y)),
+        (FloatLt, Float(x), Float(y)) => Bool(x < y),
+        (FloatLeq, Float(x), Float(y)) => Bool(x <= y),
+        (FloatGt, Float(x), Float(y)) => Bool(x > y),
+        (FloatGeq, Float(x), Float(y)) => Bool(x >= y),
+
+        (Eq, Int(x), Int(y)) => Bool(x == y),
+        (Eq, Float(x), Float(y)) => Bool(x == y),
+        (Eq, Bool(x), Bool(y)) => Bool(x == y),
+        (Neq, Int(x), Int(y)) => Bool(x != y),
+        (Neq, Float(x), Float(y)) => Bool(x != y),
+        (Neq, Bool(x), Bool(y)) => Bool(x != y),
+
+        _ => return None,
+    };
+    Some(val)
+}
+
+fn eval_unop(op: Unop, val: Lit) -> Option<Lit> {
+    match (op, val) {
+        (Unop::Not, Lit::Bool(b)) => Some(Lit::Bool(!b)),
+        (Unop::IntNeg, Lit::Int(i)) => Some(Lit::Int(i.checked_neg()?)),
+        (Unop::FloatNeg, Lit::Float(f)) => Some(Lit::Float(-f)),
+        (Unop::FloatSqrt, Lit::Float(f)) => Some(Lit::Float(f.sqrt())),
+        (Unop::FloatAbs, Lit::Float(f)) => Some(Lit::Float(f.abs())),
+        (Unop::FloatFloor, Lit::Float(f)) => Some(Lit::Float(f.floor())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::parse_and_type;
+
+    #[track_caller]
+    fn test_fold(src: &str, expected: Lit) {
+        let expr = parse_and_type(src).unwrap();
+        let ty = expr.ty();
+        assert_eq!(fold_constants(expr), Expr::Lit { ty, val: expected });
+    }
+
+    #[test]
+    fn fold_arithmetic() {
+        test_fold("1 + 2 * 3", Lit::Int(7));
+        test_fold("2 * 3 < 7", Lit::Bool(true));
+        test_fold("1.5 *. 2.0", Lit::Float(3.0));
+        test_fold("!(1 == 2)", Lit::Bool(true));
+        test_fold("- (1 + 1)", Lit::Int(-2));
+        test_fold("6 & 3 | 8", Lit::Int(10));
+        test_fold("-8 >> 1", Lit::Int(-4));
+        test_fold("1 << 65", Lit::Int(2));
+        test_fold("1 >> -1", Lit::Int(0));
+    }
+
+    #[test]
+    fn fold_if() {
+        test_fold("if false then 1 else 2", Lit::Int(2));
+        test_fold("if 1 < 2 then 1 + 1 else 0", Lit::Int(2));
+    }
+
+    #[test]
+    fn fold_leaves_non_constants() {
+        for src in &[
+            "1 / 0",
+            r"\x -> x + 1",
+            "if is_zero 0 then 1 else 2",
+        ] {
+            let expr = parse_and_type(src).unwrap();
+            assert_eq!(fold_constants(expr.clone()), expr);
+        }
+
+        let expr = fold_constants(parse_and_type(r"\x -> x + (1 + 2)").unwrap());
+        match expr {
+            Expr::Lambda {
+                body: box Expr::Binop { rhs, .. },
+                ..
+            } => assert!(matches!(*rhs, Expr::Lit { val: Lit::Int(3), .. })),
+            expr => panic!("Expected a Lambda, got {:?}", expr),
+        }
+    }
+}
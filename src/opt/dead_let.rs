@@ -0,0 +1,107 @@
+use crate::hir::{
+    visit::{free_vars, walk_fold, ExprFolder},
+    Binop, Expr, Lit,
+};
+
+/// Remove `let`s whose binding is never used, and whose value can be dropped
+/// without changing the meaning of the program.
+/// Works bottom-up, so a binding that is only used by a dead binding is also
+/// removed. Running it again has no further effect
+pub fn eliminate_dead_lets(expr: Expr) -> Expr {
+    DeadLets.fold_expr(expr)
+}
+
+struct DeadLets;
+
+impl ExprFolder for DeadLets {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        match walk_fold(self, expr) {
+            Expr::Let { binding, body, .. }
+                if is_pure(&binding.val) && !free_vars(&body).contains_key(&binding.name) =>
+            {
+                *body
+            }
+            expr => expr,
+        }
+    }
+}
+
+/// Whether evaluating `expr` has no effect other than producing a value.
+/// Function calls are treated as impure, even though nothing is effectful yet.
+/// Int division traps when dividing by zero (or `i64::MIN` by -1), so it is
+/// only pure when the divisor is a literal that rules both out
+fn is_pure(expr: &Expr) -> bool {
+    match expr {
+        Expr::Lit { .. } | Expr::Var { .. } | Expr::Lambda { .. } => true,
+        Expr::Binop {
+            op: Binop::IntDiv,
+            lhs,
+            rhs,
+            ..
+        } => {
+            let safe_divisor = match **rhs {
+                Expr::Lit {
+                    val: Lit::Int(divisor),
+                    ..
+                } => divisor != 0 && divisor != -1,
+                _ => false,
+            };
+            safe_divisor && is_pure(lhs)
+        }
+        Expr::Binop { lhs, rhs, .. } => is_pure(lhs) && is_pure(rhs),
+        Expr::Unop { operand, .. } => is_pure(operand),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    #[track_caller]
+    fn test_dead_lets(src: &str, expected: &Expr) {
+        let expr = eliminate_dead_lets(Expr::from_str(src).unwrap());
+        assert_eq!(&expr, expected);
+        assert_eq!(&eliminate_dead_lets(expr), expected);
+    }
+
+    #[test]
+    fn dead_let() {
+        let expr = Expr::from_str("let unused = 5 in 10").unwrap();
+        let body = match &expr {
+            Expr::Let { body, .. } => *body.clone(),
+            _ => unreachable!(),
+        };
+        test_dead_lets("let unused = 5 in 10", &body);
+    }
+
+    #[test]
+    fn live_let() {
+        for src in &[
+            "let x = 5 in x",
+            r"let f = \x -> x in f 1",
+            "let x = 5 in let y = x in y",
+            "let x = not true in 10",
+            // may trap
+            "let z = 1 / 0 in 0",
+            r"\y -> let z = 1 / y in 0",
+            r"\x -> let z = x / -1 in 0",
+        ] {
+            test_dead_lets(src, &Expr::from_str(src).unwrap());
+        }
+    }
+
+    #[test]
+    fn dead_let_chain() {
+        // `x` is only used by `y`, which is dead
+        let expr = eliminate_dead_lets(Expr::from_str("let x = 5, y = x + 1 in 10").unwrap());
+        assert!(matches!(expr, Expr::Lit { .. }));
+    }
+
+    #[test]
+    fn dead_division() {
+        let expr = eliminate_dead_lets(Expr::from_str(r"\x -> let z = x / 2 in 0").unwrap());
+        assert!(matches!(expr, Expr::Lambda { body: box Expr::Lit { .. }, .. }));
+    }
+}
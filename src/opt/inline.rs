@@ -0,0 +1,253 @@
+use crate::hir::{
+    visit::{free_vars, walk_fold, walk_visit, ExprFolder, ExprVisitor},
+    Expr, LetBinding, Symbol,
+};
+use std::collections::HashSet;
+
+/// Lambdas bigger than this (see `Expr::size`) are never copied into more
+/// than one use site, and `let`-bound ones are never copied at all
+const MAX_INLINE_SIZE: usize = 20;
+
+/// Beta-reduce applications of lambdas, and inline small `let`-bound lambdas
+/// that are used exactly once.
+/// `(\x -> body) arg` becomes `body` with `x` replaced by `arg` if `arg` is a
+/// literal, a variable, or a lambda that is small or used at most once, or
+/// `let x = arg in body` otherwise, so that `arg` is still evaluated before
+/// `body`.
+/// Substitution does not rename binders, so `expr` should already have been
+/// renamed by `codegen::rename::rename`. Every node keeps its type
+pub fn inline(expr: Expr) -> Expr {
+    Inliner.fold_expr(expr)
+}
+
+struct Inliner;
+
+impl ExprFolder for Inliner {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        match walk_fold(self, expr) {
+            Expr::App {
+                ty,
+                func: box Expr::Lambda { param, body, .. },
+                arg,
+            } => {
+                let expr = if can_substitute(&arg, &body) && cheap_to_copy(&arg, param.name, &body)
+                {
+                    substitute(*body, param.name, &arg)
+                } else {
+                    Expr::Let {
+                        ty,
+                        binding: LetBinding {
+                            ty: param.ty,
+                            name: param.name,
+                            ann: param.ann,
+                            val: arg,
+                        },
+                        body,
+                    }
+                };
+                // the substituted body may have new redexes
+                self.fold_expr(expr)
+            }
+            Expr::Let { binding, body, .. }
+                if matches!(*binding.val, Expr::Lambda { .. })
+                    && binding.val.size() <= MAX_INLINE_SIZE
+                    && uses(binding.name, &body) == 1
+                    && can_substitute(&binding.val, &body) =>
+            {
+                let body = substitute(*body, binding.name, &binding.val);
+                self.fold_expr(body)
+            }
+            expr => expr,
+        }
+    }
+}
+
+/// Whether `val` can be copied into `body` without changing the meaning of
+/// the program: it must be cheap to duplicate, and none of its free variables
+/// may be captured by a binder in `body`
+fn can_substitute(val: &Expr, body: &Expr) -> bool {
+    match val {
+        Expr::Lit { .. } => true,
+        Expr::Var { .. } | Expr::Lambda { .. } => {
+            let bound = binders(body);
+            free_vars(val).keys().all(|name| !bound.contains(name))
+        }
+        _ => false,
+    }
+}
+
+/// Whether copying `val` to every use of `name` in `body` does not blow up the
+/// size of the program
+fn cheap_to_copy(val: &Expr, name: Symbol, body: &Expr) -> bool {
+    !matches!(val, Expr::Lambda { .. }) || val.size() <= MAX_INLINE_SIZE || uses(name, body) <= 1
+}
+
+/// Replace the free occurences of `name` in `expr` with `val`
+fn substitute(expr: Expr, name: Symbol, val: &Expr) -> Expr {
+    struct Substitute<'a> {
+        name: Symbol,
+        val: &'a Expr,
+    }
+
+    impl ExprFolder for Substitute<'_> {
+        fn fold_expr(&mut self, expr: Expr) -> Expr {
+            match expr {
+                Expr::Var { name, .. } if name == self.name => self.val.clone(),
+                Expr::Let { ty, binding, body } if binding.name == self.name => Expr::Let {
+                    ty,
+                    binding: LetBinding {
+                        val: box self.fold_expr(*binding.val),
+                        ..binding
+                    },
+                    body,
+                },
+                Expr::Letrec { ref bindings, .. }
+                    if bindings.iter().any(|binding| binding.name == self.name) =>
+                {
+                    expr
+                }
+                Expr::Lambda { ref param, .. } if param.name == self.name => expr,
+                _ => walk_fold(self, expr),
+            }
+        }
+    }
+
+    Substitute { name, val }.fold_expr(expr)
+}
+
+/// The number of free occurences of `name` in `expr`
+fn uses(name: Symbol, expr: &Expr) -> usize {
+    struct Uses {
+        name: Symbol,
+        count: usize,
+    }
+
+    impl ExprVisitor for Uses {
+        fn visit_expr(&mut self, expr: &Expr) {
+            match expr {
+                Expr::Var { name, .. } if *name == self.name => self.count += 1,
+                Expr::Let { binding, .. } if binding.name == self.name => {
+                    self.visit_expr(&binding.val)
+                }
+                Expr::Letrec { bindings, .. }
+                    if bindings.iter().any(|binding| binding.name == self.name) => {}
+                Expr::Lambda { param, .. } if param.name == self.name => {}
+                _ => walk_visit(self, expr),
+            }
+        }
+    }
+
+    let mut visitor = Uses { name, count: 0 };
+    visitor.visit_expr(expr);
+    visitor.count
+}
+
+/// The names bound anywhere in `expr`
+fn binders(expr: &Expr) -> HashSet<Symbol> {
+    struct Binders(HashSet<Symbol>);
+
+    impl ExprVisitor for Binders {
+        fn visit_expr(&mut self, expr: &Expr) {
+            match expr {
+                Expr::Let { binding, .. } => {
+                    self.0.insert(binding.name);
+                }
+                Expr::Letrec { bindings, .. } => {
+                    self.0.extend(bindings.iter().map(|binding| binding.name));
+                }
+                Expr::Lambda { param, .. } => {
+                    self.0.insert(param.name);
+                }
+                _ => {}
+            }
+            walk_visit(self, expr)
+        }
+    }
+
+    let mut visitor = Binders(HashSet::new());
+    visitor.visit_expr(expr);
+    visitor.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        codegen::rename::rename,
+        hir::Lit,
+        opt::fold_constants,
+        types::{parse_and_type, type_of},
+    };
+    use std::str::FromStr;
+
+    #[track_caller]
+    fn test_inline(src: &str, expected: &str) {
+        let expr = rename(parse_and_type(src).unwrap());
+        let inlined = inline(expr.clone());
+        assert_eq!(inlined.pretty(), Expr::from_str(expected).unwrap().pretty());
+        assert_eq!(inlined.ty(), expr.ty());
+        assert_eq!(type_of(&inlined).unwrap(), expr.ty());
+    }
+
+    #[test]
+    fn inline_immediate_arg() {
+        test_inline(r"(\x -> x + 1) 41", "41 + 1");
+        test_inline(r"let y = 1 in (\x -> x + y) y", "let y = 1 in y + y");
+
+        let expr = inline(rename(parse_and_type(r"(\x -> x + 1) 41").unwrap()));
+        assert!(matches!(fold_constants(expr), Expr::Lit {
+            val: Lit::Int(42),
+            ..
+        }));
+    }
+
+    #[test]
+    fn inline_complex_arg() {
+        // `1 + 2` is evaluated before the body, as it was before inlining
+        test_inline(r"(\x -> x * x) (1 + 2)", "let x = 1 + 2 in x * x");
+        test_inline(
+            r"let f = \x -> x + 1 in (\n -> n * n) (f 2)",
+            "let n = 2 + 1 in n * n",
+        );
+    }
+
+    #[test]
+    fn inline_single_use_let() {
+        test_inline(r"let inc = \x -> x + 1 in inc 2", "2 + 1");
+        test_inline(r"let add = \x, y -> x + y in add 1 2", "1 + 2");
+    }
+
+    #[test]
+    fn no_inline_multi_use_let() {
+        let src = r"let inc = \x -> x + 1 in inc (inc 2)";
+        test_inline(src, src);
+    }
+
+    #[test]
+    fn inline_lambda_arg() {
+        let big = r"\x -> x * x + x * x + x * x + x * x + x * x + x * x";
+        assert!(Expr::from_str(big).unwrap().size() > MAX_INLINE_SIZE);
+
+        // small lambdas are copied to every use, big ones only to a single use
+        test_inline(r"(\f -> f (f 1)) (\x -> x + 1)", "let x = 1 + 1 in x + 1");
+        test_inline(
+            &format!(r"(\f -> f 1) ({})", big),
+            "1 * 1 + 1 * 1 + 1 * 1 + 1 * 1 + 1 * 1 + 1 * 1",
+        );
+        test_inline(
+            &format!(r"(\f -> f (f 1)) ({})", big),
+            &format!(r"let f = {} in f (f 1)", big),
+        );
+    }
+
+    #[test]
+    fn no_inline_capture() {
+        // `x` in the argument would be captured by the `\x` in the body
+        let src = r"(\f -> \x -> f 1) (\y -> x)";
+        let expr = Expr::from_str(src).unwrap();
+        assert_eq!(
+            inline(expr).pretty(),
+            Expr::from_str(r"let f = \y -> x in \x -> f 1").unwrap().pretty()
+        );
+    }
+}
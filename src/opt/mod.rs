@@ -0,0 +1,7 @@
+mod constant_fold;
+mod dead_let;
+mod inline;
+
+pub use constant_fold::fold_constants;
+pub use dead_let::eliminate_dead_lets;
+pub use inline::inline;
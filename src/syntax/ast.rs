@@ -1,45 +1,79 @@
 pub use crate::types::ty::Type;
 use derive_more::Display;
 pub use simple_symbol::Symbol;
+use std::{fmt, ops::Range};
+
+/// A range of bytes in the source
+pub type Span = Range<usize>;
+
+/// A node, together with the range of bytes in the source that it was parsed
+/// from. The span is ignored by `==` and `{:?}`, so that trees parsed from
+/// differently laid out source (eg with comments) are still equal
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
     Lit {
         val: Lit,
     },
     Var {
+        #[cfg_attr(feature = "serde", serde(with = "crate::util::symbol_serde"))]
         name: Symbol,
     },
     Binop {
-        lhs: Box<Self>,
-        rhs: Box<Self>,
+        lhs: Box<Spanned<Self>>,
+        rhs: Box<Spanned<Self>>,
         op: Binop,
     },
+    Unop {
+        op: Unop,
+        operand: Box<Spanned<Self>>,
+    },
+    /// Without an `else`, the `then` branch must be `Unit`
     If {
-        test: Box<Self>,
-        then: Box<Self>,
-        els: Box<Self>,
+        test: Box<Spanned<Self>>,
+        then: Box<Spanned<Self>>,
+        els: Option<Box<Spanned<Self>>>,
     },
     Let {
         bindings: Vec<LetBinding>,
-        body: Box<Self>,
+        body: Box<Spanned<Self>>,
     },
     Letrec {
         bindings: Vec<LetBinding>,
-        body: Box<Self>,
+        body: Box<Spanned<Self>>,
     },
     Lambda {
         params: Vec<Param>,
-        body: Box<Self>,
+        body: Box<Spanned<Self>>,
     },
     App {
-        func: Box<Self>,
-        arg: Box<Self>,
+        func: Box<Spanned<Self>>,
+        arg: Box<Spanned<Self>>,
     },
 }
 
+/// A definition at the top level of a program. Unlike `Expr::Let` and
+/// `Expr::Letrec`, it has no body: its bindings are in scope for the rest of
+/// the program
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TopLevel {
+    Let(Vec<LetBinding>),
+    Letrec(Vec<LetBinding>),
+}
+
 #[derive(Debug, Clone, PartialEq, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Lit {
+    #[display(fmt = "()")]
+    Unit,
     #[display(fmt = "{}", _0)]
     Bool(bool),
     #[display(fmt = "{}", _0)]
@@ -49,13 +83,16 @@ pub enum Lit {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LetBinding {
+    #[cfg_attr(feature = "serde", serde(with = "crate::util::symbol_serde"))]
     pub name: Symbol,
     pub ann: Option<Type>,
-    pub val: Box<Expr>,
+    pub val: Box<Spanned<Expr>>,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Binop {
     IntAdd,
     IntSub,
@@ -65,10 +102,22 @@ pub enum Binop {
     IntLeq,
     IntGt,
     IntGeq,
+    IntAnd,
+    IntOr,
+    IntXor,
+    /// Shift left, filling with zeros
+    IntShl,
+    /// Arithmetic shift right, filling with copies of the sign bit, so that
+    /// `-8 >> 1` is `-4`.
+    /// For both shifts, the shift amount is taken mod 64, so `1 << 65` is `2`
+    /// and `1 << -1` is `1 << 63`
+    IntShr,
     FloatAdd,
     FloatSub,
     FloatMul,
     FloatDiv,
+    /// Float comparisons, including `==` and `!=` on floats, follow IEEE 754:
+    /// any comparison involving NaN is false, except `!=`, which is true
     FloatLt,
     FloatLeq,
     FloatGt,
@@ -77,12 +126,55 @@ pub enum Binop {
     Neq,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Unop {
+    Not,
+    IntNeg,
+    FloatNeg,
+}
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Param {
+    #[cfg_attr(feature = "serde", serde(with = "crate::util::symbol_serde"))]
     pub name: Symbol,
     pub ann: Option<Type>,
 }
 
+impl<T> Spanned<T> {
+    pub const fn new(node: T, span: Span) -> Self {
+        Self { node, span }
+    }
+}
+
+impl<T> std::ops::Deref for Spanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.node
+    }
+}
+
+impl<T: PartialEq> PartialEq for Spanned<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Spanned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.node.fmt(f)
+    }
+}
+
+/// For trees that were not parsed from source, eg in tests. The span is empty
+impl<T> From<T> for Spanned<T> {
+    fn from(node: T) -> Self {
+        Self { node, span: 0..0 }
+    }
+}
+
 impl From<bool> for Lit {
     fn from(other: bool) -> Self {
         Self::Bool(other)
@@ -0,0 +1,75 @@
+//! Comments are stripped before lexing, since the lalrpop lexer cannot skip
+//! them. Comment text is replaced with spaces (newlines are kept), so that
+//! locations in the stripped source are also valid in the original source.
+//! `-- line comment`
+//! `{- block comment, {- which can be nested -} -}`
+
+pub fn strip_comments(src: &str) -> Result<String, &'static str> {
+    let mut out = String::with_capacity(src.len());
+    let mut chars = src.chars().peekable();
+    let mut depth = 0;
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('-')) => {
+                chars.next();
+                depth += 1;
+                out.push_str("  ");
+            }
+            ('-', Some('}')) if depth > 0 => {
+                chars.next();
+                depth -= 1;
+                out.push_str("  ");
+            }
+            ('-', Some('-')) if depth == 0 => {
+                out.push(' ');
+                while let Some(&c) = chars.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    chars.next();
+                    blank(&mut out, c);
+                }
+            }
+            _ if depth > 0 => blank(&mut out, c),
+            _ => out.push(c),
+        }
+    }
+
+    if depth > 0 {
+        Err("unterminated block comment")
+    } else {
+        Ok(out)
+    }
+}
+
+/// Replace `c` with whitespace of the same length in bytes
+fn blank(out: &mut String, c: char) {
+    if c.is_whitespace() && c.is_ascii() {
+        out.push(c);
+    } else {
+        out.extend(std::iter::repeat(' ').take(c.len_utf8()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn strip_line_comments() {
+        assert_eq!(strip_comments("1 -- ignore\n+ 2").unwrap(), "1          \n+ 2");
+        assert_eq!(strip_comments("1 -- no newline").unwrap(), "1              ");
+        assert_eq!(strip_comments("1 --").unwrap(), "1   ");
+    }
+
+    #[test]
+    fn strip_block_comments() {
+        assert_eq!(strip_comments("1 {- x -} 2").unwrap(), "1         2");
+        assert_eq!(strip_comments("{- -> -- -}1").unwrap(), "           1");
+        assert_eq!(strip_comments("{- {- -} -}1").unwrap(), "           1");
+        assert_eq!(strip_comments("{-\n-}1").unwrap(), "  \n  1");
+        assert_eq!(strip_comments("{- λ -}").unwrap().len(), "{- λ -}".len());
+        assert!(strip_comments("{- {- -}").is_err());
+    }
+}
@@ -1,14 +1,168 @@
 pub mod ast;
+mod comments;
+pub mod scope;
+pub mod source_map;
 
 #[cfg(test)]
 mod test;
 
-pub type ParseError<'a> =
-    lalrpop_util::ParseError<usize, lalrpop_util::lexer::Token<'a>, &'static str>;
+use self::{
+    ast::Span,
+    source_map::{Diagnostic, SourceMap},
+};
+use derive_more::Display;
+use lalrpop_util::lexer::Token;
+use std::str::FromStr;
+
+pub type ParseError<'a> = lalrpop_util::ParseError<usize, Token<'a>, SyntaxError>;
+
+/// Errors found by the grammar's actions, rather than by the parser itself
+#[derive(Debug, Clone, PartialEq, Eq, Display)]
+pub enum SyntaxError {
+    /// An int literal that does not fit in 64 bits, or a float literal that
+    /// is too large to be finite
+    #[display(fmt = "literal `{}` is out of range", text)]
+    LiteralOutOfRange { text: String, span: Span },
+    #[display(fmt = "{}", _0)]
+    Other(&'static str),
+}
+
+impl std::error::Error for SyntaxError {}
 
 pub fn parse(src: &str) -> Result<ast::Expr, ParseError> {
+    let stripped = strip_comments(src)?;
     let parser = grammar::ExprParser::new();
-    parser.parse(src)
+    parser
+        .parse(&stripped)
+        .map_err(|err| relocate_error(src, err))
+}
+
+/// Like `parse`, but also gives the span of the whole expression, which does
+/// not include any whitespace or comments around it
+pub fn parse_spanned(src: &str) -> Result<ast::Spanned<ast::Expr>, ParseError> {
+    let stripped = strip_comments(src)?;
+    let parser = grammar::SpannedExprParser::new();
+    parser
+        .parse(&stripped)
+        .map_err(|err| relocate_error(src, err))
+}
+
+/// Like `parse`, but errors are described with their line and column, and the
+/// offending source
+pub fn parse_with_locations(src: &str) -> Result<ast::Expr, Diagnostic> {
+    parse(src).map_err(|err| SourceMap::new(src).diagnose(&err))
+}
+
+/// Parse a program: a sequence of top-level `let` and `letrec` definitions,
+/// each of which can refer to the ones before it
+pub fn parse_program(src: &str) -> Result<Vec<ast::TopLevel>, ParseError> {
+    let stripped = strip_comments(src)?;
+    let parser = grammar::ProgramParser::new();
+    parser
+        .parse(&stripped)
+        .map_err(|err| relocate_error(src, err))
+}
+
+/// Nest the definitions of `program` into a single expression, whose value is
+/// the last variable defined. `None` if `program` is empty
+pub fn desugar_program(program: Vec<ast::TopLevel>) -> Option<ast::Expr> {
+    // there is no source for the synthesized nodes, so the final variable takes
+    // the span of its definition, and each `let` spans from its first binding
+    // to the end of the program
+    let (last, span) = match program.last()? {
+        ast::TopLevel::Let(bindings) | ast::TopLevel::Letrec(bindings) => {
+            let binding = bindings.last()?;
+            (binding.name, binding.val.span.clone())
+        }
+    };
+    let body = ast::Spanned::new(ast::Expr::Var { name: last }, span);
+
+    let expr = program.into_iter().rev().fold(body, |body, def| {
+        let start = match &def {
+            ast::TopLevel::Let(bindings) | ast::TopLevel::Letrec(bindings) => bindings
+                .first()
+                .map_or(body.span.start, |binding| binding.val.span.start),
+        };
+        let span = start..body.span.end;
+        let node = match def {
+            ast::TopLevel::Let(bindings) => ast::Expr::Let {
+                bindings,
+                body: box body,
+            },
+            ast::TopLevel::Letrec(bindings) => ast::Expr::Letrec {
+                bindings,
+                body: box body,
+            },
+        };
+        ast::Spanned::new(node, span)
+    });
+    Some(expr.node)
+}
+
+pub fn parse_type(src: &str) -> Result<ast::Type, ParseError> {
+    let stripped = strip_comments(src)?;
+    let parser = grammar::TypeParser::new();
+    parser
+        .parse(&stripped)
+        .map_err(|err| relocate_error(src, err))
+}
+
+/// Parse an int literal at `span`. Literals other than decimal have a radix
+/// prefix (eg `-0xff`), which is not handled by `i64::from_str_radix`
+fn parse_int(src: &str, radix: u32, span: Span) -> Result<i64, ParseError<'static>> {
+    let (sign, unsigned) = match src.as_bytes()[0] {
+        b'-' | b'+' => src.split_at(1),
+        _ => ("", src),
+    };
+    let unprefixed = if radix == 10 { unsigned } else { &unsigned[2..] };
+    let digits = format!("{}{}", sign, unprefixed.replace('_', ""));
+    i64::from_str_radix(&digits, radix).map_err(|_| out_of_range(src, span))
+}
+
+/// Parse a float literal at `span`. Rather than rounding to infinity,
+/// literals that are too large are rejected
+fn parse_float(src: &str, span: Span) -> Result<f64, ParseError<'static>> {
+    let val = f64::from_str(&src.replace('_', "")).unwrap();
+    if val.is_finite() {
+        Ok(val)
+    } else {
+        Err(out_of_range(src, span))
+    }
+}
+
+fn out_of_range(src: &str, span: Span) -> ParseError<'static> {
+    ParseError::User {
+        error: SyntaxError::LiteralOutOfRange {
+            text: src.to_owned(),
+            span,
+        },
+    }
+}
+
+fn strip_comments(src: &str) -> Result<String, ParseError> {
+    comments::strip_comments(src).map_err(|error| ParseError::User {
+        error: SyntaxError::Other(error),
+    })
+}
+
+/// Errors from parsing the comment-stripped source borrow from it. Since
+/// stripping preserves locations, the tokens can be recovered from `src`
+fn relocate_error<'a>(src: &'a str, err: ParseError) -> ParseError<'a> {
+    let relocate = |(l, Token(n, _), r): (usize, Token, usize)| (l, Token(n, &src[l..r]), r);
+    match err {
+        ParseError::InvalidToken { location } => ParseError::InvalidToken { location },
+        ParseError::UnrecognizedEOF { location, expected } => {
+            ParseError::UnrecognizedEOF { location, expected }
+        }
+        ParseError::UnrecognizedToken { token, expected } => ParseError::UnrecognizedToken {
+            token: relocate(token),
+            expected,
+        },
+        ParseError::ExtraToken { token } => ParseError::ExtraToken {
+            token: relocate(token),
+        },
+        ParseError::User { error } => ParseError::User { error },
+    }
 }
 
 lalrpop_mod!(
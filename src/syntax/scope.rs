@@ -0,0 +1,114 @@
+use crate::{
+    syntax::ast::{Expr, LetBinding},
+    types::ty::TypeEnv,
+};
+use derive_more::Display;
+use simple_symbol::Symbol;
+
+#[derive(Debug, Clone, PartialEq, Eq, Display)]
+pub enum ScopeError {
+    #[display(fmt = "Unbound variable: {}", _0)]
+    Unbound(Symbol),
+}
+
+/// Check that every variable in `expr` refers to a binder or a builtin.
+/// Unlike `collect`, does not stop at the first unbound variable
+pub fn check_scopes(expr: &Expr) -> Vec<ScopeError> {
+    let builtins = TypeEnv::default();
+    let mut scope = Vec::new();
+    let mut errors = Vec::new();
+    check_scopes_inner(expr, &builtins, &mut scope, &mut errors);
+    errors
+}
+
+fn check_scopes_inner(
+    expr: &Expr,
+    builtins: &TypeEnv,
+    scope: &mut Vec<Symbol>,
+    errors: &mut Vec<ScopeError>,
+) {
+    match expr {
+        Expr::Lit { .. } => {}
+        Expr::Var { name } => {
+            if !scope.contains(name) && builtins.get(*name).is_none() {
+                errors.push(ScopeError::Unbound(*name));
+            }
+        }
+        Expr::Binop { lhs, rhs, .. } => {
+            check_scopes_inner(lhs, builtins, scope, errors);
+            check_scopes_inner(rhs, builtins, scope, errors);
+        }
+        Expr::Unop { operand, .. } => check_scopes_inner(operand, builtins, scope, errors),
+        Expr::If { test, then, els } => {
+            check_scopes_inner(test, builtins, scope, errors);
+            check_scopes_inner(then, builtins, scope, errors);
+            if let Some(els) = els {
+                check_scopes_inner(els, builtins, scope, errors);
+            }
+        }
+        // each binding is in scope for the bindings after it
+        Expr::Let { bindings, body } => {
+            let len = scope.len();
+            for LetBinding { name, val, .. } in bindings {
+                check_scopes_inner(val, builtins, scope, errors);
+                scope.push(*name);
+            }
+            check_scopes_inner(body, builtins, scope, errors);
+            scope.truncate(len);
+        }
+        // every binding is in scope for every binding
+        Expr::Letrec { bindings, body } => {
+            let len = scope.len();
+            scope.extend(bindings.iter().map(|binding| binding.name));
+            for binding in bindings {
+                check_scopes_inner(&binding.val, builtins, scope, errors);
+            }
+            check_scopes_inner(body, builtins, scope, errors);
+            scope.truncate(len);
+        }
+        Expr::Lambda { params, body } => {
+            let len = scope.len();
+            scope.extend(params.iter().map(|param| param.name));
+            check_scopes_inner(body, builtins, scope, errors);
+            scope.truncate(len);
+        }
+        Expr::App { func, arg } => {
+            check_scopes_inner(func, builtins, scope, errors);
+            check_scopes_inner(arg, builtins, scope, errors);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::syntax::parse;
+    use simple_symbol::intern;
+
+    #[track_caller]
+    fn test_scopes(src: &str, expected: &[&str]) {
+        let expr = parse(src).unwrap();
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|name| ScopeError::Unbound(intern(name)))
+            .collect();
+        assert_eq!(check_scopes(&expr), expected);
+    }
+
+    #[test]
+    fn scope_bound() {
+        test_scopes(r"\x -> x", &[]);
+        test_scopes(r"\x, y -> x y", &[]);
+        test_scopes("let x = 1, y = x in y", &[]);
+        test_scopes(r"letrec f = \x -> g x, g = \y -> f y in f 0", &[]);
+        test_scopes("not (is_zero 0)", &[]);
+    }
+
+    #[test]
+    fn scope_unbound() {
+        test_scopes("x + y", &["x", "y"]);
+        test_scopes("let x = y, y = 1 in x", &["y"]);
+        test_scopes(r"(\x -> x) x", &["x"]);
+        test_scopes(r"let f = \x -> f x in f", &["f"]);
+    }
+}
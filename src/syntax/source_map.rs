@@ -0,0 +1,126 @@
+//! Turns byte offsets (as used by lalrpop) into human-friendly line and column
+//! numbers, and underlines the offending source for error messages.
+
+use crate::syntax::{ParseError, SyntaxError};
+use lalrpop_util::lexer::Token;
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct SourceMap<'a> {
+    src: &'a str,
+    /// The byte offset of the start of each line
+    line_starts: Vec<usize>,
+}
+
+impl<'a> SourceMap<'a> {
+    pub fn new(src: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect();
+        Self { src, line_starts }
+    }
+
+    /// The 1-based line and column (in chars) of `offset`
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+        let col = self.src[self.line_starts[line]..offset].chars().count();
+        (line + 1, col + 1)
+    }
+
+    /// The (1-based) `line`, without its newline
+    pub fn line(&self, line: usize) -> &'a str {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.src.len(), |next| next - 1);
+        &self.src[start..end]
+    }
+
+    /// The line containing `start`, with the span `start..end` underlined by
+    /// carets. Empty spans get a single caret
+    pub fn snippet(&self, start: usize, end: usize) -> String {
+        let (line, col) = self.line_col(start);
+        let text = self.line(line);
+        let len = self.src[start..end.max(start)].chars().count().max(1);
+        format!("{}\n{}{}", text, " ".repeat(col - 1), "^".repeat(len))
+    }
+
+    /// Describe `err`, with the location and underlined source if it has one
+    pub fn diagnose(&self, err: &ParseError) -> Diagnostic {
+        let (message, span) = match err {
+            ParseError::InvalidToken { location } => {
+                ("invalid token".into(), Some((*location, *location)))
+            }
+            ParseError::UnrecognizedEOF { location, .. } => {
+                ("unexpected end of input".into(), Some((*location, *location)))
+            }
+            ParseError::UnrecognizedToken {
+                token: (l, Token(_, s), r),
+                ..
+            } => (format!("unexpected token `{}`", s), Some((*l, *r))),
+            ParseError::ExtraToken {
+                token: (l, Token(_, s), r),
+            } => (format!("extra token `{}`", s), Some((*l, *r))),
+            ParseError::User {
+                error: error @ SyntaxError::LiteralOutOfRange { span, .. },
+            } => (error.to_string(), Some((span.start, span.end))),
+            ParseError::User { error } => (error.to_string(), None),
+        };
+
+        Diagnostic {
+            message,
+            location: span.map(|(start, _)| self.line_col(start)),
+            snippet: span.map(|(start, end)| self.snippet(start, end)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    /// The 1-based line and column of the error, if it has a location
+    pub location: Option<(usize, usize)>,
+    /// The source line of the error, with the error underlined
+    pub snippet: Option<String>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.location, &self.snippet) {
+            (Some((line, col)), Some(snippet)) => {
+                write!(f, "{}:{}: {}\n{}", line, col, self.message, snippet)
+            }
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn line_col() {
+        let map = SourceMap::new("let x = 1\nin\n  x");
+        assert_eq!(map.line_col(0), (1, 1));
+        assert_eq!(map.line_col(4), (1, 5));
+        assert_eq!(map.line_col(9), (1, 10));
+        assert_eq!(map.line_col(10), (2, 1));
+        assert_eq!(map.line_col(15), (3, 3));
+        assert_eq!(map.line(2), "in");
+        assert_eq!(map.line(3), "  x");
+    }
+
+    #[test]
+    fn snippet() {
+        let map = SourceMap::new("let x = 1\nin foo");
+        assert_eq!(map.snippet(13, 16), "in foo\n   ^^^");
+        assert_eq!(map.snippet(9, 9), "let x = 1\n         ^");
+    }
+}
@@ -1,4 +1,8 @@
-use crate::syntax::parse;
+use crate::syntax::{
+    ast::{Binop, Expr, Lit, TopLevel, Type, Unop},
+    desugar_program, parse, parse_program, parse_with_locations, ParseError, SyntaxError,
+};
+use lalrpop_util::lexer::Token;
 use insta::assert_debug_snapshot;
 
 #[track_caller]
@@ -35,6 +39,41 @@ fn if_then_else() {
     );
 }
 
+#[test]
+fn if_without_else() {
+    assert!(matches!(
+        parse("if true then ()").unwrap(),
+        Expr::If { els: None, .. }
+    ));
+    // an `else` belongs to the nearest `if`
+    assert_eq!(
+        parse("if a then if b then c else d"),
+        parse("if a then (if b then c else d)")
+    );
+    assert_eq!(
+        parse(r"if a then \x -> if x then y else z"),
+        parse(r"if a then (\x -> if x then y else z)")
+    );
+    assert_eq!(
+        parse("if a then let x = b in if x then c else d"),
+        parse("if a then (let x = b in if x then c else d)")
+    );
+    assert_eq!(
+        parse("if a then if b then c else if d then e"),
+        parse("if a then (if b then c else (if d then e))")
+    );
+}
+
+#[test]
+fn unit() {
+    assert_eq!(parse("()").unwrap(), Expr::Lit { val: Lit::Unit });
+    assert_eq!(parse("( )").unwrap(), Expr::Lit { val: Lit::Unit });
+    assert_eq!(
+        crate::syntax::parse_type("Unit -> Unit").unwrap(),
+        Type::Fn(box Type::Unit, box Type::Unit)
+    );
+}
+
 #[test]
 fn let_binding() {
     test_parse_ok("let x = 5 in x");
@@ -67,3 +106,322 @@ fn binops() {
     test_parse_ok("1 * 2 + 3 / 4"); // (1 * 2) + (3 / 4)
     test_parse_ok("1 + 2 * 3 - 4"); // 1 + (2 * 3) - 4
 }
+
+#[test]
+fn bitwise_ops() {
+    let int = |val| Expr::Lit { val: Lit::Int(val) };
+    let binop = |op, lhs: Expr, rhs: Expr| Expr::Binop {
+        lhs: box lhs.into(),
+        rhs: box rhs.into(),
+        op,
+    };
+
+    // `<<` binds tighter than `&`, then `^`, then `|`
+    assert_eq!(
+        parse("1 | 2 ^ 3 & 4 << 5").unwrap(),
+        binop(
+            Binop::IntOr,
+            int(1),
+            binop(
+                Binop::IntXor,
+                int(2),
+                binop(Binop::IntAnd, int(3), binop(Binop::IntShl, int(4), int(5)))
+            )
+        )
+    );
+    // comparisons bind loosest, unlike in C
+    assert_eq!(
+        parse("6 & 3 == 2").unwrap(),
+        binop(Binop::Eq, binop(Binop::IntAnd, int(6), int(3)), int(2))
+    );
+    assert_eq!(
+        parse("1 << 2 + 3").unwrap(),
+        binop(Binop::IntShl, int(1), binop(Binop::IntAdd, int(2), int(3)))
+    );
+    assert_eq!(
+        parse("8 >> 1 >> 1").unwrap(),
+        binop(Binop::IntShr, binop(Binop::IntShr, int(8), int(1)), int(1))
+    );
+}
+
+#[test]
+fn float_ops() {
+    let op = |src| match parse(src).unwrap() {
+        Expr::Binop { op, .. } => op,
+        expr => panic!("Unexpected expr: {:?}", expr),
+    };
+
+    assert_eq!(op("1.0 +. 2.0"), Binop::FloatAdd);
+    assert_eq!(op("1.0 -. 2.0"), Binop::FloatSub);
+    assert_eq!(op("1.0 *. 2.0"), Binop::FloatMul);
+    assert_eq!(op("1.0 /. 2.0"), Binop::FloatDiv);
+    assert_eq!(op("1.0 <. 2.0"), Binop::FloatLt);
+    assert_eq!(op("1.0 <=. 2.0"), Binop::FloatLeq);
+    assert_eq!(op("1.0 >. 2.0"), Binop::FloatGt);
+    assert_eq!(op("1.0 >=. 2.0"), Binop::FloatGeq);
+    assert_eq!(op("1.0 + 2.0"), Binop::IntAdd);
+
+    // the plain operators are only for Ints
+    let expr = crate::hir::Expr::from_ast(parse("1.0 + 2.0").unwrap());
+    assert!(crate::types::type_of(&expr).is_err());
+}
+
+#[test]
+fn comments() {
+    assert_eq!(parse("1 + {- x -} 2").unwrap(), parse("1 + 2").unwrap());
+    assert_eq!(parse("1 -- ignore\n + 2").unwrap(), parse("1 + 2").unwrap());
+    assert_eq!(parse("1 + 2 -- at end of file").unwrap(), parse("1 + 2").unwrap());
+    assert_eq!(parse(r"\x {- -> -- -} -> x").unwrap(), parse(r"\x -> x").unwrap());
+    assert_eq!(parse("{- {- nested -} -} 1").unwrap(), parse("1").unwrap());
+    assert!(parse("1 {- unterminated").is_err());
+}
+
+#[test]
+fn comments_error_location() {
+    match parse("{- comment -} 1 )") {
+        Err(ParseError::UnrecognizedToken {
+            token: (16, Token(_, ")"), 17),
+            ..
+        }) => {}
+        result => panic!("Unexpected result: {:?}", result),
+    }
+}
+
+#[test]
+fn negative_literals() {
+    let lit = |val| Expr::Lit { val };
+    let sub = |lhs, rhs| Expr::Binop {
+        lhs: box lit(lhs).into(),
+        rhs: box lit(rhs).into(),
+        op: Binop::IntSub,
+    };
+
+    assert_eq!(parse("-5").unwrap(), lit(Lit::Int(-5)));
+    assert_eq!(parse("-2.5").unwrap(), lit(Lit::Float(-2.5)));
+    assert_eq!(parse("1 - 2").unwrap(), sub(Lit::Int(1), Lit::Int(2)));
+    assert_eq!(parse("1 - -2").unwrap(), sub(Lit::Int(1), Lit::Int(-2)));
+}
+
+#[test]
+fn digit_separators() {
+    let lit = |val| Expr::Lit { val };
+
+    assert_eq!(parse("1_000").unwrap(), lit(Lit::Int(1000)));
+    assert_eq!(parse("-1_000_000").unwrap(), lit(Lit::Int(-1_000_000)));
+    assert_eq!(parse("3.141_592").unwrap(), lit(Lit::Float(3.141_592)));
+    assert_eq!(parse("1_0.5e1_0").unwrap(), lit(Lit::Float(10.5e10)));
+
+    for src in &["_1", "1_", "1__0", "1._5", "1.5_"] {
+        assert!(parse(src).is_err(), "{}", src);
+    }
+}
+
+#[test]
+fn radix_literals() {
+    let lit = |val| Expr::Lit {
+        val: Lit::Int(val),
+    };
+
+    assert_eq!(parse("0xFF").unwrap(), lit(255));
+    assert_eq!(parse("0o17").unwrap(), lit(15));
+    assert_eq!(parse("0b1010").unwrap(), lit(10));
+    assert_eq!(parse("-0x1_0").unwrap(), lit(-16));
+    assert_eq!(parse("0x7fff_ffff_ffff_ffff").unwrap(), lit(i64::MAX));
+    assert_eq!(parse("-0x8000_0000_0000_0000").unwrap(), lit(i64::MIN));
+
+    assert_eq!(
+        parse("0x8000_0000_0000_0000"),
+        Err(ParseError::User {
+            error: SyntaxError::LiteralOutOfRange {
+                text: "0x8000_0000_0000_0000".into(),
+                span: 0..21,
+            }
+        })
+    );
+}
+
+#[test]
+fn literals_out_of_range() {
+    let out_of_range = |text: &str, span| {
+        Err(ParseError::User {
+            error: SyntaxError::LiteralOutOfRange {
+                text: text.into(),
+                span,
+            },
+        })
+    };
+
+    assert_eq!(
+        parse("9999999999999999999999"),
+        out_of_range("9999999999999999999999", 0..22)
+    );
+    assert_eq!(
+        parse("1 + 9223372036854775808"),
+        out_of_range("9223372036854775808", 4..23)
+    );
+    assert_eq!(parse("1e999"), out_of_range("1e999", 0..5));
+    assert_eq!(
+        parse("-9223372036854775808").unwrap(),
+        Expr::Lit {
+            val: Lit::Int(i64::MIN)
+        }
+    );
+
+    let err = parse_with_locations("let x = 99999999999999999999 in x").unwrap_err();
+    assert_eq!(err.location, Some((1, 9)));
+    assert_eq!(
+        err.to_string(),
+        "1:9: literal `99999999999999999999` is out of range\n\
+         let x = 99999999999999999999 in x\n        ^^^^^^^^^^^^^^^^^^^^"
+    );
+}
+
+#[test]
+fn float_literals() {
+    let lit = |val| Expr::Lit {
+        val: Lit::Float(val),
+    };
+
+    assert_eq!(parse(".5").unwrap(), lit(0.5));
+    assert_eq!(parse("-.5").unwrap(), lit(-0.5));
+    assert_eq!(parse("1.0e-10").unwrap(), lit(1.0e-10));
+    assert_eq!(parse("1.5E+3").unwrap(), lit(1500.0));
+    assert_eq!(parse(".5e2").unwrap(), lit(50.0));
+    assert_eq!(parse("1e3").unwrap(), lit(1000.0));
+    assert_eq!(parse("1e10").unwrap(), lit(1e10));
+    assert_eq!(parse("2e-3").unwrap(), lit(0.002));
+    assert_eq!(parse("-4E+2").unwrap(), lit(-400.0));
+
+    assert_eq!(
+        parse("1.0e"),
+        Err(ParseError::User {
+            error: SyntaxError::Other("missing digits in float exponent")
+        })
+    );
+    assert_eq!(
+        parse("1.0e-"),
+        Err(ParseError::User {
+            error: SyntaxError::Other("missing digits in float exponent")
+        })
+    );
+    assert_eq!(
+        parse("1e"),
+        Err(ParseError::User {
+            error: SyntaxError::Other("missing digits in float exponent")
+        })
+    );
+}
+
+#[test]
+fn unops() {
+    let var = |name| Expr::Var {
+        name: simple_symbol::intern(name),
+    };
+    let unop = |op, operand: Expr| Expr::Unop {
+        op,
+        operand: box operand.into(),
+    };
+
+    assert_eq!(parse("!b").unwrap(), unop(Unop::Not, var("b")));
+    assert_eq!(parse("-x").unwrap(), unop(Unop::IntNeg, var("x")));
+    assert_eq!(parse("-.x").unwrap(), unop(Unop::FloatNeg, var("x")));
+    assert_eq!(
+        parse("1 - -x").unwrap(),
+        Expr::Binop {
+            lhs: box Expr::Lit { val: Lit::Int(1) }.into(),
+            rhs: box unop(Unop::IntNeg, var("x")).into(),
+            op: Binop::IntSub,
+        }
+    );
+    assert_eq!(
+        parse("!f x").unwrap(),
+        unop(
+            Unop::Not,
+            Expr::App {
+                func: box var("f").into(),
+                arg: box var("x").into(),
+            }
+        )
+    );
+}
+
+#[test]
+fn spans() {
+    match parse("1 + 22").unwrap() {
+        Expr::Binop { lhs, rhs, .. } => {
+            assert_eq!(lhs.span, 0..1);
+            assert_eq!(rhs.span, 4..6);
+        }
+        expr => panic!("Unexpected expr: {:?}", expr),
+    }
+
+    // spans are in the original source, and include parentheses
+    match parse("f {- comment -} (g x)").unwrap() {
+        Expr::App { func, arg } => {
+            assert_eq!(func.span, 0..1);
+            assert_eq!(arg.span, 16..21);
+        }
+        expr => panic!("Unexpected expr: {:?}", expr),
+    }
+
+    match parse("let x = 5 in x").unwrap() {
+        Expr::Let { bindings, body } => {
+            assert_eq!(bindings[0].val.span, 8..9);
+            assert_eq!(body.span, 13..14);
+        }
+        expr => panic!("Unexpected expr: {:?}", expr),
+    }
+}
+
+#[test]
+fn programs() {
+    let src = r"
+        let inc = \x -> x + 1
+        letrec fact = \n -> if n == 0 then 1 else n * fact (n - 1)
+        let x = 5, y = fact (inc x) -- two bindings
+    ";
+    let program = parse_program(src).unwrap();
+    assert_eq!(program.len(), 3);
+    assert!(matches!(&program[0], TopLevel::Let(bindings) if bindings.len() == 1));
+    assert!(matches!(&program[1], TopLevel::Letrec(bindings) if bindings.len() == 1));
+    assert!(matches!(&program[2], TopLevel::Let(bindings) if bindings.len() == 2));
+
+    assert_eq!(
+        desugar_program(program),
+        Some(
+            parse(
+                r"let inc = \x -> x + 1 in
+                  letrec fact = \n -> if n == 0 then 1 else n * fact (n - 1) in
+                  let x = 5, y = fact (inc x) in y"
+            )
+            .unwrap()
+        )
+    );
+
+    assert_eq!(parse_program("").unwrap(), vec![]);
+    assert_eq!(desugar_program(vec![]), None);
+    assert!(parse_program("let x = 1 in x").is_err());
+    assert!(parse_program("1").is_err());
+}
+
+#[test]
+fn typecheck_program() {
+    let program = parse_program("let one = 1 let two = one + one").unwrap();
+    let expr = crate::hir::Expr::from_ast(desugar_program(program).unwrap());
+    assert_eq!(crate::types::type_of(&expr), Ok(crate::hir::Type::Int));
+}
+
+#[test]
+fn error_locations() {
+    let err = parse_with_locations("1 +").unwrap_err();
+    assert_eq!(err.location, Some((1, 4)));
+    assert_eq!(err.to_string(), "1:4: unexpected end of input\n1 +\n   ^");
+
+    let err = parse_with_locations("let x = 1\nin x )").unwrap_err();
+    assert_eq!(err.location, Some((2, 6)));
+    assert_eq!(err.to_string(), "2:6: unexpected token `)`\nin x )\n     ^");
+
+    let err = parse_with_locations("{- unterminated").unwrap_err();
+    assert_eq!(err.location, None);
+    assert_eq!(err.to_string(), "unterminated block comment");
+}
@@ -0,0 +1,39 @@
+use crate::types::{
+    constraint::{simplify, Constraint, Reason, TaggedConstraints},
+    unify::unify,
+    TypeError,
+};
+
+/// Explain a unification failure caused by a `let` or `letrec` binding whose
+/// annotation does not match the type of its value (eg `let x: Int = true`),
+/// which would otherwise be reported as `Cannot unify Int with Bool`.
+/// Solves every constraint in `cons` except those from annotations, then adds
+/// the annotations back in the order they appear in the source. The first
+/// one that fails is the culprit.
+/// `None` if the failure has some other cause
+pub fn find_annotation_mismatch(cons: &TaggedConstraints) -> Option<TypeError> {
+    let mut anns = Vec::new();
+    let mut others = Vec::new();
+    for (con, reason) in cons {
+        match reason {
+            Reason::Annotation(binding) => anns.push((*binding, con)),
+            Reason::Operand { .. } | Reason::Other => others.push(con.clone()),
+        }
+    }
+    let mut subst = unify(&simplify(others)).ok()?;
+
+    for (binding, con) in anns {
+        match unify(&[con.apply(&subst)]) {
+            Ok(ann_subst) => subst = subst.compose(&ann_subst),
+            Err(_) => {
+                let Constraint(ann, inferred) = con;
+                return Some(TypeError::AnnotationMismatch {
+                    binding,
+                    ann: ann.apply(&subst),
+                    inferred: inferred.apply(&subst),
+                });
+            }
+        }
+    }
+    None
+}
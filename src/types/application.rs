@@ -0,0 +1,54 @@
+use crate::{
+    hir::{
+        visit::{walk_visit, ExprVisitor},
+        Expr,
+    },
+    types::{constraint::Constraint, ty::Type, unify::unify, TypeError},
+};
+
+/// Explain a unification failure caused by applying a non-function (eg `1 2`,
+/// or `(\x -> x) 1 2`), which would otherwise be reported as
+/// `Cannot unify Int with t1 -> t2`.
+/// Solves every constraint in `cons` except those from applications, then
+/// adds the applications back innermost first. The first one that fails
+/// because its function has a concrete non-function type is the culprit.
+/// `None` if the failure has some other cause
+pub fn find_non_function_app(expr: &Expr, cons: &[Constraint]) -> Option<TypeError> {
+    let mut visitor = Apps(Vec::new());
+    visitor.visit_expr(expr);
+    let apps = visitor.0;
+
+    let others: Vec<_> = cons
+        .iter()
+        .filter(|con| !apps.iter().any(|(_, app)| app == *con))
+        .cloned()
+        .collect();
+    let mut subst = unify(&others).ok()?;
+
+    for (func_ty, con) in apps {
+        match unify(&[con.apply(&subst)]) {
+            Ok(app_subst) => subst = subst.compose(&app_subst),
+            Err(_) => {
+                return match func_ty.apply(&subst) {
+                    Type::Fn(..) | Type::Var(_) => None,
+                    ty => Some(TypeError::NotAFunction(ty)),
+                };
+            }
+        }
+    }
+    None
+}
+
+/// The function type and constraint of every `App` in the tree, innermost
+/// first
+struct Apps(Vec<(Type, Constraint)>);
+
+impl ExprVisitor for Apps {
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_visit(self, expr);
+        if let Expr::App { ty, func, arg } = expr {
+            let con = Constraint(func.ty(), Type::Fn(box arg.ty(), box ty.clone()));
+            self.0.push((func.ty(), con));
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{
+    hir::{
+        visit::{walk_visit, ExprVisitor},
+        Binop, Expr,
+    },
+    types::{subst::Subst, ty::Type, TypeError},
+};
+
+/// Check that `==` and `!=` are only used on types that can be compared.
+/// Functions cannot be, so neither can anything containing them.
+/// Must be run after unification, since the operand types are only known
+/// once `subst` is applied
+pub fn check_comparable(expr: &Expr, subst: &Subst) -> Result<(), TypeError> {
+    let mut visitor = Comparable {
+        subst,
+        error: None,
+    };
+    visitor.visit_expr(expr);
+    visitor.error.map_or(Ok(()), Err)
+}
+
+struct Comparable<'a> {
+    subst: &'a Subst,
+    error: Option<TypeError>,
+}
+
+impl ExprVisitor for Comparable<'_> {
+    fn visit_expr(&mut self, expr: &Expr) {
+        if self.error.is_some() {
+            return;
+        }
+
+        if let Expr::Binop {
+            lhs,
+            op: Binop::Eq | Binop::Neq,
+            ..
+        } = expr
+        {
+            let ty = self.subst.apply_ty(&lhs.ty());
+            if !is_comparable(&ty) {
+                self.error = Some(TypeError::NotComparable(ty));
+                return;
+            }
+        }
+
+        walk_visit(self, expr)
+    }
+}
+
+/// Type variables are allowed, since they may yet be instantiated to a
+/// comparable type
+fn is_comparable(ty: &Type) -> bool {
+    match ty {
+        Type::Unit | Type::Int | Type::Bool | Type::Float | Type::Var(_) => true,
+        Type::Fn(..) => false,
+        Type::Tuple(tys) => tys.iter().all(is_comparable),
+    }
+}
@@ -1,26 +1,90 @@
 use crate::{
-    hir::{Binop, Expr},
-    types::ty::{Type, TypeEnv},
+    hir::{Binop, Expr, Symbol, Unop},
+    types::{
+        ty::{Type, TypeEnv, TypeVar, TypeVarGen},
+        TypeError,
+    },
 };
+use derive_more::Display;
+use std::collections::HashSet;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// An equation between two types, printed as `t0 = t1 -> t2`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Display)]
+#[display(fmt = "{} = {}", _0, _1)]
 pub struct Constraint(pub(crate) Type, pub(crate) Type);
 pub type Constraints = Vec<Constraint>;
 
+/// Remove constraints that are trivially satisfied (`t = t`), or that
+/// duplicate an earlier constraint (in either direction).
+/// Neither affects the result of unification, but both cost a pass over the
+/// remaining constraints
+pub fn simplify(cons: Constraints) -> Constraints {
+    let mut seen = HashSet::new();
+    cons.into_iter()
+        .filter(|Constraint(t1, t2)| {
+            t1 != t2
+                && !seen.contains(&Constraint(t2.clone(), t1.clone()))
+                && seen.insert(Constraint(t1.clone(), t2.clone()))
+        })
+        .collect()
+}
+
+/// Why a constraint was collected, so that a unification failure can be
+/// explained in terms of the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    /// An operand of a binary operator must have the type the operator
+    /// expects. `lhs` and `rhs` are the types of the operator's operands
+    Operand { lhs: Type, rhs: Type },
+    /// The value of the binding `name` must match its annotation
+    Annotation(Symbol),
+    Other,
+}
+
+pub type TaggedConstraints = Vec<(Constraint, Reason)>;
+
 /// Collect constraints, and check for unbound variables
-pub fn collect(expr: Expr) -> Constraints {
-    let tenv = TypeEnv::default();
-    collect_inner(expr, &tenv)
+pub fn collect(expr: Expr) -> Result<Constraints, TypeError> {
+    collect_in(expr, &TypeEnv::default())
+}
+
+/// Like `collect`, but with the builtins given by `tenv`
+pub fn collect_in(expr: Expr, tenv: &TypeEnv) -> Result<Constraints, TypeError> {
+    let cons = collect_tagged_in(expr, tenv)?;
+    Ok(cons.into_iter().map(|(con, _)| con).collect())
 }
 
-fn collect_inner(expr: Expr, tenv: &TypeEnv) -> Constraints {
+/// Like `collect_in`, but each constraint is tagged with why it was collected.
+/// Constraints are in pre-order: those from a node come before those from
+/// its children
+pub fn collect_tagged_in(expr: Expr, tenv: &TypeEnv) -> Result<TaggedConstraints, TypeError> {
+    // type variables for instantiating polytypes must not clash with those
+    // already in `expr` or `tenv`
+    let start = max_tvar(&expr).max(tenv.max_tvar()).map_or(0, |tvar| tvar + 1);
+    let mut gen = TypeVarGen::starting_at(start);
+    collect_inner(expr, tenv, &mut gen)
+}
+
+fn max_tvar(expr: &Expr) -> Option<TypeVar> {
+    expr.fold_types(None, |max, ty| max.max(ty.type_vars().into_iter().max()))
+}
+
+fn other(con: Constraint) -> (Constraint, Reason) {
+    (con, Reason::Other)
+}
+
+fn collect_inner(
+    expr: Expr,
+    tenv: &TypeEnv,
+    gen: &mut TypeVarGen,
+) -> Result<TaggedConstraints, TypeError> {
     #![allow(clippy::enum_glob_use)]
 
     match expr {
-        Expr::Lit { ty, val } => vec![Constraint(ty, val.ty())],
+        Expr::Lit { ty, val } => Ok(vec![other(Constraint(ty, val.ty()))]),
         Expr::Var { ty, name } => match tenv.get(name) {
-            Some(ty2) => vec![Constraint(ty, ty2.clone())],
-            None => panic!("Unbound variable: {}", name),
+            Some(poly) => Ok(vec![other(Constraint(ty, poly.instantiate(gen)))]),
+            None => Err(TypeError::Unbound(name)),
         },
         Expr::Binop { ty, lhs, rhs, op } => {
             use Binop::*;
@@ -28,23 +92,42 @@ fn collect_inner(expr: Expr, tenv: &TypeEnv) -> Constraints {
 
             let (lhs_ty, rhs_ty, out_ty) = match op {
                 IntAdd | IntSub | IntMul | IntDiv => (Int, Int, Int),
+                IntAnd | IntOr | IntXor | IntShl | IntShr => (Int, Int, Int),
                 IntLt | IntLeq | IntGt | IntGeq => (Int, Int, Bool),
 
-                FloatAdd | FloatSub | FloatMul | FloatDiv => (Float, Float, Float),
+                FloatAdd | FloatSub | FloatMul | FloatDiv | FloatPow => (Float, Float, Float),
                 FloatLt | FloatLeq | FloatGt | FloatGeq => (Float, Float, Bool),
 
                 Eq | Neq => (rhs.ty(), lhs.ty(), Bool),
             };
 
+            let operand = Reason::Operand {
+                lhs: lhs.ty(),
+                rhs: rhs.ty(),
+            };
             let mut cons = vec![
-                Constraint(lhs.ty(), lhs_ty),
-                Constraint(rhs.ty(), rhs_ty),
-                Constraint(ty, out_ty),
+                (Constraint(lhs.ty(), lhs_ty), operand.clone()),
+                (Constraint(rhs.ty(), rhs_ty), operand),
+                other(Constraint(ty, out_ty)),
             ];
 
-            cons.extend(collect_inner(*lhs, tenv));
-            cons.extend(collect_inner(*rhs, tenv));
-            cons
+            cons.extend(collect_inner(*lhs, tenv, gen)?);
+            cons.extend(collect_inner(*rhs, tenv, gen)?);
+            Ok(cons)
+        }
+        Expr::Unop { ty, op, operand } => {
+            let operand_ty = match op {
+                Unop::Not => Type::Bool,
+                Unop::IntNeg => Type::Int,
+                Unop::FloatNeg | Unop::FloatSqrt | Unop::FloatAbs | Unop::FloatFloor => Type::Float,
+            };
+
+            let mut cons = vec![
+                other(Constraint(operand.ty(), operand_ty.clone())),
+                other(Constraint(ty, operand_ty)),
+            ];
+            cons.extend(collect_inner(*operand, tenv, gen)?);
+            Ok(cons)
         }
         Expr::If {
             ty,
@@ -53,14 +136,14 @@ fn collect_inner(expr: Expr, tenv: &TypeEnv) -> Constraints {
             els,
         } => {
             let mut cons = vec![
-                Constraint(test.ty(), Type::Bool),
-                Constraint(then.ty(), ty.clone()),
-                Constraint(els.ty(), ty),
+                other(Constraint(test.ty(), Type::Bool)),
+                other(Constraint(then.ty(), ty.clone())),
+                other(Constraint(els.ty(), ty)),
             ];
-            cons.extend(collect_inner(*test, tenv));
-            cons.extend(collect_inner(*then, tenv));
-            cons.extend(collect_inner(*els, tenv));
-            cons
+            cons.extend(collect_inner(*test, tenv, gen)?);
+            cons.extend(collect_inner(*then, tenv, gen)?);
+            cons.extend(collect_inner(*els, tenv, gen)?);
+            Ok(cons)
         }
 
         Expr::Let { ty, binding, body } => {
@@ -68,57 +151,66 @@ fn collect_inner(expr: Expr, tenv: &TypeEnv) -> Constraints {
             ext_tenv.insert(binding.name, binding.ty.clone());
 
             let mut cons = vec![
-                Constraint(ty, body.ty()),
-                Constraint(binding.ty, binding.val.ty()),
+                other(Constraint(ty, body.ty())),
+                other(Constraint(binding.ty, binding.val.ty())),
             ];
 
             if let Some(ty) = binding.ann {
-                cons.push(Constraint(ty, binding.val.ty()));
+                cons.push((
+                    Constraint(ty, binding.val.ty()),
+                    Reason::Annotation(binding.name),
+                ));
             }
 
-            cons.extend(collect_inner(*binding.val, tenv));
-            cons.extend(collect_inner(*body, &ext_tenv));
-            cons
+            cons.extend(collect_inner(*binding.val, tenv, gen)?);
+            cons.extend(collect_inner(*body, &ext_tenv, gen)?);
+            Ok(cons)
         }
         Expr::Letrec { ty, bindings, body } => {
             assert!(!bindings.is_empty());
 
             let mut ext_tenv = tenv.clone();
-            let mut cons = vec![Constraint(ty, body.ty())];
+            let mut cons = vec![other(Constraint(ty, body.ty()))];
 
             for binding in &bindings {
-                cons.push(Constraint(binding.ty.clone(), binding.val.ty()));
+                cons.push(other(Constraint(binding.ty.clone(), binding.val.ty())));
+                if let Some(ty) = &binding.ann {
+                    cons.push((
+                        Constraint(ty.clone(), binding.val.ty()),
+                        Reason::Annotation(binding.name),
+                    ));
+                }
                 ext_tenv.insert(binding.name, binding.ty.clone())
             }
 
             for binding in bindings {
-                cons.extend(collect_inner(*binding.val.clone(), &ext_tenv));
+                cons.extend(collect_inner(*binding.val, &ext_tenv, gen)?);
             }
 
-            cons.extend(collect_inner(*body, &ext_tenv));
-            cons
+            cons.extend(collect_inner(*body, &ext_tenv, gen)?);
+            Ok(cons)
         }
         Expr::Lambda { ty, param, body } => {
             let mut ext_tenv = tenv.clone();
             ext_tenv.insert(param.name, param.ty.clone());
 
-            let mut cons = vec![Constraint(
+            let mut cons = vec![other(Constraint(
                 ty,
                 Type::Fn(box param.ty.clone(), box body.ty()),
-            )];
+            ))];
 
             if let Some(ty) = param.ann {
-                cons.push(Constraint(ty, param.ty))
+                cons.push(other(Constraint(ty, param.ty)))
             }
 
-            cons.extend(collect_inner(*body, &ext_tenv));
-            cons
+            cons.extend(collect_inner(*body, &ext_tenv, gen)?);
+            Ok(cons)
         }
         Expr::App { ty, func, arg } => {
-            let mut cons = vec![Constraint(func.ty(), Type::Fn(box arg.ty(), box ty))];
-            cons.extend(collect_inner(*func, tenv));
-            cons.extend(collect_inner(*arg, tenv));
-            cons
+            let mut cons = vec![other(Constraint(func.ty(), Type::Fn(box arg.ty(), box ty)))];
+            cons.extend(collect_inner(*func, tenv, gen)?);
+            cons.extend(collect_inner(*arg, tenv, gen)?);
+            Ok(cons)
         }
     }
 }
@@ -127,11 +219,12 @@ fn collect_inner(expr: Expr, tenv: &TypeEnv) -> Constraints {
 mod test {
     use super::*;
     use crate::{
-        hir::{LetBinding, Lit, Param},
+        hir::{builder::TypedExprBuilder, LetBinding, Lit, Param},
         ty,
         types::ty::TypeVarGen,
     };
     use simple_symbol::intern;
+    use std::str::FromStr;
 
     #[test]
     fn constrain_int() {
@@ -142,7 +235,7 @@ mod test {
             ty: t1.clone(),
             val: Lit::Int(1),
         };
-        assert_eq!(collect(expr), vec![Constraint(t1, Type::Int)]);
+        assert_eq!(collect(expr).unwrap(), vec![Constraint(t1, Type::Int)]);
     }
 
     #[test]
@@ -154,7 +247,7 @@ mod test {
             ty: t1.clone(),
             val: Lit::Bool(true),
         };
-        assert_eq!(collect(expr), vec![Constraint(t1, Type::Bool)]);
+        assert_eq!(collect(expr).unwrap(), vec![Constraint(t1, Type::Bool)]);
     }
 
     #[test]
@@ -166,7 +259,31 @@ mod test {
             ty: t1.clone(),
             val: Lit::Float(1.23),
         };
-        assert_eq!(collect(expr), vec![Constraint(t1, Type::Float)]);
+        assert_eq!(collect(expr).unwrap(), vec![Constraint(t1, Type::Float)]);
+    }
+
+    #[test]
+    fn tag_operands() {
+        let expr = Expr::from_str("1.0 / 2").unwrap();
+        let (lhs, rhs) = match &expr {
+            Expr::Binop { lhs, rhs, .. } => (lhs.ty(), rhs.ty()),
+            _ => unreachable!(),
+        };
+        let operand = Reason::Operand {
+            lhs: lhs.clone(),
+            rhs: rhs.clone(),
+        };
+        let cons = collect_tagged_in(expr, &TypeEnv::default()).unwrap();
+
+        // the literal `2` has the same constraint as its operand, but is not
+        // tagged as one
+        assert_eq!(cons, vec![
+            (Constraint(lhs.clone(), Type::Int), operand.clone()),
+            (Constraint(rhs.clone(), Type::Int), operand),
+            (Constraint(Type::Var(0), Type::Int), Reason::Other),
+            (Constraint(lhs, Type::Float), Reason::Other),
+            (Constraint(rhs, Type::Int), Reason::Other),
+        ]);
     }
 
     #[test]
@@ -190,7 +307,7 @@ mod test {
             },
         };
         assert_eq!(
-            collect(expr),
+            collect(expr).unwrap(),
             vec![Constraint(t0, ty![{1} => {2}]), Constraint(t2, t1)],
         );
     }
@@ -206,7 +323,7 @@ mod test {
             name: intern("not").into(),
         };
 
-        assert_eq!(collect(expr), vec![Constraint(t0, ty![Bool => Bool])]);
+        assert_eq!(collect(expr).unwrap(), vec![Constraint(t0, ty![Bool => Bool])]);
     }
 
     #[test]
@@ -230,7 +347,7 @@ mod test {
         };
 
         assert_eq!(
-            collect(expr),
+            collect(expr).unwrap(),
             vec![
                 Constraint(t1.clone(), ty![{2} => {0}]),
                 Constraint(t1.clone(), ty![Int => Int => Int]),
@@ -266,7 +383,7 @@ mod test {
         };
 
         assert_eq!(
-            collect(expr),
+            collect(expr).unwrap(),
             vec![
                 Constraint(t0, t3.clone()),
                 Constraint(t1.clone(), t2.clone()),
@@ -298,7 +415,7 @@ mod test {
         };
 
         assert_eq!(
-            collect(expr),
+            collect(expr).unwrap(),
             vec![Constraint(t0, ty![{1} => {2}]), Constraint(t2, t1)]
         );
     }
@@ -334,7 +451,7 @@ mod test {
         };
 
         assert_eq!(
-            collect(expr),
+            collect(expr).unwrap(),
             vec![
                 Constraint(t0, ty![{1} => {2}]),
                 Constraint(t2, ty![{3} => {4}]),
@@ -345,74 +462,95 @@ mod test {
 
     #[test]
     fn constrain_compose() {
-        let mut gen = TypeVarGen::new();
-        let t0 = gen.next();
-        let t1 = gen.next();
-        let t2 = gen.next();
-        let t3 = gen.next();
-        let t4 = gen.next();
-        let t5 = gen.next();
-        let t6 = gen.next();
-        let t7 = gen.next();
-        let t8 = gen.next();
-        let t9 = gen.next();
-        let t10 = gen.next();
+        let mut b = TypedExprBuilder::new();
+        let expr = b.lambda("f", |b| {
+            b.lambda("g", |b| {
+                b.lambda("x", |b| {
+                    b.app(|b| b.var("f"), |b| b.app(|b| b.var("g"), |b| b.var("x")))
+                })
+            })
+        });
 
-        let expr = Expr::Lambda {
-            ty: t0.clone(),
-            param: Param {
-                ty: t1.clone(),
-                name: intern("f").into(),
-                ann: None,
-            },
-            body: box Expr::Lambda {
-                ty: t2.clone(),
-                param: Param {
-                    ty: t3.clone(),
-                    name: intern("g").into(),
-                    ann: None,
-                },
-                body: box Expr::Lambda {
-                    ty: t4.clone(),
-                    param: Param {
-                        ty: t5.clone(),
-                        name: intern("x").into(),
-                        ann: None,
-                    },
-                    body: box Expr::App {
-                        ty: t6.clone(),
-                        func: box Expr::Var {
-                            ty: t7.clone(),
-                            name: intern("f").into(),
-                        },
-                        arg: box Expr::App {
-                            ty: t8.clone(),
-                            func: box Expr::Var {
-                                ty: t9.clone(),
-                                name: intern("g").into(),
-                            },
-                            arg: box Expr::Var {
-                                ty: t10.clone(),
-                                name: intern("x").into(),
-                            },
-                        },
-                    },
-                },
-            },
-        };
+        assert_eq!(
+            collect(expr).unwrap(),
+            vec![
+                Constraint(ty![{0}], ty![{1} => {2}]),
+                Constraint(ty![{2}], ty![{3} => {4}]),
+                Constraint(ty![{4}], ty![{5} => {6}]),
+                Constraint(ty![{7}], ty![{8} => {6}]),
+                Constraint(ty![{7}], ty![{1}]),
+                Constraint(ty![{9}], ty![{10} => {8}]),
+                Constraint(ty![{9}], ty![{3}]),
+                Constraint(ty![{10}], ty![{5}]),
+            ]
+        );
+    }
+
+    #[test]
+    fn simplify_constraints() {
+        let cons = vec![
+            Constraint(Type::Var(0), Type::Int),
+            Constraint(Type::Var(1), Type::Var(1)),
+            Constraint(Type::Var(0), Type::Int),
+            Constraint(Type::Int, Type::Var(0)),
+            Constraint(Type::Var(2), Type::Var(0)),
+        ];
+        assert_eq!(
+            simplify(cons),
+            vec![
+                Constraint(Type::Var(0), Type::Int),
+                Constraint(Type::Var(2), Type::Var(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn simplify_shrinks_constraints() {
+        use crate::types::{type_of, unify::unify};
+        use std::str::FromStr;
+
+        // `==` constrains its operands to each other in both directions
+        for src in &[r"(\x -> x) 1 == (\y -> y) 2", r"\f -> \g -> \x -> f (g x)"] {
+            let expr = Expr::from_str(src).unwrap();
+            let cons = collect(expr.clone()).unwrap();
+            let simplified = simplify(cons.clone());
+            assert!(simplified.len() <= cons.len());
+
+            let ty = unify(&simplified).unwrap().apply_ty(&expr.ty());
+            assert_eq!(ty, type_of(&expr).unwrap());
+        }
 
+        // the smallest program with a redundant constraint: `t2 = t1` is
+        // dropped as the reverse of `t1 = t2`
+        let expr = Expr::from_str("1 == 2").unwrap();
+        let cons = collect(expr).unwrap();
+        assert_eq!(cons.len(), 5);
         assert_eq!(
-            collect(expr),
+            simplify(cons),
             vec![
-                Constraint(t0.clone(), Type::Fn(box t1.clone(), box t2.clone())),
-                Constraint(t2.clone(), Type::Fn(box t3.clone(), box t4.clone())),
-                Constraint(t4.clone(), Type::Fn(box t5.clone(), box t6.clone())),
-                Constraint(t7.clone(), Type::Fn(box t8.clone(), box t6.clone())),
-                Constraint(t7.clone(), t1.clone()),
-                Constraint(t9.clone(), Type::Fn(box t10.clone(), box t8.clone())),
-                Constraint(t9.clone(), t3.clone()),
-                Constraint(t10.clone(), t5.clone()),
+                Constraint(Type::Var(1), Type::Var(2)),
+                Constraint(Type::Var(0), Type::Bool),
+                Constraint(Type::Var(1), Type::Int),
+                Constraint(Type::Var(2), Type::Int),
             ]
         );
     }
+
+    #[test]
+    fn constrain_large_letrec() {
+        use std::str::FromStr;
+
+        // f0 calls f1, ..., f19 calls f0
+        let n = 20;
+        let bindings: Vec<_> = (0..n)
+            .map(|i| format!(r"f{} = \x -> f{} x", i, (i + 1) % n))
+            .collect();
+        let src = format!("letrec {} in f0 1", bindings.join(", "));
+        let expr = Expr::from_str(&src).unwrap();
+
+        // each binding is collected exactly once: 5 constraints per binding,
+        // plus 1 for the letrec and 3 for its body
+        assert_eq!(collect(expr.clone()).unwrap().len(), 5 * n + 4);
+        assert!(crate::types::type_of(&expr).is_ok());
+    }
 }
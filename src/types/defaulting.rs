@@ -0,0 +1,70 @@
+use crate::{
+    hir::Expr,
+    types::{ty::Type, Subst},
+};
+
+/// Replace every type variable left in `expr` after inference with `Int`, so
+/// that programs with unconstrained types (eg an unused `\x -> x`) can still be
+/// compiled. `expr` should already be typed, eg by `infer_and_apply`.
+/// This is optional: without it, such programs are rejected by the backend as
+/// ambiguous
+pub fn default_type_vars(expr: &Expr) -> Expr {
+    expr.map_types(default_ty)
+}
+
+fn default_ty(ty: &Type) -> Type {
+    let mut subst = Subst::new();
+    for tvar in ty.type_vars() {
+        subst.insert(tvar, Type::Int);
+    }
+    ty.apply(&subst)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        ty,
+        types::{infer_and_apply, parse_and_type},
+    };
+    use std::str::FromStr;
+
+    fn all_monomorphic(expr: &Expr) -> bool {
+        expr.fold_types(true, |ok, ty| ok && ty.is_monomorphic())
+    }
+
+    #[test]
+    fn default_leftover_vars() {
+        let expr = parse_and_type(r"let unused = \x -> x in 5").unwrap();
+        assert!(!all_monomorphic(&expr));
+        let expr = default_type_vars(&expr);
+        assert!(all_monomorphic(&expr));
+        match expr {
+            Expr::Let { binding, .. } => assert_eq!(binding.ty, ty![Int => Int]),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn default_keeps_inferred_types() {
+        let expr = parse_and_type(r"(\x -> x) 5").unwrap();
+        assert_eq!(default_type_vars(&expr), expr);
+
+        let expr = parse_and_type(r"\x -> x +. 1.0").unwrap();
+        assert_eq!(default_type_vars(&expr), expr);
+    }
+
+    #[test]
+    fn default_does_not_hide_errors() {
+        // the types it picks for an ill-typed program do not make it type check
+        let expr = Expr::from_str(r"let f = \x -> x in f 5 == f true").unwrap();
+        let defaulted = default_type_vars(&expr);
+        assert!(all_monomorphic(&defaulted));
+        assert!(infer_and_apply(&defaulted).is_err());
+
+        // and those for a well-typed program are consistent with inference
+        let expr = parse_and_type(r"let unused = \x -> x in 5").unwrap();
+        let defaulted = default_type_vars(&expr);
+        assert_eq!(infer_and_apply(&defaulted).unwrap(), defaulted);
+    }
+}
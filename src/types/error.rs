@@ -0,0 +1,45 @@
+use crate::{
+    hir::Symbol,
+    types::ty::{Type, TypeVar},
+};
+use derive_more::Display;
+
+#[derive(Debug, Clone, PartialEq, Eq, Display)]
+pub enum TypeError {
+    #[display(fmt = "Parse error: {}", _0)]
+    Parse(String),
+    #[display(fmt = "Expected type {}, found {}", expected, actual)]
+    Mismatch { expected: Type, actual: Type },
+    #[display(fmt = "Unbound variable: {}", _0)]
+    Unbound(Symbol),
+    #[display(fmt = "Cannot unify {} with {}", _0, _1)]
+    CannotUnify(Type, Type),
+    #[display(fmt = "Circular use: t{} occurs in {}", _0, _1)]
+    Occurs(TypeVar, Type),
+    #[display(fmt = "{} is used in the definition of {} before it is defined", name, binding)]
+    ForwardReference { binding: Symbol, name: Symbol },
+    #[display(
+        fmt = "Annotation {} does not match inferred type {} for binding {}",
+        ann,
+        inferred,
+        binding
+    )]
+    AnnotationMismatch {
+        binding: Symbol,
+        ann: Type,
+        inferred: Type,
+    },
+    #[display(fmt = "Cannot compare values of type {}", _0)]
+    NotComparable(Type),
+    #[display(fmt = "Cannot apply a value of type {}, as it is not a function", _0)]
+    NotAFunction(Type),
+    #[display(
+        fmt = "Cannot use {} and {} together, as there is no implicit conversion between \
+               them. The operators for Floats end in `.`, eg `+.`, `*.` and `<.`",
+        left,
+        right
+    )]
+    NumericMismatch { left: Type, right: Type },
+}
+
+impl std::error::Error for TypeError {}
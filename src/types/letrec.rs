@@ -0,0 +1,111 @@
+use crate::{
+    hir::{
+        visit::{free_vars, walk_visit, ExprVisitor},
+        Expr, Symbol,
+    },
+    types::TypeError,
+};
+
+/// Check that no `letrec` binding uses a binding from its own group before it
+/// has been evaluated.
+/// Lambdas are fine, because their bodies are not evaluated until they are
+/// called. Any other value is evaluated straight away, so SiMPL being strict,
+/// the bindings it refers to may not exist yet
+pub fn check_forward_refs(expr: &Expr) -> Result<(), TypeError> {
+    let mut visitor = ForwardRefs(None);
+    visitor.visit_expr(expr);
+    visitor.0.map_or(Ok(()), Err)
+}
+
+struct ForwardRefs(Option<TypeError>);
+
+impl ExprVisitor for ForwardRefs {
+    fn visit_expr(&mut self, expr: &Expr) {
+        if self.0.is_some() {
+            return;
+        }
+
+        if let Expr::Letrec { bindings, .. } = expr {
+            let names: Vec<Symbol> = bindings.iter().map(|binding| binding.name).collect();
+            for binding in bindings {
+                if let Expr::Lambda { .. } = *binding.val {
+                    continue;
+                }
+                if let Some(name) = free_vars(&binding.val)
+                    .keys()
+                    .find(|name| names.contains(name))
+                {
+                    self.0 = Some(TypeError::ForwardReference {
+                        binding: binding.name,
+                        name: *name,
+                    });
+                    return;
+                }
+            }
+        }
+
+        walk_visit(self, expr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        hir::{builder::TypedExprBuilder, Binop},
+        types::type_of,
+    };
+    use simple_symbol::intern;
+    use std::str::FromStr;
+
+    #[test]
+    fn forward_reference() {
+        // letrec a = b + 1, b = 1 in a
+        let mut b = TypedExprBuilder::new();
+        let expr = b.letrec(
+            &[
+                ("a", &|b| {
+                    b.binop(Binop::IntAdd, |b| b.var("b"), |b| b.lit_int(1))
+                }),
+                ("b", &|b| b.lit_int(1)),
+            ],
+            |b| b.var("a"),
+        );
+        let err = TypeError::ForwardReference {
+            binding: intern("a"),
+            name: intern("b"),
+        };
+        assert_eq!(check_forward_refs(&expr), Err(err.clone()));
+        assert_eq!(type_of(&expr), Err(err));
+
+        // letrec a = a in a
+        let mut b = TypedExprBuilder::new();
+        let expr = b.letrec(&[("a", &|b| b.var("a"))], |b| b.var("a"));
+        assert!(matches!(
+            check_forward_refs(&expr),
+            Err(TypeError::ForwardReference { .. })
+        ));
+    }
+
+    #[test]
+    fn lambdas_are_not_forward_references() {
+        for src in &[
+            r"letrec f = \x -> g x, g = \y -> f y in f 0",
+            r"letrec f = \x -> if is_zero x then 0 else f (x - 1) in f 5",
+        ] {
+            let expr = Expr::from_str(src).unwrap();
+            assert_eq!(check_forward_refs(&expr), Ok(()));
+        }
+
+        // letrec a = 1, f = \x -> a in f 0
+        let mut b = TypedExprBuilder::new();
+        let expr = b.letrec(
+            &[
+                ("a", &|b| b.lit_int(1)),
+                ("f", &|b| b.lambda("x", |b| b.var("a"))),
+            ],
+            |b| b.app(|b| b.var("f"), |b| b.lit_int(0)),
+        );
+        assert_eq!(check_forward_refs(&expr), Ok(()));
+    }
+}
@@ -1,8 +1,23 @@
-use self::ty::Type;
-use crate::hir::Expr;
-use std::str::FromStr;
+use self::ty::{Type, TypeEnv};
+use crate::{
+    hir::Expr,
+    syntax::ast::{self, Span},
+};
+pub use constraint::{Constraint, Constraints};
+pub use defaulting::default_type_vars;
+pub use error::TypeError;
+pub use subst::Subst;
+pub use unify::{unify, unify_ordered};
+use std::{collections::HashMap, str::FromStr};
 
+mod annotation;
+mod application;
+mod comparable;
 mod constraint;
+mod defaulting;
+mod error;
+mod letrec;
+mod numeric;
 mod subst;
 pub mod ty;
 mod unify;
@@ -11,24 +26,83 @@ mod unify;
 mod test;
 
 /// Infer the type of the expr
-pub fn type_of(expr: &Expr) -> Type {
-    let cons = constraint::collect(expr.clone());
-    let subst = unify::unify(&cons);
-    subst.apply_ty(&expr.ty())
+pub fn type_of(expr: &Expr) -> Result<Type, TypeError> {
+    type_of_in(expr, &TypeEnv::default())
+}
+
+/// Infer the type of the expr, with the builtins given by `tenv`
+pub fn type_of_in(expr: &Expr, tenv: &TypeEnv) -> Result<Type, TypeError> {
+    let subst = solve(expr, tenv)?;
+    Ok(subst.apply_ty(&expr.ty()))
+}
+
+/// The constraints that inference would solve for `expr`, before they are
+/// simplified. Useful for seeing how inference works
+pub fn constraints_of(expr: &Expr) -> Result<Constraints, TypeError> {
+    constraint::collect(expr.clone())
 }
 
 /// Infer the type of the expr, and apply the resulting substitution to the
 /// expression (so every expr has its inferred type attatched)
-pub fn infer_and_apply(expr: &Expr) -> Expr {
-    let cons = constraint::collect(expr.clone());
-    let subst = unify::unify(&cons);
-    expr.apply(&subst)
+pub fn infer_and_apply(expr: &Expr) -> Result<Expr, TypeError> {
+    infer_and_apply_in(expr, &TypeEnv::default())
+}
+
+/// Like `infer_and_apply`, with the builtins given by `tenv`
+pub fn infer_and_apply_in(expr: &Expr, tenv: &TypeEnv) -> Result<Expr, TypeError> {
+    let subst = solve(expr, tenv)?;
+    Ok(expr.apply(&subst))
+}
+
+/// The substitution that inference solves for `expr`, mapping each of its
+/// type variables to its solution. Useful for seeing how inference works
+pub fn solution_of(expr: &Expr) -> Result<Subst, TypeError> {
+    solve(expr, &TypeEnv::default())
+}
+
+/// The inferred type of every expression in `ast`, keyed by its span in the
+/// source (eg from `syntax::parse_spanned`). For editor tooling, such as
+/// showing the type of the expression under the cursor
+pub fn type_map(ast: &ast::Spanned<ast::Expr>) -> Result<HashMap<Span, Type>, TypeError> {
+    let (expr, spans) = Expr::from_spanned_ast(ast.clone());
+    let subst = solve(&expr, &TypeEnv::default())?;
+    Ok(spans
+        .into_iter()
+        .map(|(span, ty)| (span, subst.apply_ty(&ty)))
+        .collect())
+}
+
+fn solve(expr: &Expr, tenv: &TypeEnv) -> Result<Subst, TypeError> {
+    letrec::check_forward_refs(expr)?;
+    let tagged = constraint::collect_tagged_in(expr.clone(), tenv)?;
+    let cons = constraint::simplify(tagged.iter().map(|(con, _)| con.clone()).collect());
+    let subst = unify::unify(&cons).map_err(|err| {
+        application::find_non_function_app(expr, &cons)
+            .or_else(|| numeric::find_numeric_mismatch(&tagged))
+            .or_else(|| annotation::find_annotation_mismatch(&tagged))
+            .unwrap_or(err)
+    })?;
+    comparable::check_comparable(expr, &subst)?;
+    Ok(subst)
 }
 
 /// Convenience function. Parse source code, and give every expr its inferred
-/// type
-pub fn parse_and_type(src: &str) -> Expr {
-    // TODO: return a &dyn impl Error instead of unwrapping
-    let expr = Expr::from_str(src).unwrap();
+/// type. Deeply nested programs need a large stack, see `with_large_stack`
+pub fn parse_and_type(src: &str) -> Result<Expr, TypeError> {
+    let expr = Expr::from_str(src).map_err(TypeError::Parse)?;
     infer_and_apply(&expr)
 }
+
+/// Parse and infer the type of `src`, and check that it is the same as the
+/// type `expected` (up to renaming of type variables)
+pub fn type_check_against(src: &str, expected: &str) -> Result<(), TypeError> {
+    let expr = Expr::from_str(src).map_err(TypeError::Parse)?;
+    let expected = Type::from_str(expected).map_err(TypeError::Parse)?;
+    let actual = type_of(&expr)?;
+
+    if actual.normalize_vars() == expected.normalize_vars() {
+        Ok(())
+    } else {
+        Err(TypeError::Mismatch { expected, actual })
+    }
+}
@@ -0,0 +1,44 @@
+use crate::types::{
+    constraint::{simplify, Reason, TaggedConstraints},
+    ty::Type,
+    unify::unify,
+    TypeError,
+};
+
+/// Explain a unification failure caused by a binary operator used on an `Int`
+/// and a `Float` (eg `1 + 2.0`, or `1 == 2.0`), which would otherwise be
+/// reported as `Cannot unify Float with Int`. There is no implicit conversion
+/// between them.
+/// Solves every constraint in `cons` except those on the operands of binary
+/// operators, then adds the operands' constraints back innermost first. The
+/// first one that fails because its operands are an `Int` and a `Float` is
+/// the culprit.
+/// `None` if the failure has some other cause
+pub fn find_numeric_mismatch(cons: &TaggedConstraints) -> Option<TypeError> {
+    let mut operands = Vec::new();
+    let mut others = Vec::new();
+    for (con, reason) in cons {
+        match reason {
+            Reason::Operand { lhs, rhs } => operands.push((con, lhs, rhs)),
+            Reason::Annotation(_) | Reason::Other => others.push(con.clone()),
+        }
+    }
+    let mut subst = unify(&simplify(others)).ok()?;
+
+    // `cons` is in pre-order, so reversing it puts children before parents
+    for (con, lhs, rhs) in operands.into_iter().rev() {
+        match unify(&[con.apply(&subst)]) {
+            Ok(con_subst) => subst = subst.compose(&con_subst),
+            Err(_) => {
+                return match (lhs.apply(&subst), rhs.apply(&subst)) {
+                    (left @ Type::Int, right @ Type::Float)
+                    | (left @ Type::Float, right @ Type::Int) => {
+                        Some(TypeError::NumericMismatch { left, right })
+                    }
+                    _ => None,
+                };
+            }
+        }
+    }
+    None
+}
@@ -1,5 +1,5 @@
 use crate::{
-    hir::{Expr, LetBinding, Param},
+    hir::Expr,
     types::{
         constraint::{Constraint, Constraints},
         ty::{Type, TypeVar},
@@ -18,88 +18,57 @@ impl Constraint {
 }
 
 impl Type {
+    /// Replace every type variable in `self` with its solution in `subst`.
+    /// Solutions are applied transitively, so this does a single walk over the
+    /// type, even when `subst` is not fully resolved
     pub fn apply(&self, subst: &Subst) -> Self {
-        subst.0.iter().fold(self.clone(), |acc, solution| {
-            let (tvar, solution_ty) = solution;
-            subst.replace(acc, *tvar, solution_ty.clone())
-        })
+        match self {
+            Self::Unit | Self::Int | Self::Bool | Self::Float => self.clone(),
+            Self::Var(tvar) => subst
+                .0
+                .get(tvar)
+                .map_or_else(|| self.clone(), |ty| ty.apply(subst)),
+            Self::Fn(arg, ret) => Self::Fn(box arg.apply(subst), box ret.apply(subst)),
+            Self::Tuple(tys) => Self::Tuple(tys.iter().map(|ty| ty.apply(subst)).collect()),
+        }
     }
-}
 
-impl Expr {
-    pub fn apply(&self, subst: &Subst) -> Self {
+    /// Replace every occurence of `tvar` in `self` with `replacement`. Unlike
+    /// `apply`, `replacement` is not itself substituted
+    pub fn substitute_one(&self, tvar: TypeVar, replacement: &Self) -> Self {
         match self {
-            Self::Lit { ty, val } => Self::Lit {
-                ty: ty.apply(subst),
-                val: *val,
-            },
-            Self::Var { ty, name } => Self::Var {
-                ty: ty.apply(subst),
-                name: *name,
-            },
-            Self::Binop { ty, lhs, rhs, op } => Self::Binop {
-                ty: ty.apply(subst),
-                lhs: box lhs.apply(subst),
-                rhs: box rhs.apply(subst),
-                op: *op,
-            },
-            Self::If {
-                ty,
-                test,
-                then,
-                els,
-            } => Self::If {
-                ty: ty.apply(subst),
-                test: box test.apply(subst),
-                then: box then.apply(subst),
-                els: box els.apply(subst),
-            },
-            Self::Let { ty, binding, body } => Self::Let {
-                ty: ty.apply(subst),
-                binding: LetBinding {
-                    ty: binding.ty.apply(subst),
-                    name: binding.name,
-                    ann: binding.ann.clone(),
-                    val: box binding.val.apply(subst),
-                },
-                body: box body.apply(subst),
-            },
-            Self::Letrec { ty, bindings, body } => Self::Letrec {
-                ty: ty.apply(subst),
-                bindings: bindings
-                    .iter()
-                    .map(|binding| LetBinding {
-                        ty: binding.ty.apply(subst),
-                        name: binding.name,
-                        val: box binding.val.apply(subst),
-                        ann: binding.ann.clone(),
-                    })
+            Self::Unit | Self::Int | Self::Bool | Self::Float => self.clone(),
+            Self::Var(tvar2) if tvar == *tvar2 => replacement.clone(),
+            Self::Var(_) => self.clone(),
+            Self::Fn(arg, ret) => Self::Fn(
+                box arg.substitute_one(tvar, replacement),
+                box ret.substitute_one(tvar, replacement),
+            ),
+            Self::Tuple(tys) => Self::Tuple(
+                tys.iter()
+                    .map(|ty| ty.substitute_one(tvar, replacement))
                     .collect(),
-                body: box body.apply(subst),
-            },
-            Self::Lambda { ty, param, body } => Self::Lambda {
-                ty: ty.apply(subst),
-                param: Param {
-                    ty: param.ty.apply(subst),
-                    ..param.clone()
-                },
-                body: box body.apply(subst),
-            },
-            Self::App { ty, func, arg } => Self::App {
-                ty: ty.apply(subst),
-                func: box func.apply(subst),
-                arg: box arg.apply(subst),
-            },
+            ),
         }
     }
 }
 
+impl Expr {
+    pub fn apply(&self, subst: &Subst) -> Self {
+        self.map_types(|ty| ty.apply(subst))
+    }
+}
+
 impl Subst {
     pub fn new() -> Self {
         Self::default()
     }
 
-    pub fn insert(&mut self, tvar: TypeVar, ty: Type) {
+    /// `Type::apply` chases solutions transitively, so `ty` must not mention
+    /// `tvar`, directly or through other solutions, or applying the result
+    /// would never terminate. Only `unify` and the passes that build
+    /// substitutions from it can guarantee this
+    pub(crate) fn insert(&mut self, tvar: TypeVar, ty: Type) {
         self.0.insert(tvar, ty);
     }
 
@@ -119,17 +88,24 @@ impl Subst {
         expr.apply(self)
     }
 
-    // Replace all occurances of `tvar` in `ty` with `ty`
-    pub fn replace(&self, ty: Type, tvar: TypeVar, replacement: Type) -> Type {
-        match ty {
-            Type::Int | Type::Bool | Type::Float => ty,
-            Type::Var(tvar2) if tvar == tvar2 => replacement,
-            Type::Var(_) => ty,
-            Type::Fn(box arg, box ret) => Type::Fn(
-                box self.replace(arg, tvar, replacement.clone()),
-                box self.replace(ret, tvar, replacement),
-            ),
-        }
+    /// The solution for `tvar`, if there is one. After `unify`, solutions do
+    /// not mention any of the solved type variables
+    pub fn get(&self, tvar: TypeVar) -> Option<&Type> {
+        self.0.get(&tvar)
+    }
+
+    /// Every type variable with a solution, and its solution, in no particular
+    /// order
+    pub fn iter(&self) -> impl Iterator<Item = (TypeVar, &Type)> {
+        self.0.iter().map(|(tvar, ty)| (*tvar, ty))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
     }
 
     pub fn compose(&self, other: &Self) -> Self {
@@ -145,7 +121,8 @@ impl Subst {
         Self(new_subst)
     }
 
-    pub fn from_pair(tvar: TypeVar, ty: Type) -> Self {
+    /// See `insert` for the restriction on `ty`
+    pub(crate) fn from_pair(tvar: TypeVar, ty: Type) -> Self {
         let mut hm = HashMap::new();
         hm.insert(tvar, ty);
         Self(hm)
@@ -173,6 +150,55 @@ mod test {
         )
     }
 
+    #[test]
+    fn subst_accessors() {
+        use crate::types::solution_of;
+        use std::str::FromStr;
+
+        // the parameter is given type variable 1
+        let subst = solution_of(&Expr::from_str(r"\x -> x + 1").unwrap()).unwrap();
+        assert_eq!(subst.get(1), Some(&Type::Int));
+        assert_eq!(subst.get(100), None);
+        assert_eq!(subst.iter().count(), subst.len());
+        assert!(Subst::new().is_empty());
+    }
+
+    #[test]
+    fn substitute_one() {
+        let ty = Type::Fn(box Type::Var(1), box Type::Var(2));
+        assert_eq!(
+            ty.substitute_one(1, &Type::Var(2)),
+            Type::Fn(box Type::Var(2), box Type::Var(2))
+        );
+        // the replacement is not substituted again
+        assert_eq!(
+            ty.substitute_one(2, &Type::Fn(box Type::Var(2), box Type::Int)),
+            Type::Fn(box Type::Var(1), box Type::Fn(box Type::Var(2), box Type::Int))
+        );
+    }
+
+    #[test]
+    fn subst_letrec_expr() {
+        use std::str::FromStr;
+
+        let expr = Expr::from_str(r"letrec f = \x -> g x, g = \y -> f y in f").unwrap();
+        let mut subst = Subst::new();
+        for tvar in 0..64 {
+            subst.insert(tvar, Type::Int);
+        }
+        match expr.apply(&subst) {
+            Expr::Letrec { ty, bindings, body } => {
+                assert_eq!(ty, Type::Int);
+                for binding in bindings {
+                    assert_eq!(binding.ty, Type::Int);
+                    assert_eq!(binding.val.ty(), Type::Int);
+                }
+                assert_eq!(body.ty(), Type::Int);
+            }
+            expr => panic!("expected a letrec, got {:?}", expr),
+        }
+    }
+
     #[test]
     fn subst_constraint() {
         let mut subst = Subst::new();
@@ -224,4 +250,19 @@ mod test {
 
         assert_eq!(subst1.compose(&subst2), expected);
     }
+
+    #[test]
+    fn subst_chain() {
+        let mut subst = Subst::new();
+        subst.insert(0, Type::Var(1));
+        subst.insert(1, Type::Var(2));
+        subst.insert(2, Type::Int);
+
+        assert_eq!(subst.apply_ty(&Type::Var(0)), Type::Int);
+        assert_eq!(subst.apply_ty(&Type::Var(1)), Type::Int);
+        assert_eq!(
+            subst.apply_ty(&Type::Fn(box Type::Var(0), box Type::Var(3))),
+            Type::Fn(box Type::Int, box Type::Var(3))
+        );
+    }
 }
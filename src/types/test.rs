@@ -1,15 +1,21 @@
 use crate::{
+    syntax::parse_spanned,
     ty,
     types::{
-        ty::{Type, Type::*},
+        ty::{Polytype, Type, Type::*, TypeEnv},
         *,
     },
+    util::stack::with_large_stack,
 };
+use simple_symbol::intern;
+
+#[path = "../../benches/programs/mod.rs"]
+pub(super) mod programs;
 
 #[track_caller]
 fn test_infer(src: &str, expected: Type) {
     let expr = Expr::from_str(src).unwrap();
-    let ty = type_of(&expr);
+    let ty = type_of(&expr).unwrap();
     assert_eq!(ty, expected);
 }
 
@@ -17,8 +23,18 @@ fn test_infer(src: &str, expected: Type) {
 fn infer_lit() {
     test_infer("1", Int);
     test_infer("1.0", Float);
+    test_infer("1e3", Float);
+    test_infer("0xFF", Int);
     test_infer("true", Bool);
     test_infer("false", Bool);
+    test_infer("()", Unit);
+}
+
+#[test]
+fn infer_negative_lit() {
+    test_infer("-5", Int);
+    test_infer("-2.5", Float);
+    test_infer("1 - -2", Int);
 }
 
 #[test]
@@ -39,6 +55,14 @@ fn infer_compose_fn() {
     );
 }
 
+#[test]
+fn infer_compose_fn_normalized() {
+    let expr = Expr::from_str(r"\f -> \g -> \x -> f (g x)").unwrap();
+    let ty = type_of(&expr).unwrap().normalize_vars();
+    assert_eq!(ty, ty![({0} => {1}) => ({2} => {0}) => {2} => {1}]);
+    assert_eq!(ty.to_string(), "(t0 -> t1) -> (t2 -> t0) -> t2 -> t1");
+}
+
 #[test]
 fn infer_pred_fn() {
     test_infer(
@@ -52,6 +76,13 @@ fn infer_inc_fn() {
     test_infer(r"let inc = \x -> x + 1 in inc (inc 1)", ty![Int]);
 }
 
+#[test]
+fn infer_multi_let() {
+    test_infer("let x = 1, y = add x 1 in y", Int);
+    test_infer("let x = 1, y = true in x", Int);
+    test_infer("let x = 1, y = true in y", Bool);
+}
+
 #[test]
 fn infer_letrec() {
     test_infer(
@@ -99,18 +130,431 @@ fn infer_lambda_many() {
     );
 }
 
+#[test]
+fn infer_partial_application() {
+    test_infer("add 1", ty![Int => Int]);
+    test_infer("add 1 2", Int);
+    test_infer("pow 2.0", ty![Float => Float]);
+    test_infer(r"let inc = add 1 in inc (inc 1)", Int);
+    assert_eq!(type_check_against("const 1", "t0 -> Int"), Ok(()));
+    assert_eq!(type_check_against("compose not", "(t0 -> Bool) -> t0 -> Bool"), Ok(()));
+}
+
+#[test]
+fn type_map_spans() {
+    let src = "let x = 5 in x + 1";
+    let types = type_map(&parse_spanned(src).unwrap()).unwrap();
+    assert_eq!(types[&(0..18)], Int); // the whole expression
+    assert_eq!(types[&(8..9)], Int); // `5`
+    assert_eq!(types[&(13..14)], Int); // the use of `x`
+    assert_eq!(types[&(13..18)], Int); // `x + 1`
+
+    // the lambda for `y` is not in the source, so only its body has a type
+    let src = r"\x, y -> x + y";
+    let types = type_map(&parse_spanned(src).unwrap()).unwrap();
+    assert_eq!(types[&(0..14)], ty![Int => Int => Int]);
+    assert_eq!(types[&(9..14)], Int);
+    assert_eq!(types.len(), 4);
+
+    let src = "if 1 then 2 else 3";
+    assert!(type_map(&parse_spanned(src).unwrap()).is_err());
+}
+
 #[test]
 fn infer_annotations() {
     test_infer(r"let idInt: Int -> Int = \a -> a in idInt", ty![Int => Int]);
+    test_infer(r"\a: Int -> a", ty![Int => Int]);
+    test_infer(r"letrec f: Int -> Int = \x -> f x in f", ty![Int => Int]);
+
+    let infer = |src| type_of(&Expr::from_str(src).unwrap());
+    assert_eq!(
+        infer(r"\a: Bool -> a + 1"),
+        Err(TypeError::CannotUnify(Int, Bool))
+    );
+    assert_eq!(
+        infer(r"let x: Float = 1 in x"),
+        Err(TypeError::AnnotationMismatch {
+            binding: intern("x"),
+            ann: Float,
+            inferred: Int
+        })
+    );
+    assert_eq!(
+        infer(r"letrec f: Int -> Bool = \x -> x + 1 in f"),
+        Err(TypeError::AnnotationMismatch {
+            binding: intern("f"),
+            ann: ty![Int => Bool],
+            inferred: ty![Int => Int]
+        })
+    );
+}
+
+#[test]
+fn infer_annotation_mismatch() {
+    let err = type_of(&Expr::from_str("let y = 1, x: Int = true in x").unwrap()).unwrap_err();
+    assert_eq!(
+        err,
+        TypeError::AnnotationMismatch {
+            binding: intern("x"),
+            ann: Int,
+            inferred: Bool
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "Annotation Int does not match inferred type Bool for binding x"
+    );
+
+    // annotations that agree with their values are not blamed
+    assert!(matches!(
+        type_of(&Expr::from_str("let x: Int = 1 in x + true").unwrap()),
+        Err(TypeError::CannotUnify(..))
+    ));
+    assert!(matches!(
+        type_of(&Expr::from_str("let x: Int = 1 in if x then 1 else 2").unwrap()),
+        Err(TypeError::CannotUnify(..))
+    ));
+    assert_eq!(
+        type_of(&Expr::from_str("let x: Int = 1 in x +. 1.0").unwrap()),
+        Err(TypeError::NumericMismatch {
+            left: Int,
+            right: Float
+        })
+    );
 }
 
 #[test]
 fn infer_operators() {
     test_infer("1 + 2", Int);
     test_infer("1.0 +. 2.0", Float);
+    test_infer("1 << 2 & 3", Int);
+    assert_eq!(
+        type_of(&Expr::from_str("1.0 | 2").unwrap()),
+        Err(TypeError::NumericMismatch {
+            left: Float,
+            right: Int
+        })
+    );
 
     test_infer("1 == 2", Bool);
     test_infer("1.0 == 2.0", Bool);
     test_infer("true == false", Bool);
-    test_infer(r"(\x -> x) == (\y -> y)", Bool);
+}
+
+#[test]
+fn infer_eq_on_functions() {
+    for src in &[r"(\x -> x) == (\y -> y)", r"not != not"] {
+        let expr = Expr::from_str(src).unwrap();
+        assert!(matches!(
+            type_of(&expr),
+            Err(TypeError::NotComparable(Type::Fn(..)))
+        ));
+    }
+    assert_eq!(
+        type_of(&Expr::from_str("not == not").unwrap())
+            .unwrap_err()
+            .to_string(),
+        "Cannot compare values of type Bool -> Bool"
+    );
+
+    // the operands may still be functions once instantiated, but there is no
+    // let-polymorphism yet, so they never are
+    assert_eq!(
+        type_check_against(r"\x -> \y -> x == y", "t0 -> t0 -> Bool"),
+        Ok(())
+    );
+    test_infer("1 != 2", Bool);
+    test_infer("1.0 != 2.0", Bool);
+    test_infer("true != false", Bool);
+}
+
+#[test]
+fn infer_unops() {
+    test_infer("!true", Bool);
+    test_infer("- 5", Int);
+    test_infer("-. 2.5", Float);
+    test_infer(r"\x -> -x", ty![Int => Int]);
+    test_infer(r"\x -> -.x", ty![Float => Float]);
+    test_infer(r"\b -> !b", ty![Bool => Bool]);
+    test_infer("!(1 == 2)", Bool);
+}
+
+#[test]
+fn infer_division() {
+    test_infer("1 / 2", Int);
+    test_infer("1.0 /. 2.0", Float);
+
+    let expr = Expr::from_str("1.0 / 2").unwrap();
+    assert_eq!(
+        type_of(&expr),
+        Err(TypeError::NumericMismatch {
+            left: Float,
+            right: Int
+        })
+    );
+    assert_eq!(
+        type_of(&expr).unwrap_err().to_string(),
+        "Cannot use Float and Int together, as there is no implicit conversion between them. \
+         The operators for Floats end in `.`, eg `+.`, `*.` and `<.`"
+    );
+
+    let expr = Expr::from_str("1 /. 2.0").unwrap();
+    assert!(matches!(
+        type_of(&expr),
+        Err(TypeError::NumericMismatch { .. })
+    ));
+}
+
+#[test]
+fn infer_numeric_mismatch() {
+    let infer = |src| type_of(&Expr::from_str(src).unwrap());
+    let mismatch = |left, right| Err::<Type, _>(TypeError::NumericMismatch { left, right });
+
+    assert_eq!(infer("1 + 2.0"), mismatch(Int, Float));
+    assert_eq!(infer("1 == 2.0"), mismatch(Int, Float));
+    assert_eq!(infer("1.0 < 2"), mismatch(Float, Int));
+    assert_eq!(infer(r"let x = 1 in x +. 2.0"), mismatch(Int, Float));
+    assert_eq!(infer(r"\x -> (x + 1) == 2.5"), mismatch(Int, Float));
+
+    // the operands have the same type, so there is no conversion to suggest
+    assert!(matches!(infer("1.0 + 2.0"), Err(TypeError::CannotUnify(..))));
+    assert!(matches!(infer("true + 1"), Err(TypeError::CannotUnify(..))));
+}
+
+#[test]
+fn infer_if_without_builtins() {
+    let tenv = TypeEnv::empty();
+    let infer = |src| type_of_in(&Expr::from_str(src).unwrap(), &tenv);
+
+    assert_eq!(infer("if true then 1 else 2"), Ok(Int));
+    assert_eq!(infer(r"\b -> if b then 1.0 else 2.0"), Ok(ty![Bool => Float]));
+    assert_eq!(
+        infer("if 1 then 2 else 3"),
+        Err(TypeError::CannotUnify(Bool, Int))
+    );
+    assert!(infer("if true then 1 else false").is_err());
+}
+
+#[test]
+fn infer_if_without_else() {
+    test_infer("if true then ()", Unit);
+    test_infer(r"\b -> if b then ()", ty![Bool => Unit]);
+    test_infer(r"\x -> if true then x", ty![Unit => Unit]);
+    assert!(matches!(
+        type_of(&Expr::from_str("if true then 1").unwrap()),
+        Err(TypeError::CannotUnify(..))
+    ));
+}
+
+#[test]
+fn infer_polymorphic_builtins() {
+    test_infer("if_then_else true 1 0", Int);
+    test_infer("if_then_else true true false", Bool);
+    test_infer(
+        "let x = if_then_else true 1 0, b = if_then_else true true false in if b then x else 0",
+        Int,
+    );
+    test_infer(r"\b -> if_then_else b", ty![Bool => {5} => {5} => {5}]);
+}
+
+#[test]
+fn infer_id_const_compose() {
+    test_infer("id 1", Int);
+    test_infer("id true", Bool);
+    test_infer("const 1 true", Int);
+    test_infer(
+        "let x = id 1, y = id true, z = const 1 true in if y then x else z",
+        Int,
+    );
+    test_infer("compose not is_zero", ty![Int => Bool]);
+    test_infer("compose id id 2.5", Float);
+}
+
+#[test]
+fn quantified_vars_do_not_leak() {
+    // `t0` is quantified, and also the type of the whole expression. Each use
+    // of `choose` gets fresh type variables, so neither is unified with the
+    // other or with the expression's own `t0`
+    let mut tenv = TypeEnv::empty();
+    tenv.insert_poly(
+        intern("choose"),
+        Polytype::new(vec![0], ty![Bool => {0} => {0} => {0}]),
+    );
+    let expr = Expr::from_str("if choose true false true then choose true 1 2 else 3").unwrap();
+    let typed = infer_and_apply_in(&expr, &tenv).unwrap();
+    assert_eq!(typed.ty(), Int);
+    assert!(typed.fold_types(true, |ok, ty| ok && ty.type_vars().is_empty()));
+
+    // the scheme itself is untouched
+    assert_eq!(
+        tenv.get(intern("choose")),
+        Some(&Polytype::new(vec![0], ty![Bool => {0} => {0} => {0}]))
+    );
+}
+
+#[test]
+fn infer_float_builtins() {
+    test_infer("sqrt", ty![Float => Float]);
+    test_infer("abs", ty![Float => Float]);
+    test_infer("floor", ty![Float => Float]);
+    test_infer("pow", ty![Float => Float => Float]);
+    test_infer("floor (sqrt 2.0) +. abs -1.0", Float);
+    assert!(type_of(&Expr::from_str("sqrt 4").unwrap()).is_err());
+}
+
+#[test]
+fn infer_custom_builtins() {
+    let mut tenv = TypeEnv::default();
+    tenv.insert(intern("sqrt"), ty![Float => Float]);
+
+    let expr = Expr::from_str("sqrt 4.0").unwrap();
+    assert_eq!(type_of_in(&expr, &tenv).unwrap(), Float);
+    assert_eq!(infer_and_apply_in(&expr, &tenv).unwrap().ty(), Float);
+
+    let expr = Expr::from_str(r"\x -> sqrt (sqrt x)").unwrap();
+    assert_eq!(type_of_in(&expr, &tenv).unwrap(), ty![Float => Float]);
+}
+
+#[test]
+fn check_against() {
+    assert_eq!(type_check_against(r"\x -> x", "t0 -> t0"), Ok(()));
+    assert_eq!(type_check_against(r"\x -> x", "t5 -> t5"), Ok(()));
+    assert_eq!(
+        type_check_against(r"\f -> \g -> \x -> f (g x)", "(t1 -> t2) -> (t0 -> t1) -> t0 -> t2"),
+        Ok(())
+    );
+    assert_eq!(type_check_against("1 + 2", "Int"), Ok(()));
+    assert_eq!(type_check_against(r"\x, y -> x", "t0 -> t1 -> t0"), Ok(()));
+
+    assert_eq!(
+        type_check_against(r"\x -> x", "Int -> Int"),
+        Err(TypeError::Mismatch {
+            expected: ty![Int => Int],
+            actual: ty![{1} => {1}],
+        })
+    );
+    assert!(matches!(
+        type_check_against(r"\x -> x", "t0 -> t1"),
+        Err(TypeError::Mismatch { .. })
+    ));
+    assert!(matches!(
+        type_check_against(r"\x -> ", "t0 -> t0"),
+        Err(TypeError::Parse(_))
+    ));
+}
+
+#[test]
+fn infer_unbound() {
+    let expr = Expr::from_str("x + 1").unwrap();
+    assert_eq!(type_of(&expr), Err(TypeError::Unbound(intern("x"))));
+    assert_eq!(
+        type_of(&expr).unwrap_err().to_string(),
+        "Unbound variable: x"
+    );
+
+    let expr = Expr::from_str(r"\x -> let y = x in z").unwrap();
+    assert_eq!(infer_and_apply(&expr), Err(TypeError::Unbound(intern("z"))));
+}
+
+#[test]
+fn infer_not_a_function() {
+    let infer = |src| type_of(&Expr::from_str(src).unwrap());
+
+    assert_eq!(infer("1 2"), Err(TypeError::NotAFunction(Int)));
+    assert_eq!(infer(r"(\x -> x) 1 2"), Err(TypeError::NotAFunction(Int)));
+    assert_eq!(infer("add 1 2 3"), Err(TypeError::NotAFunction(Int)));
+    assert_eq!(
+        infer("let b = true in b 1"),
+        Err(TypeError::NotAFunction(Bool))
+    );
+    assert_eq!(
+        infer("1 2").unwrap_err().to_string(),
+        "Cannot apply a value of type Int, as it is not a function"
+    );
+
+    // the function is fine, but its argument is not
+    assert!(matches!(
+        infer(r"(\f -> f 1) 2"),
+        Err(TypeError::CannotUnify(..))
+    ));
+}
+
+#[test]
+fn type_error_is_std_error() {
+    fn infer(src: &str) -> Result<Type, Box<dyn std::error::Error>> {
+        let expr = Expr::from_str(src)?;
+        Ok(type_of(&expr)?)
+    }
+
+    assert_eq!(infer("1 + 2").unwrap(), Int);
+    let err = infer("1 + true").unwrap_err();
+    assert_eq!(err.to_string(), "Cannot unify Int with Bool");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_roundtrip() {
+    let expr = parse_and_type("let x = 5 in x + 1").unwrap();
+    let json = serde_json::to_string(&expr).unwrap();
+    assert!(json.contains(r#""name":"x""#));
+    let expr2: Expr = serde_json::from_str(&json).unwrap();
+    assert_eq!(expr, expr2);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_pretty_deserialized() {
+    // names are serialized as strings, and interned again when deserializing, so
+    // a name that has never been interned in this process still prints correctly
+    let expr = parse_and_type(r"let x = 5 in \y -> x + y").unwrap();
+    let json = serde_json::to_string(&expr)
+        .unwrap()
+        .replace(r#""name":"x""#, r#""name":"only_in_json""#);
+    let expr: Expr = serde_json::from_str(&json).unwrap();
+    assert_eq!(expr.pretty(), r"let only_in_json = 5 in \y -> only_in_json + y");
+}
+
+#[test]
+fn infer_deep_programs() {
+    with_large_stack(|| {
+        test_infer(&programs::nested_lets(1000), Int);
+        test_infer(&programs::compose_chain(1000), ty![Int => Int]);
+    });
+}
+
+#[test]
+fn parse_and_type_deeply_nested() {
+    let depth = 5000;
+    let mut src = String::new();
+    for i in 0..depth {
+        src.push_str(&format!("let x{} = {} in ", i, i));
+    }
+    src.push_str("x0");
+
+    with_large_stack(move || {
+        let expr = parse_and_type(&src).unwrap();
+        assert_eq!(expr.ty(), Int);
+    });
+}
+
+#[test]
+fn dump_constraints() {
+    let cons = constraints_of(&Expr::from_str(r"\x -> x").unwrap()).unwrap();
+    assert_eq!(cons.len(), 2);
+    let cons: Vec<_> = cons.iter().map(ToString::to_string).collect();
+    assert_eq!(cons.join(", "), "t0 = t1 -> t2, t2 = t1");
+
+    assert_eq!(
+        constraints_of(&Expr::from_str("y").unwrap()),
+        Err(TypeError::Unbound(intern("y")))
+    );
+}
+
+#[test]
+fn solve_constraints_in_order() {
+    let cons = constraints_of(&Expr::from_str(r"\x -> x").unwrap()).unwrap();
+    assert_eq!(
+        unify_ordered(&cons),
+        Ok(vec![(0, ty![{1} => {1}]), (2, Var(1))])
+    );
 }
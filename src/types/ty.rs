@@ -1,15 +1,19 @@
 use crate::{
     hir::{Lit, Symbol},
+    types::subst::Subst,
     util::counter::{Counter, FromId},
 };
 use derive_more::Display;
 use simple_symbol::intern;
-use std::collections::HashMap;
+use std::{collections::HashMap, convert::TryFrom, str::FromStr};
 
 pub type TypeVar = u32;
 
 #[derive(Debug, Clone, PartialEq, Eq, Display, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Type {
+    #[display(fmt = "Unit")]
+    Unit,
     #[display(fmt = "Int")]
     Int,
     #[display(fmt = "Float")]
@@ -20,19 +24,149 @@ pub enum Type {
     Var(TypeVar),
     #[display(fmt = "{}", "display_fn_type(_0, _1)")]
     Fn(Box<Type>, Box<Type>),
+    #[display(fmt = "{}", "display_tuple_type(_0)")]
+    Tuple(Vec<Type>),
 }
 
 fn display_fn_type(t1: &Type, t2: &Type) -> String {
-    if let Type::Fn(..) = t1 {
+    if t1.is_function() {
         format!("({}) -> {}", t1, t2)
     } else {
         format!("{} -> {}", t1, t2)
     }
 }
 
+fn display_tuple_type(tys: &[Type]) -> String {
+    let tys = tys.iter().map(ToString::to_string).collect::<Vec<_>>();
+    format!("({})", tys.join(", "))
+}
+
+impl Type {
+    /// Parse the name of a type variable: either as printed by `Display` (eg
+    /// `t0`), or a single lowercase letter (`a` is `t0`, `b` is `t1`, etc)
+    pub fn var_from_name(name: &str) -> Option<Self> {
+        if let [c @ b'a'..=b'z'] = name.as_bytes() {
+            return Some(Self::Var(TypeVar::from(c - b'a')));
+        }
+
+        let id = name.strip_prefix('t')?;
+        if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        id.parse().ok().map(Self::Var)
+    }
+
+    /// The type variables in `self`, in order of first occurence
+    pub fn type_vars(&self) -> Vec<TypeVar> {
+        let mut tvars = Vec::new();
+        self.type_vars_inner(&mut tvars);
+        tvars
+    }
+
+    fn type_vars_inner(&self, tvars: &mut Vec<TypeVar>) {
+        match self {
+            Self::Unit | Self::Int | Self::Bool | Self::Float => {}
+            Self::Var(tvar) => {
+                if !tvars.contains(tvar) {
+                    tvars.push(*tvar)
+                }
+            }
+            Self::Fn(arg, ret) => {
+                arg.type_vars_inner(tvars);
+                ret.type_vars_inner(tvars);
+            }
+            Self::Tuple(tys) => tys.iter().for_each(|ty| ty.type_vars_inner(tvars)),
+        }
+    }
+
+    /// Whether `tvar` appears anywhere in `self`
+    pub fn occurs(&self, tvar: TypeVar) -> bool {
+        match self {
+            Self::Unit | Self::Int | Self::Bool | Self::Float => false,
+            Self::Var(tvar2) => tvar == *tvar2,
+            Self::Fn(arg, ret) => arg.occurs(tvar) || ret.occurs(tvar),
+            Self::Tuple(tys) => tys.iter().any(|ty| ty.occurs(tvar)),
+        }
+    }
+
+    /// Whether `self` contains no type variables, so that it can be compiled
+    pub fn is_monomorphic(&self) -> bool {
+        self.type_vars().is_empty()
+    }
+
+    /// Whether values of type `self` are functions (closures, once compiled)
+    pub const fn is_function(&self) -> bool {
+        matches!(self, Self::Fn(..))
+    }
+
+    /// The number of arguments a function of type `self` takes before it
+    /// returns a non-function, eg 2 for `Int -> Bool -> Float`. Function
+    /// arguments count once, so `(Int -> Int) -> Int` has arity 1
+    pub fn arity(&self) -> usize {
+        match self {
+            Self::Fn(_, ret) => 1 + ret.arity(),
+            _ => 0,
+        }
+    }
+
+    /// Split a curried function type into its arguments and its final result.
+    /// Non-function types have no arguments, and are their own result
+    pub fn params_and_result(&self) -> (Vec<Self>, Self) {
+        let mut params = Vec::new();
+        let mut ty = self;
+        while let Self::Fn(arg, ret) = ty {
+            params.push((**arg).clone());
+            ty = ret;
+        }
+        (params, ty.clone())
+    }
+
+    /// Renumber the type variables in `self` in order of first occurence
+    /// (left to right), starting from `t0`
+    pub fn normalize_vars(&self) -> Self {
+        let mut gen = TypeVarGen::new();
+        let mut mapping = HashMap::new();
+        self.normalize_vars_inner(&mut gen, &mut mapping)
+    }
+
+    /// Replace the type variables in `self` with fresh ones from `gen`. Each
+    /// occurrence of the same variable gets the same replacement
+    pub fn freshen_vars(&self, gen: &mut TypeVarGen) -> Self {
+        self.normalize_vars_inner(gen, &mut HashMap::new())
+    }
+
+    fn normalize_vars_inner(
+        &self,
+        gen: &mut TypeVarGen,
+        mapping: &mut HashMap<TypeVar, Self>,
+    ) -> Self {
+        match self {
+            Self::Unit | Self::Int | Self::Bool | Self::Float => self.clone(),
+            Self::Var(tvar) => mapping.entry(*tvar).or_insert_with(|| gen.next()).clone(),
+            Self::Fn(arg, ret) => Self::Fn(
+                box arg.normalize_vars_inner(gen, mapping),
+                box ret.normalize_vars_inner(gen, mapping),
+            ),
+            Self::Tuple(tys) => Self::Tuple(
+                tys.iter()
+                    .map(|ty| ty.normalize_vars_inner(gen, mapping))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl FromStr for Type {
+    type Err = String;
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        crate::syntax::parse_type(src).map_err(|e| e.to_string())
+    }
+}
+
 impl Lit {
     pub const fn ty(&self) -> Type {
         match self {
+            Self::Unit => Type::Unit,
             Self::Int(_) => Type::Int,
             Self::Bool(_) => Type::Bool,
             Self::Float(_) => Type::Float,
@@ -54,6 +188,7 @@ macro_rules! ty {
         Type::Var($e)
     }};
 
+    [Unit] => {Type::Unit};
     [Int] => {Type::Int};
     [Float] => {Type::Float};
     [Bool] => {Type::Bool};
@@ -72,6 +207,14 @@ macro_rules! ty {
 
 }
 
+#[macro_export]
+macro_rules! tuple_ty {
+    [$($tts:tt),+] => {{
+        let tys = vec!($( $crate::ty!($tts)),*);
+        $crate::types::ty::fold_tuple(&tys)
+    }};
+}
+
 pub fn fold_tys(tys: &mut [Type]) -> Type {
     assert!(tys.len() >= 2);
     tys.reverse();
@@ -81,21 +224,101 @@ pub fn fold_tys(tys: &mut [Type]) -> Type {
         .fold(head, |acc, ty| Type::Fn(box ty.clone(), box acc.clone()))
 }
 
+pub fn fold_tuple(tys: &[Type]) -> Type {
+    assert!(tys.len() >= 2);
+    Type::Tuple(tys.to_vec())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Display)]
+/// A type scheme: `ty`, quantified over the type variables in `vars`.
+/// Each use of a variable with a polytype gets fresh type variables
+#[display(fmt = "{}", "display_polytype(vars, ty)")]
+pub struct Polytype {
+    pub vars: Vec<TypeVar>,
+    pub ty: Type,
+}
+
+/// The quantified variables are named `a`, `b`, ..., `z`, `a1`, `b1`, ... in
+/// the order they are quantified. Free variables are printed as usual
+fn display_polytype(vars: &[TypeVar], ty: &Type) -> String {
+    if vars.is_empty() {
+        return ty.to_string();
+    }
+
+    let mut display = TypeDisplay::new();
+    let names: Vec<_> = (0..vars.len())
+        .map(|i| {
+            let letter = char::from(b'a' + u8::try_from(i % 26).unwrap());
+            match i / 26 {
+                0 => letter.to_string(),
+                n => format!("{}{}", letter, n),
+            }
+        })
+        .collect();
+    for (tvar, name) in vars.iter().zip(&names) {
+        display.alias(Type::Var(*tvar), name);
+    }
+    format!("forall {}. {}", names.join(" "), display.display(ty))
+}
+
+impl Polytype {
+    pub fn new(vars: Vec<TypeVar>, ty: Type) -> Self {
+        Self { vars, ty }
+    }
+
+    /// Quantify `ty` over all of its type variables
+    pub fn generalize(ty: Type) -> Self {
+        Self::new(ty.type_vars(), ty)
+    }
+
+    /// Replace the quantified type variables with fresh ones from `gen`
+    pub fn instantiate(&self, gen: &mut TypeVarGen) -> Type {
+        let mut subst = Subst::new();
+        for tvar in &self.vars {
+            subst.insert(*tvar, gen.next());
+        }
+        self.ty.apply(&subst)
+    }
+}
+
+impl From<Type> for Polytype {
+    fn from(ty: Type) -> Self {
+        Self::new(vec![], ty)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
-/// A mapping from `Ident`s (that is, variables) to `Type`s.
+/// A mapping from `Ident`s (that is, variables) to `Polytype`s.
 /// Used when looking up type of an `Expr::Var`
-pub struct TypeEnv(HashMap<Symbol, Type>);
+pub struct TypeEnv(HashMap<Symbol, Polytype>);
 
 impl Default for TypeEnv {
     fn default() -> Self {
-        let mut hm = HashMap::new();
-        hm.insert(intern("add"), ty![Int => Int => Int]);
-        hm.insert(intern("sub"), ty![Int => Int => Int]);
-        hm.insert(intern("mul"), ty![Int => Int => Int]);
-        hm.insert(intern("is_zero"), ty![Int => Bool]);
-        hm.insert(intern("not"), ty![Bool => Bool]);
+        let mut env = Self::empty();
+        env.insert(intern("add"), ty![Int => Int => Int]);
+        env.insert(intern("sub"), ty![Int => Int => Int]);
+        env.insert(intern("mul"), ty![Int => Int => Int]);
+        env.insert(intern("is_zero"), ty![Int => Bool]);
+        env.insert(intern("not"), ty![Bool => Bool]);
+        env.insert(intern("sqrt"), ty![Float => Float]);
+        env.insert(intern("abs"), ty![Float => Float]);
+        env.insert(intern("floor"), ty![Float => Float]);
+        env.insert(intern("pow"), ty![Float => Float => Float]);
+        env.insert_poly(
+            intern("if_then_else"),
+            Polytype::new(vec![0], ty![Bool => {0} => {0} => {0}]),
+        );
+        env.insert_poly(intern("id"), Polytype::new(vec![0], ty![{0} => {0}]));
+        env.insert_poly(
+            intern("const"),
+            Polytype::new(vec![0, 1], ty![{0} => {1} => {0}]),
+        );
+        env.insert_poly(
+            intern("compose"),
+            Polytype::new(vec![0, 1, 2], ty![({1} => {2}) => ({0} => {1}) => {0} => {2}]),
+        );
 
-        Self(hm)
+        env
     }
 }
 
@@ -104,13 +327,60 @@ impl TypeEnv {
         Self(HashMap::new())
     }
 
-    pub fn get(&self, var: Symbol) -> Option<&Type> {
+    pub fn get(&self, var: Symbol) -> Option<&Polytype> {
         self.0.get(&var)
     }
 
+    /// Insert a monomorphic type
     pub fn insert(&mut self, name: Symbol, ty: Type) {
+        self.insert_poly(name, ty.into());
+    }
+
+    pub fn insert_poly(&mut self, name: Symbol, ty: Polytype) {
         self.0.insert(name, ty);
     }
+
+    /// The largest type variable used by a quantified type, if any
+    pub(crate) fn max_tvar(&self) -> Option<TypeVar> {
+        self.0.values().flat_map(|poly| poly.vars.iter()).copied().max()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Configuration for displaying `Type`s.
+/// Types with an alias are printed as the alias instead of their structure
+pub struct TypeDisplay {
+    aliases: HashMap<Type, String>,
+}
+
+impl TypeDisplay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn alias(&mut self, ty: Type, name: &str) {
+        self.aliases.insert(ty, name.to_owned());
+    }
+
+    pub fn display(&self, ty: &Type) -> String {
+        if let Some(name) = self.aliases.get(ty) {
+            return name.clone();
+        }
+
+        match ty {
+            Type::Unit | Type::Int | Type::Float | Type::Bool | Type::Var(_) => ty.to_string(),
+            Type::Fn(arg, ret) => match (&**arg, self.aliases.contains_key(arg)) {
+                (Type::Fn(..), false) => {
+                    format!("({}) -> {}", self.display(arg), self.display(ret))
+                }
+                _ => format!("{} -> {}", self.display(arg), self.display(ret)),
+            },
+            Type::Tuple(tys) => {
+                let tys = tys.iter().map(|ty| self.display(ty)).collect::<Vec<_>>();
+                format!("({})", tys.join(", "))
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -141,6 +411,146 @@ mod test {
         assert_eq!(ty![({0} => {1}) => {1}].to_string(), "(t0 -> t1) -> t1");
     }
 
+    #[test]
+    fn test_occurs() {
+        assert!(Type::Var(1).occurs(1));
+        assert!(!Type::Var(2).occurs(1));
+        assert!(Type::Fn(box Type::Var(1), box Type::Int).occurs(1));
+        assert!(ty![Int => Bool => {1}].occurs(1));
+        assert!(Type::Tuple(vec![Type::Bool, Type::Var(1)]).occurs(1));
+        assert!(!Type::Int.occurs(1));
+        assert!(!ty![{2} => {3}].occurs(1));
+    }
+
+    #[test]
+    fn test_is_monomorphic() {
+        assert!(ty![Int => Bool => Float].is_monomorphic());
+        assert!(Type::Tuple(vec![Type::Int, Type::Bool]).is_monomorphic());
+        assert!(!Type::Var(1).is_monomorphic());
+        assert!(!ty![Int => {1}].is_monomorphic());
+        assert!(!Type::Tuple(vec![Type::Int, Type::Var(1)]).is_monomorphic());
+    }
+
+    #[test]
+    fn test_arity() {
+        assert_eq!(ty![Int].arity(), 0);
+        assert_eq!(ty![Int => Bool => Float].arity(), 2);
+        assert_eq!(ty![(Int => Int) => Int].arity(), 1);
+        assert_eq!(ty![{0} => ({1} => {2}) => {0} => {2}].arity(), 3);
+    }
+
+    #[test]
+    fn test_params_and_result() {
+        assert_eq!(ty![Int].params_and_result(), (vec![], Int));
+        assert_eq!(
+            ty![Int => Bool => Float].params_and_result(),
+            (vec![Int, Bool], Float)
+        );
+
+        let ty = ty![(Int => Int) => Bool => Float];
+        assert_eq!(ty.to_string(), "(Int -> Int) -> Bool -> Float");
+        assert_eq!(
+            ty.params_and_result(),
+            (vec![ty![Int => Int], Bool], Float)
+        );
+        assert_eq!(ty.params_and_result().0.len(), ty.arity());
+    }
+
+    #[test]
+    fn test_tuple_ty_macro() {
+        assert_eq!(tuple_ty![Int, Bool, Float], Tuple(vec![Int, Bool, Float]));
+        assert_eq!(
+            tuple_ty![(Int => Bool), {0}],
+            Tuple(vec![ty![Int => Bool], Var(0)])
+        );
+        assert_eq!(tuple_ty![Int, Bool, Float].to_string(), "(Int, Bool, Float)");
+        assert_eq!(
+            Fn(box tuple_ty![Int, Bool], box Int).to_string(),
+            "(Int, Bool) -> Int"
+        );
+    }
+
+    #[test]
+    fn test_normalize_vars() {
+        assert_eq!(ty![{8} => {6}].normalize_vars(), ty![{0} => {1}]);
+        assert_eq!(ty![{6} => {8}].normalize_vars(), ty![{0} => {1}]);
+        assert_eq!(ty![{3} => {3}].normalize_vars(), ty![{0} => {0}]);
+        assert_eq!(
+            ty![({8} => {6}) => ({5} => {8}) => {5} => {6}].normalize_vars(),
+            ty![({0} => {1}) => ({2} => {0}) => {2} => {1}]
+        );
+        assert_eq!(
+            ty![Int => {4} => Bool].normalize_vars(),
+            ty![Int => {0} => Bool]
+        );
+        assert_eq!(
+            tuple_ty![{5}, Int, {2}, {5}].normalize_vars(),
+            tuple_ty![{0}, Int, {1}, {0}]
+        );
+    }
+
+    #[test]
+    fn test_type_display_aliases() {
+        let mut display = TypeDisplay::new();
+        display.alias(ty![Int => Int => Int], "IntBinop");
+
+        let add = TypeEnv::default().get(intern("add")).unwrap().ty.clone();
+        assert_eq!(display.display(&add), "IntBinop");
+        assert_eq!(
+            display.display(&ty![(Int => Int => Int) => Bool]),
+            "IntBinop -> Bool"
+        );
+        assert_eq!(display.display(&ty![Int => Int]), "Int -> Int");
+        assert_eq!(
+            display.display(&ty![(Int => Int) => Int]),
+            "(Int -> Int) -> Int"
+        );
+        assert_eq!(
+            display.display(&tuple_ty![(Int => Int => Int), Bool]),
+            "(IntBinop, Bool)"
+        );
+    }
+
+    #[test]
+    fn test_polytype() {
+        let poly = Polytype::new(vec![0, 1], ty![{0} => {1} => {0}]);
+        assert_eq!(poly.to_string(), "forall a b. a -> b -> a");
+        assert_eq!(Polytype::from(Int).to_string(), "Int");
+
+        let mut gen = TypeVarGen::starting_at(10);
+        assert_eq!(poly.instantiate(&mut gen), ty![{10} => {11} => {10}]);
+        assert_eq!(poly.instantiate(&mut gen), ty![{12} => {13} => {12}]);
+
+        let poly = Polytype::new(vec![0], ty![{0} => {1}]);
+        assert_eq!(poly.instantiate(&mut gen), ty![{14} => {1}]);
+    }
+
+    #[test]
+    fn test_polytype_display() {
+        let tenv = TypeEnv::default();
+        let scheme = |name| tenv.get(intern(name)).unwrap().to_string();
+        assert_eq!(scheme("id"), "forall a. a -> a");
+        assert_eq!(scheme("const"), "forall a b. a -> b -> a");
+        assert_eq!(
+            scheme("compose"),
+            "forall a b c. (b -> c) -> (a -> b) -> a -> c"
+        );
+        assert_eq!(scheme("not"), "Bool -> Bool");
+
+        // only quantified variables are renamed
+        assert_eq!(
+            Polytype::new(vec![3], ty![{3} => {7}]).to_string(),
+            "forall a. a -> t7"
+        );
+        assert_eq!(
+            Polytype::generalize(ty![({5} => {6}) => {5}]).to_string(),
+            "forall a b. (a -> b) -> a"
+        );
+
+        let many = Polytype::new((0..28).collect(), ty![{26} => {27}]);
+        assert!(many.to_string().ends_with(". a1 -> b1"), "{}", many);
+    }
+
     #[test]
     fn test_type_display() {
         assert_eq!(Int.to_string(), "Int");
@@ -169,4 +579,41 @@ mod test {
             "(Int -> Bool -> Int) -> Int -> Bool"
         );
     }
+
+    #[test]
+    fn test_type_from_str() {
+        assert_eq!(Type::from_str("Int"), Ok(Int));
+        assert_eq!(Type::from_str("t0 -> t0"), Ok(ty![{0} => {0}]));
+        assert_eq!(Type::from_str("Int -> Bool"), Ok(ty![Int => Bool]));
+        assert_eq!(
+            Type::from_str("(Int -> Bool) -> Int"),
+            Ok(ty![(Int => Bool) => Int])
+        );
+        assert_eq!(
+            Type::from_str("Int -> Bool -> Float"),
+            Ok(ty![Int => (Bool => Float)])
+        );
+        assert_eq!(Type::from_str("a -> b"), Ok(ty![{0} => {1}]));
+        assert!(Type::from_str("Int ->").is_err());
+        assert!(Type::from_str("foo").is_err());
+    }
+
+    #[test]
+    fn test_type_roundtrip() {
+        let tys = vec![
+            ty![Unit],
+            ty![Int],
+            ty![Float],
+            ty![Bool],
+            ty![{ 12 }],
+            ty![Int => Bool => Float],
+            ty![(Int => Bool) => Float],
+            ty![({0} => {1}) => {1}],
+            ty![(Int => Bool => Int) => Int => Bool],
+            ty![Unit => Unit],
+        ];
+        for ty in tys {
+            assert_eq!(Type::from_str(&ty.to_string()), Ok(ty));
+        }
+    }
 }
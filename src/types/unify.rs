@@ -1,79 +1,152 @@
 use crate::types::{
     constraint::Constraint,
+    error::TypeError,
     subst::Subst,
     ty::{Type, TypeVar},
 };
+use std::collections::HashSet;
 
-pub fn unify(cons: &[Constraint]) -> Subst {
-    match &cons[..] {
-        [] => Subst::new(),
-        [head, tail @ ..] => {
-            let subst = unify1(head);
-            let substituted_tail = subst.apply_cons(&tail.to_vec());
-            let subst_tail = unify(&substituted_tail);
-            subst.compose(&subst_tail)
+pub fn unify(cons: &[Constraint]) -> Result<Subst, TypeError> {
+    unify_memo(cons, &mut HashSet::new())
+}
+
+/// `seen` holds every constraint in `cons` that has already been unified, as
+/// it was given. Once a constraint is solved its two sides stay equal under
+/// every later substitution, so a repeat of it is already satisfied, and is
+/// dropped before it is copied or rewritten. The keys are never rewritten, so
+/// the memo costs one hash per constraint.
+/// Each substitution is applied to the remaining constraints in place, so
+/// `cons` is only copied once
+fn unify_memo<'a>(
+    cons: &'a [Constraint],
+    seen: &mut HashSet<&'a Constraint>,
+) -> Result<Subst, TypeError> {
+    let mut cons: Vec<_> = cons.iter().filter(|con| seen.insert(*con)).cloned().collect();
+    let mut subst = Subst::new();
+
+    for i in 0..cons.len() {
+        let (head, tail) = cons[i..].split_first_mut().unwrap();
+        let head_subst = unify1(head)?;
+        for con in tail {
+            *con = con.apply(&head_subst);
         }
+        subst = subst.compose(&head_subst);
     }
+
+    Ok(subst)
 }
 
-fn unify1(con: &Constraint) -> Subst {
+/// Like `unify`, but returns the solutions as a list, in the order they were
+/// discovered. Each solution has every later solution applied to it, so the
+/// list is fully resolved
+pub fn unify_ordered(cons: &[Constraint]) -> Result<Vec<(TypeVar, Type)>, TypeError> {
+    let mut solutions: Vec<(TypeVar, Type)> = Vec::new();
+
+    // use `cons` as a stack, so that constraints are solved in order
+    let mut cons = cons.to_vec();
+    cons.reverse();
+
+    while let Some(Constraint(t1, t2)) = cons.pop() {
+        match (t1, t2) {
+            (Type::Unit, Type::Unit)
+            | (Type::Int, Type::Int)
+            | (Type::Bool, Type::Bool)
+            | (Type::Float, Type::Float) => {}
+            (Type::Var(tvar1), Type::Var(tvar2)) if tvar1 == tvar2 => {}
+            (Type::Var(tvar), ty) | (ty, Type::Var(tvar)) => {
+                if ty.occurs(tvar) {
+                    return Err(TypeError::Occurs(tvar, ty));
+                }
+
+                let subst = Subst::from_pair(tvar, ty.clone());
+                for con in &mut cons {
+                    *con = con.apply(&subst);
+                }
+                for (_, solution) in &mut solutions {
+                    *solution = solution.apply(&subst);
+                }
+                solutions.push((tvar, ty));
+            }
+            (Type::Fn(box arg1, box ret1), Type::Fn(box arg2, box ret2)) => {
+                cons.push(Constraint(ret1, ret2));
+                cons.push(Constraint(arg1, arg2));
+            }
+            (Type::Tuple(tys1), Type::Tuple(tys2)) if tys1.len() == tys2.len() => cons.extend(
+                tys1.into_iter()
+                    .zip(tys2)
+                    .rev()
+                    .map(|(ty1, ty2)| Constraint(ty1, ty2)),
+            ),
+            (t1, t2) => return Err(TypeError::CannotUnify(t1, t2)),
+        }
+    }
+
+    Ok(solutions)
+}
+
+fn unify1(con: &Constraint) -> Result<Subst, TypeError> {
     let Constraint(t1, t2) = con;
     match (t1, t2) {
-        (Type::Int, Type::Int) | (Type::Bool, Type::Bool) | (Type::Float, Type::Float) => {
-            Subst::new()
-        }
+        (Type::Unit, Type::Unit)
+        | (Type::Int, Type::Int)
+        | (Type::Bool, Type::Bool)
+        | (Type::Float, Type::Float) => Ok(Subst::new()),
         (Type::Var(tvar), ty) | (ty, Type::Var(tvar)) => unify_var(*tvar, ty),
         (Type::Fn(box arg1, box ret1), Type::Fn(box arg2, box ret2)) => unify(&[
             Constraint(arg1.clone(), arg2.clone()),
             Constraint(ret1.clone(), ret2.clone()),
         ]),
-        _ => panic!("Cannot unify {} with {}", t1, t2),
+        (Type::Tuple(tys1), Type::Tuple(tys2)) if tys1.len() == tys2.len() => unify(
+            &tys1
+                .iter()
+                .zip(tys2)
+                .map(|(ty1, ty2)| Constraint(ty1.clone(), ty2.clone()))
+                .collect::<Vec<_>>(),
+        ),
+        _ => Err(TypeError::CannotUnify(t1.clone(), t2.clone())),
     }
 }
 
-fn unify_var(tvar: TypeVar, ty: &Type) -> Subst {
+fn unify_var(tvar: TypeVar, ty: &Type) -> Result<Subst, TypeError> {
     match ty {
-        Type::Var(tvar2) if tvar == *tvar2 => Subst::new(),
-        Type::Var(_) => Subst::from_pair(tvar, ty.clone()),
-        ty if occurs(tvar, ty) => panic!("Circular use: {} occurs in {}", tvar, ty),
-        ty => Subst::from_pair(tvar, ty.clone()),
-    }
-}
-
-/// Check if `Type` contains `tvar`
-fn occurs(tvar: TypeVar, ty: &Type) -> bool {
-    match ty {
-        Type::Var(tvar2) => tvar == *tvar2,
-        Type::Fn(arg, ret) => occurs(tvar, arg) || occurs(tvar, ret),
-        _ => false,
+        Type::Var(tvar2) if tvar == *tvar2 => Ok(Subst::new()),
+        Type::Var(_) => Ok(Subst::from_pair(tvar, ty.clone())),
+        ty if ty.occurs(tvar) => Err(TypeError::Occurs(tvar, ty.clone())),
+        ty => Ok(Subst::from_pair(tvar, ty.clone())),
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::{
+        hir::Expr,
+        ty,
+        types::{constraint::collect, test::programs::compose_chain},
+    };
+    use std::str::FromStr;
 
     #[test]
     fn unify_2_ints() {
-        let subst = unify(&[Constraint(Type::Int, Type::Int)]);
+        let subst = unify(&[Constraint(Type::Int, Type::Int)]).unwrap();
         assert_eq!(subst, Subst::new());
     }
 
     #[test]
     fn unify_2_floats() {
-        let subst = unify(&[Constraint(Type::Float, Type::Float)]);
+        let subst = unify(&[Constraint(Type::Float, Type::Float)]).unwrap();
         assert_eq!(subst, Subst::new());
     }
 
     #[test]
     fn unify_2_bools() {
-        let subst = unify(&[Constraint(Type::Bool, Type::Bool)]);
+        let subst = unify(&[Constraint(Type::Bool, Type::Bool)]).unwrap();
         assert_eq!(subst, Subst::new());
     }
 
     #[test]
     fn unify_2_vars() {
-        let subst = unify(&[Constraint(Type::Var(1), Type::Var(2))]);
+        let subst = unify(&[Constraint(Type::Var(1), Type::Var(2))]).unwrap();
         assert_eq!(subst, Subst::from_pair(1, Type::Var(2)));
     }
 
@@ -82,13 +155,14 @@ mod test {
         let subst = unify(&[Constraint(
             Type::Fn(box Type::Bool, box Type::Bool),
             Type::Fn(box Type::Bool, box Type::Bool),
-        )]);
+        )])
+        .unwrap();
         assert_eq!(subst, Subst::new());
     }
 
     #[test]
     fn unify_var_with_non_var() {
-        let subst = unify(&[Constraint(Type::Var(1), Type::Int)]);
+        let subst = unify(&[Constraint(Type::Var(1), Type::Int)]).unwrap();
         assert_eq!(subst, Subst::from_pair(1, Type::Int));
     }
 
@@ -97,11 +171,137 @@ mod test {
         let subst = unify(&[Constraint(
             Type::Fn(box Type::Var(1), box Type::Bool),
             Type::Fn(box Type::Int, box Type::Var(2)),
-        )]);
+        )])
+        .unwrap();
+
+        let mut expected = Subst::new();
+        expected.insert(1, Type::Int);
+        expected.insert(2, Type::Bool);
+        assert_eq!(subst, expected);
+    }
+
+    #[test]
+    fn unify_vars_in_tuples() {
+        let subst = unify(&[Constraint(
+            Type::Tuple(vec![Type::Var(1), Type::Bool]),
+            Type::Tuple(vec![Type::Int, Type::Var(2)]),
+        )])
+        .unwrap();
 
         let mut expected = Subst::new();
         expected.insert(1, Type::Int);
         expected.insert(2, Type::Bool);
         assert_eq!(subst, expected);
     }
+
+    #[test]
+    fn unify_ordered_solutions() {
+        let solutions = unify_ordered(&[
+            Constraint(Type::Var(1), ty![{2} => Int]),
+            Constraint(Type::Var(2), Type::Bool),
+            Constraint(Type::Var(3), Type::Var(1)),
+        ])
+        .unwrap();
+
+        assert_eq!(solutions, vec![
+            (1, ty![Bool => Int]),
+            (2, Type::Bool),
+            (3, ty![Bool => Int]),
+        ]);
+    }
+
+    #[test]
+    fn unify_ordered_is_stable() {
+        let src = r"let inc = \x -> x + 1 in inc 1";
+        let cons = collect(Expr::from_str(src).unwrap()).unwrap();
+        let solutions = unify_ordered(&cons).unwrap();
+
+        for _ in 0..10 {
+            let cons = collect(Expr::from_str(src).unwrap()).unwrap();
+            assert_eq!(unify_ordered(&cons).unwrap(), solutions);
+        }
+
+        // agrees with `unify`
+        let subst = unify(&cons).unwrap();
+        for (tvar, ty) in &solutions {
+            assert_eq!(&subst.apply_ty(&Type::Var(*tvar)), ty);
+        }
+    }
+
+    #[test]
+    fn unify_skips_solved_constraints() {
+        let big = ty![({1} => Int) => ({2} => Bool) => {1}];
+        let con = Constraint(Type::Var(3), big.clone());
+        let cons: Vec<_> = std::iter::repeat(con.clone())
+            .take(100)
+            .chain(vec![
+                Constraint(Type::Var(1), Type::Float),
+                Constraint(Type::Var(2), Type::Int),
+            ])
+            .collect();
+
+        // only the first copy of `con` is unified: once `t3` is solved, the
+        // others are all `big = big`
+        let mut seen = HashSet::new();
+        let subst = unify_memo(&cons, &mut seen).unwrap();
+        assert_eq!(seen.len(), 3);
+        assert!(seen.contains(&con));
+
+        let expected = unify(&[
+            con,
+            Constraint(Type::Var(1), Type::Float),
+            Constraint(Type::Var(2), Type::Int),
+        ])
+        .unwrap();
+        assert_eq!(subst, expected);
+        assert_eq!(
+            subst.apply_ty(&Type::Var(3)),
+            ty![(Float => Int) => (Int => Bool) => Float]
+        );
+    }
+
+    #[test]
+    fn unify_matches_recursive_definition() {
+        /// `unify` as it was originally defined, copying the remaining
+        /// constraints at every step
+        fn unify_recursive(cons: &[Constraint]) -> Result<Subst, TypeError> {
+            match cons {
+                [] => Ok(Subst::new()),
+                [head, tail @ ..] => {
+                    let subst = unify1(head)?;
+                    let tail = subst.apply_cons(tail);
+                    Ok(subst.compose(&unify_recursive(&tail)?))
+                }
+            }
+        }
+
+        let compose_chain = compose_chain(50);
+        for src in &[
+            r"\f -> \g -> \x -> f (g x)",
+            r"let inc = \x -> x + 1 in inc 1",
+            compose_chain.as_str(),
+        ] {
+            let cons = collect(Expr::from_str(src).unwrap()).unwrap();
+            assert_eq!(unify(&cons), unify_recursive(&cons), "{}", src);
+        }
+    }
+
+    #[test]
+    fn unify_errors() {
+        assert_eq!(
+            unify(&[Constraint(Type::Int, Type::Float)]),
+            Err(TypeError::CannotUnify(Type::Int, Type::Float))
+        );
+        assert_eq!(
+            unify(&[Constraint(
+                Type::Var(1),
+                Type::Fn(box Type::Var(1), box Type::Int)
+            )]),
+            Err(TypeError::Occurs(1, Type::Fn(box Type::Var(1), box Type::Int)))
+        );
+        assert_eq!(
+            unify_ordered(&[Constraint(Type::Bool, Type::Int)]),
+            Err(TypeError::CannotUnify(Type::Bool, Type::Int))
+        );
+    }
 }
@@ -17,9 +17,20 @@ impl<T: FromId> Counter<T> {
         }
     }
 
+    pub fn starting_at(counter: u32) -> Self {
+        Self {
+            counter,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// # Panics
+    /// If the counter has run out of ids, rather than silently wrapping around
+    /// and handing out ids that are already in use. `reset` the counter
+    /// between independent uses to avoid this
     fn next_id(&mut self) -> u32 {
         let x = self.counter;
-        self.counter += 1;
+        self.counter = x.checked_add(1).expect("Counter ran out of fresh ids");
         x
     }
 
@@ -35,6 +46,8 @@ impl<T: FromId> Counter<T> {
         T::from_id(self.next_id())
     }
 
+    /// Restart numbering from 0. Only use this once every previously
+    /// generated id has been discarded
     pub fn reset(&mut self) {
         self.counter = 0;
     }
@@ -49,3 +62,26 @@ impl FromId for u32 {
         id
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counter_reset() {
+        let mut counter = Counter::<u32>::starting_at(5);
+        assert_eq!(counter.next(), 5);
+        assert_eq!(counter.next(), 6);
+        counter.reset();
+        assert_eq!(counter.current(), 0);
+        assert_eq!(counter.next(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Counter ran out of fresh ids")]
+    fn counter_overflow() {
+        let mut counter = Counter::<u32>::starting_at(u32::MAX - 1);
+        assert_eq!(counter.next(), u32::MAX - 1);
+        counter.next();
+    }
+}
@@ -1 +1,4 @@
 pub mod counter;
+pub mod stack;
+#[cfg(feature = "serde")]
+pub mod symbol_serde;
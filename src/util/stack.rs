@@ -0,0 +1,35 @@
+use std::{panic, thread};
+
+/// Enough for every recursive pass over programs nested around 10^4 deep
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// Run `f` to completion on a new thread with a large stack.
+/// The passes over `Expr`s and `Type`s are recursive, so deeply nested
+/// programs (eg a long chain of `let`s) would otherwise overflow the stack.
+/// Spawning the thread is not free, so run a whole pipeline in one call (as
+/// the binaries do) rather than each pass separately.
+/// Panics in `f` are propagated to the caller
+pub fn with_large_stack<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(f)
+        .expect("could not spawn a thread")
+        .join()
+        .unwrap_or_else(|err| panic::resume_unwind(err))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn large_stack_result() {
+        assert_eq!(with_large_stack(|| 1 + 2), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "inner panic")]
+    fn large_stack_panic() {
+        with_large_stack(|| panic!("inner panic"));
+    }
+}
@@ -0,0 +1,15 @@
+//! (De)serialize a `Symbol` as its resolved string.
+//! Use with `#[serde(with = "crate::util::symbol_serde")]`
+
+use serde::{Deserialize, Deserializer, Serializer};
+use simple_symbol::{intern, resolve, Symbol};
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+pub fn serialize<S: Serializer>(sym: &Symbol, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(resolve(*sym))
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Symbol, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    Ok(intern(&s))
+}
@@ -0,0 +1,52 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+fn run_repl(input: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_repl"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn repl_prints_values_and_types() {
+    let output = run_repl("1 + 2\n\\x -> x\n");
+    assert_eq!(
+        output.lines().collect::<Vec<_>>(),
+        vec!["3 : Int", "\\x -> x : forall a. a -> a"]
+    );
+}
+
+#[test]
+fn repl_has_polymorphic_builtins() {
+    let output = run_repl("id 5\nconst 1 2\nif_then_else true 1 0\n2.0\n");
+    assert_eq!(output.lines().collect::<Vec<_>>(), vec![
+        "5 : Int",
+        "1 : Int",
+        "1 : Int",
+        "2.0 : Float"
+    ]);
+}
+
+#[test]
+fn repl_recovers_from_errors() {
+    let output = run_repl("1 +\n1.0 / 2\n1 / 0\ntrue\n");
+    let lines: Vec<_> = output.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("Error: Parse error"));
+    assert!(lines[1].starts_with("Error: Cannot use Float and Int together"));
+    assert_eq!(lines[2], "Error: Division by zero");
+    assert_eq!(lines[3], "true : Bool");
+}
@@ -0,0 +1,81 @@
+use std::{env, fs, path::PathBuf, process::Command};
+
+/// A path in the temp dir that is unique to this test
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("simplc-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn simplc_emits_llvm_ir() {
+    let input = temp_path("arith.simpl");
+    let output = temp_path("arith.ll");
+    fs::write(&input, "1 + 2 * 3").unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_simplc"))
+        .arg(&input)
+        .arg("--emit=llvm-ir")
+        .arg("-o")
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let ir = fs::read_to_string(&output).unwrap();
+    assert!(ir.contains("@toplevel"), "{}", ir);
+
+    fs::remove_file(input).unwrap();
+    fs::remove_file(output).unwrap();
+}
+
+#[test]
+fn simplc_reports_errors() {
+    let input = temp_path("bad.simpl");
+    fs::write(&input, "1.0 / 2").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_simplc"))
+        .arg(&input)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("error: Cannot use Float and Int together"),
+        "{}",
+        stderr
+    );
+
+    fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn simplc_output_links_into_a_program() {
+    let input = temp_path("main.simpl");
+    let object = temp_path("main.o");
+    let exe = temp_path("main");
+    fs::write(&input, "1 + 2").unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_simplc"))
+        .arg(&input)
+        .arg("--emit=obj")
+        .arg("-o")
+        .arg(&object)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let status = Command::new("cc")
+        .arg(&object)
+        .arg("-o")
+        .arg(&exe)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = Command::new(&exe).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3\n");
+
+    for path in &[input, object, exe] {
+        fs::remove_file(path).unwrap();
+    }
+}